@@ -0,0 +1,227 @@
+use crate::common::types::MAX_DEVICE_INSTANCE;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Default location of the gateway configuration file.
+pub const DEFAULT_CONFIG_PATH: &str = "rustygate.json";
+
+/// Gateway configuration. Every field has a default, so a partial (or missing)
+/// config file is valid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GatewayConfig {
+    /// SQLite database file holding the device map and other persisted state
+    pub database_path: String,
+    pub discovery: DiscoveryConfig,
+    pub mqtt: MqttConfig,
+    pub liveness: LivenessConfig,
+    pub overrides: OverrideConfig,
+    pub writes: WriteConfig,
+    pub history: HistoryConfig,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            database_path: "rustygate.db".to_string(),
+            discovery: DiscoveryConfig::default(),
+            mqtt: MqttConfig::default(),
+            liveness: LivenessConfig::default(),
+            overrides: OverrideConfig::default(),
+            writes: WriteConfig::default(),
+            history: HistoryConfig::default(),
+        }
+    }
+}
+
+/// Ranged Who-Is sweep settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    /// Instance range covered by a sweep
+    pub sweep_low: u32,
+    pub sweep_high: u32,
+    /// Device instances per Who-Is
+    pub chunk_size: u32,
+    /// Pause between Who-Is chunks, giving devices time to answer
+    pub chunk_delay_ms: u64,
+    /// Interval of background rediscovery; 0 disables it
+    pub rediscovery_interval_secs: u64,
+    /// How long a rediscovery round collects I-Am replies
+    pub rediscovery_wait_secs: u64,
+    /// Rediscover with a ranged sweep instead of a single unbounded Who-Is
+    pub rediscovery_sweep: bool,
+    /// Rounds a device may miss before it is removed from the device map
+    pub removal_after_missed: u32,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            sweep_low: 0,
+            sweep_high: MAX_DEVICE_INSTANCE,
+            chunk_size: 1000,
+            chunk_delay_ms: 500,
+            rediscovery_interval_secs: 3600,
+            rediscovery_wait_secs: 10,
+            rediscovery_sweep: false,
+            removal_after_missed: 3,
+        }
+    }
+}
+
+/// Device online/offline tracking.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LivenessConfig {
+    /// Consecutive unanswered requests before a device is marked offline
+    pub offline_after_failures: u32,
+    /// A device not heard from for this long is probed
+    pub check_interval_secs: u64,
+    /// Probe backoff for offline devices, doubling from min to max
+    pub probe_backoff_min_secs: u64,
+    pub probe_backoff_max_secs: u64,
+}
+
+impl Default for LivenessConfig {
+    fn default() -> Self {
+        Self {
+            offline_after_failures: 3,
+            check_interval_secs: 60,
+            probe_backoff_min_secs: 10,
+            probe_backoff_max_secs: 600,
+        }
+    }
+}
+
+/// Detection of manual overrides on mapped commandable points.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverrideConfig {
+    /// Interval of Priority_Array checks; 0 disables them
+    pub check_interval_secs: u64,
+    /// Priorities 1 up to this one count as manual
+    pub manual_priority_max: u8,
+}
+
+impl Default for OverrideConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: 300,
+            manual_priority_max: 8,
+        }
+    }
+}
+
+/// Gateway-wide write settings. Per-point permissions are in the point map.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriteConfig {
+    /// Reject every write; can be changed at runtime via `/api/read-only`
+    pub read_only: bool,
+}
+
+/// Local historian. Retention can be overridden per point in the point map.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    /// Days of history kept
+    pub retention_days: u32,
+    /// Hours of raw samples kept before they are downsampled
+    pub raw_retention_hours: u32,
+    /// Width of the min/max/avg buckets of downsampled history
+    pub bucket_secs: u32,
+    /// Interval of batched inserts
+    pub flush_interval_ms: u64,
+    /// Interval of downsampling and expiry
+    pub maintenance_interval_secs: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            retention_days: 21,
+            raw_retention_hours: 48,
+            bucket_secs: 300,
+            flush_interval_ms: 1000,
+            maintenance_interval_secs: 600,
+        }
+    }
+}
+
+/// MQTT broker connection. Topics are rooted at `bacnet/{gateway_id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub gateway_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub keep_alive_secs: u64,
+    /// Drop values of faulted points instead of publishing them with `"quality": "bad"`
+    pub suppress_faulted: bool,
+    /// Messages queued while the broker is unreachable; the oldest are dropped beyond this
+    pub queue_max_messages: u64,
+    /// Queued messages older than this are dropped
+    pub queue_max_age_secs: u64,
+    /// Publish point updates in batches instead of one message each
+    pub batch: MqttBatchMode,
+    /// How long point updates are collected into a batch
+    pub batch_window_ms: u64,
+    /// Updates that close a batch before its window ends
+    pub batch_max_size: usize,
+}
+
+/// How point updates are published.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttBatchMode {
+    /// One message per update on the point's topic
+    #[default]
+    Off,
+    /// One JSON array per device on `bacnet/{gateway_id}/{device_id}/batch`
+    Device,
+    /// One JSON array on `bacnet/{gateway_id}/batch`
+    Gateway,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: "rustygate".to_string(),
+            gateway_id: "rustygate".to_string(),
+            username: None,
+            password: None,
+            keep_alive_secs: 30,
+            suppress_faulted: false,
+            queue_max_messages: 100_000,
+            queue_max_age_secs: 86_400,
+            batch: MqttBatchMode::Off,
+            batch_window_ms: 1000,
+            batch_max_size: 500,
+        }
+    }
+}
+
+impl GatewayConfig {
+    /// Loads the config from `path`, or returns defaults if the file does not exist.
+    pub fn load(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            log::info!("No config file at {}, using defaults", path);
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)?;
+        let config = serde_json::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path, e))?;
+        Ok(config)
+    }
+}
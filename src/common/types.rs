@@ -3,7 +3,26 @@ use serde::{Serialize, Deserialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Command {
     StartDiscovery,
+    /// Cancels a running sweep
     StopDiscovery,
+    /// Who-Is limited to the instance range `low..=high`
+    DiscoverRange {
+        low: u32,
+        high: u32,
+    },
+    /// Ranged Who-Is sweep in paced chunks; unset fields come from the discovery config
+    SweepDiscovery {
+        #[serde(default)]
+        low: Option<u32>,
+        #[serde(default)]
+        high: Option<u32>,
+        #[serde(default)]
+        chunk_size: Option<u32>,
+        #[serde(default)]
+        chunk_delay_ms: Option<u64>,
+    },
+    /// Broadcast Who-Is-Router-To-Network to map the routed topology
+    DiscoverRouters,
     BindInterface(String),
     /// For CLI testing: binds and starts discovery immediately
     BindAndDiscover(String),
@@ -16,20 +35,131 @@ pub enum Command {
         interface: String,
         device_id: u32,
         address: String,
+        /// Remote network number (DNET) for devices behind a BACnet router
+        #[serde(default)]
+        network: Option<u16>,
+        /// Hex-encoded MAC on the remote network (DADR)
+        #[serde(default)]
+        mac: Option<String>,
     },
     WriteProperty {
         device_id: u32,
         address: String,
+        #[serde(default)]
+        network: Option<u16>,
+        #[serde(default)]
+        mac: Option<String>,
         object_type: u16,
         instance: u32,
         property: u32,
         value: String,
+        /// Command priority 1-16; unset writes without one (the device's default, normally 16)
+        #[serde(default)]
+        priority: Option<u8>,
+        /// Relinquishes the priority again after this many seconds (requires `priority`)
+        #[serde(default)]
+        duration_secs: Option<u64>,
+        /// Reads the value back after the write and compares it
+        #[serde(default)]
+        verify: bool,
+        /// Accepted absolute difference when verifying
+        #[serde(default)]
+        tolerance: Option<f32>,
+        #[serde(default)]
+        origin: Origin,
     },
+    /// Relinquishes a timed override before it expires
+    CancelTimedOverride {
+        id: i64,
+        #[serde(default)]
+        origin: Origin,
+    },
+    /// Reads Priority_Array and Relinquish_Default of a commandable object
+    ReadPriorityArray {
+        device_id: u32,
+        address: String,
+        #[serde(default)]
+        network: Option<u16>,
+        #[serde(default)]
+        mac: Option<String>,
+        object_type: u16,
+        instance: u32,
+    },
+    /// ReadProperty of any property; the whole array when `array_index` is unset
+    ReadProperty {
+        device_id: u32,
+        address: String,
+        #[serde(default)]
+        network: Option<u16>,
+        #[serde(default)]
+        mac: Option<String>,
+        object_type: u16,
+        instance: u32,
+        property: u32,
+        #[serde(default)]
+        array_index: Option<u32>,
+    },
+    /// The point map was edited; reload it into the acquisition engine
+    ReloadPoints,
+    /// Broadcast Who-Has, optionally limited to device instances `low..=high`
+    WhoHas {
+        search: ObjectSearch,
+        #[serde(default)]
+        low: Option<u32>,
+        #[serde(default)]
+        high: Option<u32>,
+    },
+}
+
+/// Object looked for with Who-Has.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectSearch {
+    Name(String),
+    Id { object_type: u16, instance: u32 },
+}
+
+impl ObjectSearch {
+    /// Parses `type:instance` as an object identifier, anything else as a name.
+    pub fn parse(s: &str) -> Self {
+        match s.split_once(':').map(|(t, i)| (t.parse(), i.parse())) {
+            Some((Ok(object_type), Ok(instance))) => ObjectSearch::Id { object_type, instance },
+            _ => ObjectSearch::Name(s.to_string()),
+        }
+    }
+
+    pub fn matches(&self, i_have: &IHave) -> bool {
+        match self {
+            ObjectSearch::Name(name) => i_have.object_name == *name,
+            ObjectSearch::Id { object_type, instance } => i_have.object_type == *object_type && i_have.instance == *instance,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Event {
     DeviceDiscovered(BacnetDevice),
+    /// A device instance that was not in the device map announced itself
+    DeviceAdded(BacnetDevice),
+    /// A known device missed several rediscovery rounds and was dropped from the device map
+    DeviceRemoved {
+        device_id: u32,
+    },
+    /// A known device answered from a different address
+    DeviceAddressChanged {
+        device_id: u32,
+        old_address: String,
+        new_address: String,
+    },
+    /// Rediscovery found objects added to or removed from a device's object list
+    ObjectListChanged {
+        device_id: u32,
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    /// Device record updated with properties read from the device
+    DeviceUpdated(BacnetDevice),
+    RouterDiscovered(BacnetRouter),
     DeviceObjectsDiscovered {
         device_id: u32,
         objects: Vec<BacnetObjectInfo>,
@@ -38,20 +168,834 @@ pub enum Event {
         device_id: u32,
         object_id: String,
         value: f32,
+        #[serde(default)]
+        status: ValueStatus,
+        #[serde(default)]
+        quality: Quality,
+    },
+    DiscoveryProgress(DiscoveryProgress),
+    /// I-Have reply to a Who-Has
+    IHave(IHave),
+    /// A device went online or offline
+    DeviceStatusChanged(DeviceStatus),
+    /// Two devices share an instance number, or two instances answer from one address
+    DeviceConflict(DeviceConflict),
+    /// A device acknowledged a confirmed request that returns no data (SimpleAck)
+    RequestAcknowledged {
+        address: String,
+        invoke_id: u8,
+        service: String,
     },
+    /// A device answered a confirmed request with Error, Reject or Abort
+    RequestFailed(RequestFailure),
+    /// Outcome of a `WriteProperty` command
+    WriteCompleted(WriteOutcome),
+    /// Outcome of a `ReadProperty` command
+    PropertyRead(PropertyReading),
+    /// Outcome of a `ReadPriorityArray` command
+    PriorityArrayRead(PriorityArrayReading),
+    /// A commandable point is held at a manual priority by someone other than the gateway
+    OverrideDetected(PriorityOverride),
+    /// A previously detected override was relinquished
+    OverrideCleared(PriorityOverride),
+    /// A timed write succeeded; the priority is relinquished at `expires_ms`
+    TimedOverrideStarted(TimedOverride),
+    /// A timed override was relinquished on expiry or cancellation, or the
+    /// cancellation failed
+    TimedOverrideEnded(TimedOverrideEnd),
+    /// The point map was edited
+    PointMapChanged,
+    /// Broker connection and store-and-forward queue of the MQTT bridge
+    MqttQueue(MqttQueueStatus),
     StatusMessage(String),
 }
 
+/// State of the MQTT bridge's outbound queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttQueueStatus {
+    pub connected: bool,
+    /// Messages waiting for the broker
+    pub depth: u64,
+    /// Creation time of the oldest queued message (Unix ms)
+    pub oldest_ms: Option<i64>,
+    /// Messages dropped by the queue's size and age limits since startup
+    pub dropped: u64,
+}
+
+/// An MQTT message held while the broker is unreachable.
+#[derive(Debug, Clone)]
+pub struct QueuedMessage {
+    pub id: i64,
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IHave {
+    pub device_id: u32,
+    pub address: String,
+    #[serde(default)]
+    pub network: Option<u16>,
+    #[serde(default)]
+    pub mac: Option<String>,
+    pub object_type: u16,
+    pub instance: u32,
+    pub object_name: String,
+}
+
+/// Who-Has results of one device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectSearchResult {
+    pub device_id: u32,
+    pub address: String,
+    pub network: Option<u16>,
+    pub mac: Option<String>,
+    pub objects: Vec<FoundObject>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FoundObject {
+    pub object_type: u16,
+    pub instance: u32,
+    pub name: String,
+}
+
+/// Communication status of a device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceStatus {
+    pub device_id: u32,
+    pub online: bool,
+    /// Last successful communication (Unix ms)
+    pub last_seen_ms: Option<i64>,
+    pub consecutive_failures: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ConflictKind {
+    /// The same device instance answered from several addresses
+    DuplicateInstance,
+    /// Several device instances answered from the same address
+    DuplicateAddress,
+}
+
+/// A commissioning conflict between devices seen in I-Am replies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceConflict {
+    pub kind: ConflictKind,
+    /// The shared instance number or address
+    pub key: String,
+    /// Every conflicting I-Am, one per address (duplicate instance) or per instance (duplicate address)
+    pub devices: Vec<BacnetDevice>,
+    pub first_seen_ms: i64,
+    pub last_seen_ms: i64,
+}
+
+/// BACnetErrorClass
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorClass {
+    Device,
+    Object,
+    Property,
+    Resources,
+    Security,
+    Services,
+    Vt,
+    Communication,
+    Other(u32),
+}
+
+/// BACnetErrorCode, limited to the codes a gateway is likely to see
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    ConfigurationInProgress,
+    DeviceBusy,
+    InconsistentParameters,
+    InvalidDataType,
+    MissingRequiredParameter,
+    NoSpaceForObject,
+    NoSpaceToWriteProperty,
+    OperationalProblem,
+    PasswordFailure,
+    ReadAccessDenied,
+    ServiceRequestDenied,
+    Timeout,
+    UnknownObject,
+    UnknownProperty,
+    UnsupportedObjectType,
+    ValueOutOfRange,
+    WriteAccessDenied,
+    CharacterSetNotSupported,
+    InvalidArrayIndex,
+    CovSubscriptionFailed,
+    NotCovProperty,
+    OptionalFunctionalityNotSupported,
+    DatatypeNotSupported,
+    PropertyIsNotAnArray,
+    Other(u32),
+}
+
+/// BACnetRejectReason
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectReason {
+    BufferOverflow,
+    InconsistentParameters,
+    InvalidParameterDataType,
+    InvalidTag,
+    MissingRequiredParameter,
+    ParameterOutOfRange,
+    TooManyArguments,
+    UndefinedEnumeration,
+    UnrecognizedService,
+    Other(u32),
+}
+
+/// BACnetAbortReason
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AbortReason {
+    BufferOverflow,
+    InvalidApduInThisState,
+    PreemptedByHigherPriorityTask,
+    SegmentationNotSupported,
+    SecurityError,
+    InsufficientSecurity,
+    WindowSizeOutOfRange,
+    ApplicationExceededReplyTime,
+    OutOfResources,
+    TsmTimeout,
+    ApduTooLong,
+    Other(u32),
+}
+
+/// Why a device refused a confirmed request. Returned as the error of the
+/// request (downcast from `anyhow::Error`) and carried by `RequestFailed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RequestError {
+    Error { class: ErrorClass, code: ErrorCode },
+    Reject(RejectReason),
+    Abort { reason: AbortReason, server: bool },
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Error { class, code } => write!(f, "Error: {}/{}", class.name(), code.name()),
+            RequestError::Reject(reason) => write!(f, "Reject: {}", reason.name()),
+            RequestError::Abort { reason, .. } => write!(f, "Abort: {}", reason.name()),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// A confirmed request answered with Error, Reject or Abort.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestFailure {
+    /// Address the reply came from
+    pub address: String,
+    pub invoke_id: u8,
+    /// Confirmed service name; Reject and Abort do not carry one
+    pub service: Option<String>,
+    pub error: RequestError,
+}
+
+/// Outcome of a WriteProperty command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteOutcome {
+    pub device_id: u32,
+    pub object_id: String,
+    pub property: u32,
+    pub value: String,
+    #[serde(default)]
+    pub priority: Option<u8>,
+    /// When a timed write is relinquished
+    #[serde(default)]
+    pub expires_ms: Option<i64>,
+    /// Round trip of the WriteProperty request, when it was sent
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    /// Readback of an acknowledged write sent with `verify`
+    #[serde(default)]
+    pub verification: Option<WriteVerification>,
+    /// Refused by the gateway's write permissions; `error` gives the reason
+    #[serde(default)]
+    pub rejected: bool,
+    /// `None` when the device acknowledged the write
+    pub error: Option<String>,
+    /// The device's Error, Reject or Abort, when it sent one
+    pub failure: Option<RequestError>,
+}
+
+/// Where a verified write was read back from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadbackSource {
+    /// The written property itself
+    Property,
+    /// The Priority_Array slot of the write's priority
+    PriorityArray,
+}
+
+/// Result of reading a written value back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteVerification {
+    pub source: ReadbackSource,
+    /// `None` if the read failed or the slot is empty
+    pub readback: Option<f32>,
+    pub tolerance: f32,
+    pub passed: bool,
+    /// Round trip of the readback request
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Result of a ReadProperty command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropertyReading {
+    pub device_id: u32,
+    pub object_id: String,
+    pub property: u32,
+    pub array_index: Option<u32>,
+    /// Structured view of the value; `None` when the read failed
+    pub value: Option<serde_json::Value>,
+    /// The encoded value as hex
+    pub raw: Option<String>,
+    pub error: Option<String>,
+    /// The device's Error, Reject or Abort, when it sent one
+    pub failure: Option<RequestError>,
+}
+
+/// One command priority of a Priority_Array.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrioritySlot {
+    pub priority: u8,
+    /// `None` when relinquished
+    pub value: Option<f32>,
+    /// The gateway itself commanded this priority
+    pub by_gateway: bool,
+}
+
+/// Priority_Array and Relinquish_Default of a commandable object.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityArray {
+    pub slots: Vec<PrioritySlot>,
+    pub relinquish_default: Option<f32>,
+}
+
+impl PriorityArray {
+    /// The highest (lowest-numbered) priority in control, if any.
+    pub fn active(&self) -> Option<&PrioritySlot> {
+        self.slots.iter().find(|slot| slot.value.is_some())
+    }
+}
+
+/// Result of a ReadPriorityArray command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityArrayReading {
+    pub device_id: u32,
+    pub object_id: String,
+    pub priority_array: Option<PriorityArray>,
+    pub error: Option<String>,
+}
+
+/// A manual priority held by someone other than the gateway.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriorityOverride {
+    pub device_id: u32,
+    pub object_id: String,
+    pub priority: u8,
+    pub value: f32,
+    /// The priority is the highest one commanding the point
+    pub in_control: bool,
+    pub since_ms: i64,
+}
+
+/// A write the gateway relinquishes (writes Null at the same priority) once
+/// it expires. Persisted so the relinquish survives restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimedOverride {
+    /// Assigned by the database
+    pub id: i64,
+    pub device_id: u32,
+    pub object_type: u16,
+    pub instance: u32,
+    pub priority: u8,
+    pub value: String,
+    pub created_ms: i64,
+    pub expires_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimedOverrideEnd {
+    pub timed_override: TimedOverride,
+    /// Relinquished before it expired
+    pub cancelled: bool,
+    /// Set when the relinquish failed; the override is then still pending
+    pub error: Option<String>,
+}
+
+/// The Status_Flags bits of an object.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusFlags {
+    pub in_alarm: bool,
+    pub fault: bool,
+    pub overridden: bool,
+    pub out_of_service: bool,
+}
+
+impl StatusFlags {
+    pub fn from_bits(bits: &[bool]) -> Self {
+        let bit = |i: usize| bits.get(i).copied().unwrap_or(false);
+        Self { in_alarm: bit(0), fault: bit(1), overridden: bit(2), out_of_service: bit(3) }
+    }
+
+    pub fn to_bits(self) -> [bool; 4] {
+        [self.in_alarm, self.fault, self.overridden, self.out_of_service]
+    }
+}
+
+/// Reliability of an object without a detected fault.
+pub const NO_FAULT_DETECTED: u32 = 0;
+
+/// Status_Flags, Reliability and Out_Of_Service read with a point value.
+/// Properties the device did not report are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValueStatus {
+    pub status_flags: Option<StatusFlags>,
+    pub reliability: Option<u32>,
+    pub out_of_service: Option<bool>,
+}
+
+impl ValueStatus {
+    /// Faulted values are bad; out-of-service and overridden ones uncertain.
+    pub fn quality(&self) -> Quality {
+        let flags = self.status_flags.unwrap_or_default();
+        if flags.fault || self.reliability.is_some_and(|r| r != NO_FAULT_DETECTED) {
+            Quality::Bad
+        } else if flags.out_of_service || flags.overridden || self.out_of_service == Some(true) {
+            Quality::Uncertain
+        } else {
+            Quality::Good
+        }
+    }
+}
+
+/// How far a point value can be trusted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quality {
+    #[default]
+    Good,
+    Uncertain,
+    Bad,
+}
+
+/// Highest device instance; 4194303 is reserved as the wildcard.
+pub const MAX_DEVICE_INSTANCE: u32 = 4_194_302;
+
+/// Progress of a ranged Who-Is sweep, reported after each chunk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryProgress {
+    pub low: u32,
+    pub high: u32,
+    /// Upper limit of the last chunk sent
+    pub swept_to: u32,
+    pub chunks_sent: u32,
+    pub chunks_total: u32,
+    /// Distinct devices in range that answered so far
+    pub devices_found: usize,
+    pub finished: bool,
+    /// Set when the sweep was stopped before covering the range
+    #[serde(default)]
+    pub cancelled: bool,
+}
+
+/// Object identifier as used in point updates: `"{object_type}:{instance}"`.
+pub fn object_id_string(object_type: u16, instance: u32) -> String {
+    format!("{}:{}", object_type, instance)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BacnetObjectInfo {
     pub object_type: u16,
     pub instance: u32,
+    /// Name of the object type (see `object_type_name`)
+    #[serde(default)]
+    pub type_name: String,
     pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Engineering units of analog objects
+    #[serde(default)]
+    pub units: Option<String>,
+    /// State names of multi-state objects, for values 1..=n
+    #[serde(default)]
+    pub state_text: Option<Vec<String>>,
+    /// Active/inactive texts of binary objects
+    #[serde(default)]
+    pub active_text: Option<String>,
+    #[serde(default)]
+    pub inactive_text: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BacnetDevice {
     pub instance: u32,
+    /// BACnet/IP address of the device, or of the router it sits behind
     pub address: String,
+    /// Source network number (SNET) when the device is behind a router
+    #[serde(default)]
+    pub network: Option<u16>,
+    /// Hex-encoded MAC on the remote network (SADR)
+    #[serde(default)]
+    pub mac: Option<String>,
+    pub name: String,
+    pub max_apdu_length_accepted: u32,
+    pub segmentation_supported: Segmentation,
+    pub vendor_id: u16,
+    /// Resolved from the BACnet vendor ID table, `None` if unassigned
+    pub vendor_name: Option<String>,
+    /// Identity properties read from the Device object after discovery
+    #[serde(default)]
+    pub model_name: Option<String>,
+    #[serde(default)]
+    pub firmware_revision: Option<String>,
+    #[serde(default)]
+    pub application_software_version: Option<String>,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub protocol_services_supported: Option<Vec<String>>,
+    /// Database_Revision seen when the object list was last read
+    #[serde(default)]
+    pub database_revision: Option<u32>,
+}
+
+impl BacnetDevice {
+    /// Refreshes the fields carried by an I-Am, keeping properties read from the device.
+    pub fn update_from_i_am(&mut self, i_am: &BacnetDevice) {
+        self.address = i_am.address.clone();
+        self.network = i_am.network;
+        self.mac = i_am.mac.clone();
+        self.max_apdu_length_accepted = i_am.max_apdu_length_accepted;
+        self.segmentation_supported = i_am.segmentation_supported;
+        if self.vendor_id != i_am.vendor_id {
+            self.vendor_id = i_am.vendor_id;
+            self.vendor_name = i_am.vendor_name.clone();
+        }
+    }
+
+    /// Address including the remote network and MAC, e.g. `10.0.0.5:47808` or `5:0a@10.0.0.1:47808`.
+    pub fn route(&self) -> String {
+        match (self.network, &self.mac) {
+            (Some(network), Some(mac)) => format!("{}:{}@{}", network, mac, self.address),
+            _ => self.address.clone(),
+        }
+    }
+
+    /// Whether Protocol_Services_Supported lists `service`. False if it was never read.
+    pub fn supports_service(&self, service: &str) -> bool {
+        self.protocol_services_supported
+            .as_ref()
+            .is_some_and(|services| services.iter().any(|s| s == service))
+    }
+}
+
+/// Per-device capability profile used to pick services and request sizes.
+/// Devices whose Protocol_Services_Supported is unknown are treated as
+/// ReadProperty-only.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceCapabilities {
+    pub services_known: bool,
+    pub read_property_multiple: bool,
+    pub subscribe_cov: bool,
+    pub segmentation: Segmentation,
+    pub max_apdu: u32,
+}
+
+impl Default for DeviceCapabilities {
+    /// Profile for a device that has not been seen: ReadProperty only, with
+    /// the 480-byte APDU common on small controllers.
+    fn default() -> Self {
+        Self {
+            services_known: false,
+            read_property_multiple: false,
+            subscribe_cov: false,
+            segmentation: Segmentation::None,
+            max_apdu: 480,
+        }
+    }
+}
+
+/// A mapped point: an object whose Present_Value the gateway acquires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PointConfig {
+    /// Assigned by the database; ignored when creating a point
+    #[serde(default)]
+    pub id: i64,
+    pub device_id: u32,
+    pub object_type: u16,
+    pub instance: u32,
+    #[serde(default)]
     pub name: String,
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_secs: u32,
+    /// Prefer COV subscription when the device supports SubscribeCOV
+    #[serde(default = "default_true")]
+    pub cov: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Whether and within which limits the point may be written
+    #[serde(default)]
+    pub write: WritePolicy,
+    /// Whether and for how long the point's values are kept in the historian
+    #[serde(default)]
+    pub history: HistoryPolicy,
+    /// Which of the point's updates are published over MQTT
+    #[serde(default)]
+    pub publish: PublishFilter,
+}
+
+/// Filters applied to a point's updates before they are published over
+/// MQTT. Without any set, every update is published. Changes of quality or
+/// status are always published.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublishFilter {
+    /// Publish only changes of at least this much from the last published value
+    pub deadband: Option<f32>,
+    /// Publish only changes of at least this percentage of the last published value
+    pub deadband_percent: Option<f32>,
+    /// Publish only when the value changes; meant for binary and multi-state points
+    pub change_only: bool,
+    /// Minimum time between publishes; a change held back is published once it has passed
+    pub min_interval_secs: Option<u32>,
+    /// Republish the latest value after this long without a publish
+    pub heartbeat_secs: Option<u32>,
+}
+
+/// History retention of a point. Unset durations take the `history`
+/// config defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryPolicy {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Days of history kept, downsampled after `raw_retention_hours`
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+    /// Hours of raw samples kept before they are downsampled into buckets
+    #[serde(default)]
+    pub raw_retention_hours: Option<u32>,
+}
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            retention_days: None,
+            raw_retention_hours: None,
+        }
+    }
+}
+
+/// How a history query combines the samples of each interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryAggregation {
+    /// The stored samples, without aggregation
+    Raw,
+    Avg,
+    Min,
+    Max,
+    Last,
+}
+
+/// A value of a history series: a raw sample with its quality, or an
+/// interval's aggregate with the number of samples it covers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryValue {
+    /// Sample time, or the start of the interval
+    pub timestamp_ms: i64,
+    pub value: f32,
+    pub quality: Option<Quality>,
+    pub count: Option<u64>,
+}
+
+/// A point value stored by the historian.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySample {
+    pub point_id: i64,
+    pub timestamp_ms: i64,
+    pub value: f32,
+    pub quality: Quality,
+}
+
+/// Write permission of a point. Points are read-only unless `enabled`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WritePolicy {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+    /// Values permitted for binary and multi-state points
+    #[serde(default)]
+    pub allowed_states: Option<Vec<u32>>,
+    /// Permitted command priorities, inclusive
+    #[serde(default = "default_priority_min")]
+    pub priority_min: u8,
+    #[serde(default = "default_priority_max")]
+    pub priority_max: u8,
+}
+
+impl Default for WritePolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min: None,
+            max: None,
+            allowed_states: None,
+            priority_min: default_priority_min(),
+            priority_max: default_priority_max(),
+        }
+    }
+}
+
+fn default_priority_min() -> u8 {
+    1
+}
+
+fn default_priority_max() -> u8 {
+    16
+}
+
+/// A recorded write attempt or configuration change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Assigned by the database
+    pub id: i64,
+    pub timestamp_ms: i64,
+    pub action: AuditAction,
+    pub source: AuditSource,
+    pub client_id: Option<String>,
+    pub device_id: Option<u32>,
+    pub object_id: Option<String>,
+    pub property: Option<u32>,
+    /// What a configuration change applies to, such as `point 4`
+    pub target: Option<String>,
+    pub value: Option<String>,
+    pub priority: Option<u8>,
+    pub result: AuditResult,
+    /// Reason of a rejection or failure
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Write,
+    /// A Null write ending a timed override
+    Relinquish,
+    Config,
+}
+
+/// Who issued a write or configuration change.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Origin {
+    pub source: AuditSource,
+    /// User or client that sent it, when known
+    pub client_id: Option<String>,
+}
+
+impl Origin {
+    pub fn new(source: AuditSource, client_id: Option<String>) -> Self {
+        Self { source, client_id }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditSource {
+    Rest,
+    Mqtt,
+    Cli,
+    /// Automation rules
+    Rule,
+    /// The gateway itself, e.g. relinquishing an expired timed override
+    #[default]
+    Gateway,
+}
+
+/// Filter of an audit log query; unset fields match everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditFilter {
+    /// Inclusive range of `timestamp_ms`
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub action: Option<AuditAction>,
+    pub source: Option<AuditSource>,
+    pub result: Option<AuditResult>,
+    pub device_id: Option<u32>,
+    pub client_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditResult {
+    Ok,
+    /// Refused by the gateway's write permissions
+    Rejected,
+    Failed,
+}
+
+fn default_poll_interval() -> u32 {
+    10
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Segmentation support advertised in I-Am (BACnetSegmentation).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Segmentation {
+    Both,
+    Transmit,
+    Receive,
+    None,
+}
+
+impl Segmentation {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            0 => Segmentation::Both,
+            1 => Segmentation::Transmit,
+            2 => Segmentation::Receive,
+            _ => Segmentation::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacnetRouter {
+    pub address: String,
+    /// Network numbers reachable through this router
+    pub networks: Vec<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkSegment {
+    /// Network number, or `None` for the directly connected BACnet/IP network
+    pub network: Option<u16>,
+    pub routers: Vec<String>,
+    pub devices: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkTopology {
+    pub routers: Vec<BacnetRouter>,
+    pub networks: Vec<NetworkSegment>,
 }
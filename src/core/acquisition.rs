@@ -0,0 +1,423 @@
+use crate::common::types::{object_id_string, DeviceCapabilities, Event, PointConfig, ValueStatus};
+use crate::core::bacnet::codec;
+use crate::core::bacnet::properties;
+use crate::core::bacnet::property_value;
+use crate::core::bacnet::npdu::{BacnetAddress, RoutingTable};
+use crate::core::bacnet::object_id::ObjectId;
+use crate::core::bacnet::rpm::ReadAccessSpec;
+use crate::core::bacnet::services::BacnetServices;
+use crate::core::devices::DeviceMap;
+use crate::core::liveness::Liveness;
+use crate::core::persistence::db::Database;
+use bacnet_rs::object::PropertyIdentifier;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, Notify};
+use tokio::time::Instant;
+
+const TICK: Duration = Duration::from_secs(1);
+
+/// Subscriber process identifier used for all COV subscriptions.
+pub const COV_PROCESS_ID: u32 = 1;
+const COV_LIFETIME_SECS: u32 = 300;
+/// Subscriptions are renewed this long before they lapse.
+const COV_RENEW_MARGIN: Duration = Duration::from_secs(60);
+/// After a failed subscription the point is polled for this long before COV is retried.
+const COV_RETRY_AFTER: Duration = Duration::from_secs(600);
+
+/// Estimated ReadPropertyMultiple-ACK size per object when reading Present_Value
+/// with Status_Flags, Reliability and Out_Of_Service.
+const VALUE_BYTES_PER_OBJECT: usize = 40;
+
+struct PointState {
+    config: PointConfig,
+    object_id: ObjectId,
+    next_poll: Instant,
+    /// When the current COV subscription must be renewed
+    cov_renew_at: Option<Instant>,
+    /// Set after a failed subscription; COV is not retried before then
+    cov_retry_at: Option<Instant>,
+}
+
+/// What a point needs in the current acquisition round.
+#[derive(Debug, PartialEq)]
+enum Due {
+    Subscribe,
+    Poll,
+}
+
+impl PointState {
+    fn new(config: PointConfig, object_id: ObjectId, now: Instant) -> Self {
+        Self { config, object_id, next_poll: now, cov_renew_at: None, cov_retry_at: None }
+    }
+
+    fn wants_cov(&self, caps: &DeviceCapabilities, now: Instant) -> bool {
+        self.config.cov && caps.subscribe_cov && self.cov_retry_at.is_none_or(|t| t <= now)
+    }
+
+    /// Whether the point is due for a COV (re)subscription or a poll at `now`,
+    /// rescheduling it if so.
+    fn take_due(&mut self, caps: &DeviceCapabilities, now: Instant) -> Option<Due> {
+        if self.wants_cov(caps, now) {
+            if self.cov_renew_at.is_some_and(|t| t > now) {
+                return None;
+            }
+            // Hold off further attempts until the outcome arrives
+            self.cov_renew_at = Some(now + COV_RETRY_AFTER);
+            Some(Due::Subscribe)
+        } else if self.next_poll <= now {
+            self.next_poll = now + Duration::from_secs(self.config.poll_interval_secs.max(1) as u64);
+            Some(Due::Poll)
+        } else {
+            None
+        }
+    }
+
+    fn cov_subscribed(&mut self, now: Instant) {
+        self.cov_renew_at = Some(now + Duration::from_secs(COV_LIFETIME_SECS as u64) - COV_RENEW_MARGIN);
+    }
+
+    /// Falls back to polling right away and retries COV after `COV_RETRY_AFTER`.
+    fn cov_failed(&mut self, now: Instant) {
+        self.cov_renew_at = None;
+        self.cov_retry_at = Some(now + COV_RETRY_AFTER);
+        self.next_poll = now;
+    }
+}
+
+/// Outcomes reported back from per-device acquisition tasks.
+enum Outcome {
+    CovSubscribed(i64),
+    CovFailed(i64),
+    DeviceDone(u32),
+}
+
+/// Acquires the Present_Value of every mapped point, with its status
+/// properties as a quality indicator. For each device the
+/// capability profile decides between COV subscriptions and polling, and
+/// between ReadPropertyMultiple (chunked to fit the device's max APDU) and
+/// ReadProperty.
+pub struct Acquisition {
+    services: BacnetServices,
+    db: Database,
+    event_tx: broadcast::Sender<Event>,
+    routing_table: RoutingTable,
+    devices: DeviceMap,
+    liveness: Liveness,
+    reload: Arc<Notify>,
+    points: HashMap<i64, PointState>,
+    /// Devices with an acquisition task in flight
+    busy: HashSet<u32>,
+}
+
+impl Acquisition {
+    pub fn new(services: BacnetServices, db: Database, event_tx: broadcast::Sender<Event>, routing_table: RoutingTable, devices: DeviceMap, liveness: Liveness) -> Self {
+        Self {
+            services,
+            db,
+            event_tx,
+            routing_table,
+            devices,
+            liveness,
+            reload: Arc::new(Notify::new()),
+            points: HashMap::new(),
+            busy: HashSet::new(),
+        }
+    }
+
+    /// Notifying this handle makes the engine reload the point map.
+    pub fn reload_handle(&self) -> Arc<Notify> {
+        Arc::clone(&self.reload)
+    }
+
+    pub async fn run(mut self) {
+        self.load_points().await;
+        let (outcome_tx, mut outcome_rx) = mpsc::channel(256);
+        let mut tick = tokio::time::interval(TICK);
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = self.reload.notified() => self.load_points().await,
+                Some(outcome) = outcome_rx.recv() => self.on_outcome(outcome),
+                _ = tick.tick() => self.acquire(&outcome_tx).await,
+            }
+        }
+    }
+
+    async fn load_points(&mut self) {
+        let configs = match self.db.load_points().await {
+            Ok(points) => points,
+            Err(e) => {
+                log::error!("Failed to load point map: {}", e);
+                return;
+            }
+        };
+        let mut points = HashMap::new();
+        for config in configs.into_iter().filter(|p| p.enabled) {
+            let object_id = ObjectId::new(config.object_type, config.instance);
+            // Keep subscription state of points that did not move to another object
+            let state = match self.points.remove(&config.id) {
+                Some(old) if old.config.device_id == config.device_id && old.object_id == object_id => {
+                    PointState { config, ..old }
+                }
+                _ => PointState::new(config, object_id, Instant::now()),
+            };
+            points.insert(state.config.id, state);
+        }
+        log::info!("Acquiring {} points", points.len());
+        self.points = points;
+    }
+
+    fn on_outcome(&mut self, outcome: Outcome) {
+        let now = Instant::now();
+        match outcome {
+            Outcome::CovSubscribed(id) => {
+                if let Some(point) = self.points.get_mut(&id) {
+                    point.cov_subscribed(now);
+                }
+            }
+            Outcome::CovFailed(id) => {
+                if let Some(point) = self.points.get_mut(&id) {
+                    point.cov_failed(now);
+                }
+            }
+            Outcome::DeviceDone(device_id) => {
+                self.busy.remove(&device_id);
+            }
+        }
+    }
+
+    /// Starts a task for every device that has points due for polling or
+    /// COV (re)subscription.
+    async fn acquire(&mut self, outcome_tx: &mpsc::Sender<Outcome>) {
+        let now = Instant::now();
+        let mut jobs: HashMap<u32, DeviceJob> = HashMap::new();
+        let devices = self.devices.lock().await;
+        for (id, point) in self.points.iter_mut() {
+            let device_id = point.config.device_id;
+            if self.busy.contains(&device_id) {
+                continue;
+            }
+            let Some(device) = devices.get(&device_id) else { continue };
+            if !self.liveness.is_online(device_id) {
+                // Resubscribe once the device is back; it may have restarted
+                point.cov_renew_at = None;
+                continue;
+            }
+            let caps = device.capabilities();
+            let job = jobs.entry(device_id).or_insert_with(|| DeviceJob {
+                device_id,
+                dest: BacnetAddress::resolve(&device.address, device.network, device.mac.as_deref(), &self.routing_table),
+                caps: caps.clone(),
+                subscribe: Vec::new(),
+                poll: Vec::new(),
+            });
+            match point.take_due(&caps, now) {
+                Some(Due::Subscribe) => job.subscribe.push((*id, point.object_id)),
+                Some(Due::Poll) => job.poll.push((*id, point.object_id)),
+                None => {}
+            }
+        }
+        drop(devices);
+
+        for (device_id, job) in jobs {
+            if job.subscribe.is_empty() && job.poll.is_empty() {
+                continue;
+            }
+            self.busy.insert(device_id);
+            let services = self.services.clone();
+            let event_tx = self.event_tx.clone();
+            let liveness = self.liveness.clone();
+            let outcome_tx = outcome_tx.clone();
+            tokio::spawn(async move {
+                job.run(&services, &event_tx, &liveness, &outcome_tx).await;
+                let _ = outcome_tx.send(Outcome::DeviceDone(device_id)).await;
+            });
+        }
+    }
+}
+
+/// One acquisition round for a single device.
+struct DeviceJob {
+    device_id: u32,
+    dest: Option<BacnetAddress>,
+    caps: DeviceCapabilities,
+    subscribe: Vec<(i64, ObjectId)>,
+    poll: Vec<(i64, ObjectId)>,
+}
+
+impl DeviceJob {
+    async fn run(mut self, services: &BacnetServices, event_tx: &broadcast::Sender<Event>, liveness: &Liveness, outcome_tx: &mpsc::Sender<Outcome>) {
+        let Some(dest) = self.dest.take() else {
+            log::warn!("Device {} has no usable address, skipping acquisition", self.device_id);
+            return;
+        };
+
+        for (id, object_id) in std::mem::take(&mut self.subscribe) {
+            let result = services.subscribe_cov(&dest, COV_PROCESS_ID, object_id, COV_LIFETIME_SECS).await;
+            liveness.record_result(self.device_id, &result);
+            if !liveness.is_online(self.device_id) {
+                return;
+            }
+            match result {
+                Ok(()) => {
+                    log::debug!("Subscribed to COV of {} on device {}", object_id, self.device_id);
+                    let _ = outcome_tx.send(Outcome::CovSubscribed(id)).await;
+                }
+                Err(e) => {
+                    log::warn!("SubscribeCOV for {} on device {} failed, polling instead: {}", object_id, self.device_id, e);
+                    let _ = outcome_tx.send(Outcome::CovFailed(id)).await;
+                    self.poll.push((id, object_id));
+                }
+            }
+        }
+
+        let present_value = PropertyIdentifier::PresentValue as u32;
+        let status_flags = properties::STATUS_FLAGS;
+        let mut use_rpm = self.caps.read_property_multiple;
+        for chunk in self.poll.chunks(self.caps.objects_per_request(VALUE_BYTES_PER_OBJECT)) {
+            if use_rpm {
+                let properties = vec![present_value, status_flags, properties::RELIABILITY, PropertyIdentifier::OutOfService as u32];
+                let specs: Vec<ReadAccessSpec> = chunk
+                    .iter()
+                    .map(|(_, object_id)| ReadAccessSpec { object_id: *object_id, properties: properties.clone() })
+                    .collect();
+                let result = services.read_property_multiple(&dest, &specs).await;
+                liveness.record_result(self.device_id, &result);
+                if !liveness.is_online(self.device_id) {
+                    return;
+                }
+                match result {
+                    Ok(results) => {
+                        for result in results {
+                            let values = result.results.iter().filter_map(|prop| Some((prop.property, prop.value.as_deref().ok()?)));
+                            let status = property_value::value_status(values);
+                            match result.results.iter().find(|prop| prop.property == present_value).map(|prop| &prop.value) {
+                                Some(Ok(data)) => self.publish(event_tx, result.object_type, result.instance, data, status),
+                                Some(Err(e)) => log::debug!("Device {} object {}:{} Present_Value unreadable: {:?}", self.device_id, result.object_type, result.instance, e),
+                                None => {}
+                            }
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        log::warn!("ReadPropertyMultiple to device {} failed, falling back to ReadProperty: {}", self.device_id, e);
+                        use_rpm = false;
+                    }
+                }
+            }
+            for (_, object_id) in chunk {
+                let result = services.read_property(&dest, *object_id, present_value, None).await;
+                liveness.record_result(self.device_id, &result);
+                if !liveness.is_online(self.device_id) {
+                    return;
+                }
+                let data = match result {
+                    Ok(data) => data,
+                    Err(e) => {
+                        log::debug!("Device {} {} Present_Value unreadable: {}", self.device_id, object_id, e);
+                        continue;
+                    }
+                };
+                let status = self.read_status(services, &dest, *object_id).await;
+                self.publish(event_tx, object_id.object_type, object_id.instance, &data, status);
+            }
+        }
+    }
+
+    /// Reads Status_Flags, and Reliability only when the fault flag is set.
+    /// Out_Of_Service is not read separately since Status_Flags mirrors it.
+    async fn read_status(&self, services: &BacnetServices, dest: &BacnetAddress, object_id: ObjectId) -> ValueStatus {
+        let Ok(flags) = services.read_property(dest, object_id, properties::STATUS_FLAGS, None).await else {
+            return ValueStatus::default();
+        };
+        let mut status = property_value::value_status([(properties::STATUS_FLAGS, flags.as_slice())]);
+        if status.status_flags.is_some_and(|f| f.fault) {
+            if let Ok(reliability) = services.read_property(dest, object_id, properties::RELIABILITY, None).await {
+                status.reliability = property_value::value_status([(properties::RELIABILITY, reliability.as_slice())]).reliability;
+            }
+        }
+        status
+    }
+
+    fn publish(&self, event_tx: &broadcast::Sender<Event>, object_type: u16, instance: u32, data: &[u8], status: ValueStatus) {
+        match codec::decode_application_value(data).ok().and_then(|(value, _)| value.as_f32()) {
+            Some(value) => {
+                let _ = event_tx.send(Event::PointUpdate {
+                    device_id: self.device_id,
+                    object_id: object_id_string(object_type, instance),
+                    value,
+                    status,
+                    quality: status.quality(),
+                });
+            }
+            None => log::debug!("Device {} object {}:{} has a non-numeric Present_Value", self.device_id, object_type, instance),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(cov: bool) -> PointState {
+        let config: PointConfig = serde_json::from_value(serde_json::json!({
+            "device_id": 1234,
+            "object_type": 2,
+            "instance": 1,
+            "poll_interval_secs": 10,
+            "cov": cov,
+        }))
+        .unwrap();
+        PointState::new(config, ObjectId::new(2, 1), Instant::now())
+    }
+
+    fn caps(subscribe_cov: bool) -> DeviceCapabilities {
+        DeviceCapabilities { subscribe_cov, ..Default::default() }
+    }
+
+    #[test]
+    fn cov_needs_the_point_and_the_device() {
+        let now = Instant::now();
+        assert!(point(true).wants_cov(&caps(true), now));
+        assert!(!point(false).wants_cov(&caps(true), now));
+        assert!(!point(true).wants_cov(&caps(false), now));
+    }
+
+    #[test]
+    fn polls_at_the_configured_interval() {
+        let mut point = point(false);
+        let now = point.next_poll;
+        assert_eq!(point.take_due(&caps(true), now), Some(Due::Poll));
+        assert_eq!(point.take_due(&caps(true), now + Duration::from_secs(9)), None);
+        assert_eq!(point.take_due(&caps(true), now + Duration::from_secs(10)), Some(Due::Poll));
+    }
+
+    #[test]
+    fn subscriptions_are_renewed_before_they_lapse() {
+        let mut point = point(true);
+        let now = point.next_poll;
+        assert_eq!(point.take_due(&caps(true), now), Some(Due::Subscribe));
+        // No second attempt while the first is outstanding
+        assert_eq!(point.take_due(&caps(true), now + Duration::from_secs(1)), None);
+
+        point.cov_subscribed(now);
+        let renew = Duration::from_secs(COV_LIFETIME_SECS as u64) - COV_RENEW_MARGIN;
+        assert_eq!(point.take_due(&caps(true), now + renew - Duration::from_secs(1)), None);
+        assert_eq!(point.take_due(&caps(true), now + renew), Some(Due::Subscribe));
+    }
+
+    #[test]
+    fn failed_subscriptions_poll_until_the_retry() {
+        let mut point = point(true);
+        let now = point.next_poll;
+        assert_eq!(point.take_due(&caps(true), now), Some(Due::Subscribe));
+
+        point.cov_failed(now);
+        assert!(!point.wants_cov(&caps(true), now));
+        assert_eq!(point.take_due(&caps(true), now), Some(Due::Poll));
+        assert_eq!(point.take_due(&caps(true), now + Duration::from_secs(10)), Some(Due::Poll));
+        assert_eq!(point.take_due(&caps(true), now + COV_RETRY_AFTER), Some(Due::Subscribe));
+    }
+}
@@ -0,0 +1,59 @@
+//! Audit log of writes, relinquishes and configuration changes.
+//!
+//! Commands carry an `Origin`: REST requests take the `X-Client-Id` header
+//! or the peer address, MQTT commands their `client_id` field, the CLI
+//! `$USER`, and the gateway's own relinquishes of expired overrides have
+//! source `gateway`. Entries are kept in the `audit_log` table.
+
+use crate::common::types::{AuditAction, AuditEntry, AuditResult, Origin, WriteOutcome};
+use crate::core::persistence::db::{now_ms, Database};
+
+/// An entry stamped now, with no target yet.
+pub fn entry(action: AuditAction, origin: &Origin, result: AuditResult) -> AuditEntry {
+    AuditEntry {
+        id: 0,
+        timestamp_ms: now_ms(),
+        action,
+        source: origin.source,
+        client_id: origin.client_id.clone(),
+        device_id: None,
+        object_id: None,
+        property: None,
+        target: None,
+        value: None,
+        priority: None,
+        result,
+        detail: None,
+    }
+}
+
+/// The entry of a write attempt.
+pub fn write_entry(origin: &Origin, outcome: &WriteOutcome) -> AuditEntry {
+    let result = match (&outcome.error, outcome.rejected) {
+        (None, _) => AuditResult::Ok,
+        (Some(_), true) => AuditResult::Rejected,
+        (Some(_), false) => AuditResult::Failed,
+    };
+    let verification_failed = outcome
+        .verification
+        .as_ref()
+        .filter(|v| !v.passed)
+        .map(|v| format!("Verification failed, read back {:?}", v.readback));
+    AuditEntry {
+        device_id: Some(outcome.device_id),
+        object_id: Some(outcome.object_id.clone()),
+        property: Some(outcome.property),
+        value: Some(outcome.value.clone()),
+        priority: outcome.priority,
+        detail: outcome.error.clone().or(verification_failed),
+        ..entry(AuditAction::Write, origin, result)
+    }
+}
+
+/// Appends to the audit log. A failure is logged rather than returned, so
+/// it never fails the audited operation.
+pub async fn record(db: &Database, entry: AuditEntry) {
+    if let Err(e) = db.record_audit(&entry).await {
+        log::error!("Failed to record {:?} in the audit log: {}", entry.action, e);
+    }
+}
@@ -1,28 +1,41 @@
 use bacnet_rs::{
     app::{Apdu, MaxApduSize, MaxSegments},
     datalink::{bip::BacnetIpDataLink, DataLink, DataLinkAddress},
-    service::{UnconfirmedServiceChoice, WhoIsRequest, ReadPropertyRequest},
+    service::{UnconfirmedServiceChoice, WhoIsRequest},
 };
+use crate::common::types::ObjectSearch;
+use crate::core::bacnet::npdu::{self, BacnetAddress};
+use crate::core::bacnet::object_id::ObjectId;
+use crate::core::bacnet::rpm::{self, ReadAccessSpec};
+use crate::core::bacnet::transaction::TransactionManager;
+use crate::core::bacnet::who_has;
 use anyhow::Result;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 
 pub struct BacnetClient {
     pub datalink: BacnetIpDataLink,
-    invoke_id: u8,
+    transactions: Arc<TransactionManager>,
 }
 
 impl BacnetClient {
-    pub fn new(bind_addr: SocketAddr) -> Result<Self> {
+    /// Binds the datalink. Invoke IDs are allocated from `transactions`, so
+    /// they never clash with requests still in flight to the same peer.
+    pub fn new(bind_addr: SocketAddr, transactions: Arc<TransactionManager>) -> Result<Self> {
         let datalink = BacnetIpDataLink::new(bind_addr)?;
-        Ok(Self { datalink, invoke_id: 0 })
+        Ok(Self { datalink, transactions })
     }
 
-    fn next_invoke_id(&mut self) -> u8 {
-        let id = self.invoke_id;
-        self.invoke_id = self.invoke_id.wrapping_add(1);
-        id
+    /// Wraps an APDU in an NPDU addressed to `dest` and sends it to the next hop.
+    fn send_apdu(&mut self, apdu: &Apdu, dest: &BacnetAddress, expecting_reply: bool) -> Result<()> {
+        let mut frame = npdu::encode_header(dest, expecting_reply);
+        frame.extend(apdu.encode());
+        self.datalink.send_frame(&frame, &dest.datalink)?;
+        Ok(())
     }
 
+    /// Sends a Who-Is. Without a destination it is sent as a global broadcast
+    /// so routers forward it to remote networks.
     pub fn send_who_is(&mut self, low: Option<u32>, high: Option<u32>, destination: Option<DataLinkAddress>) -> Result<()> {
         let mut who_is = WhoIsRequest::new();
         who_is.device_instance_range_low_limit = low;
@@ -36,45 +49,70 @@ impl BacnetClient {
             service_data: data,
         };
 
-        let encoded = apdu.encode();
-        let dest = destination.unwrap_or(DataLinkAddress::Broadcast);
-        log::info!("Sending Who-Is from {:?} to {:?}", self.datalink.local_address(), dest);
-        self.datalink.send_frame(&encoded, &dest)?;
-        
+        let dest = match destination {
+            Some(datalink) => BacnetAddress::local(datalink),
+            None => BacnetAddress::global_broadcast(),
+        };
+        log::info!("Sending Who-Is from {:?} to {}", self.datalink.local_address(), dest);
+        self.send_apdu(&apdu, &dest, false)
+    }
+
+    /// Sends Who-Is as a global broadcast and, when `subnet_broadcast` is set, also
+    /// as a directed broadcast on the bound subnet.
+    pub fn broadcast_who_is(&mut self, low: Option<u32>, high: Option<u32>, subnet_broadcast: Option<IpAddr>) -> Result<()> {
+        self.send_who_is(low, high, None)?;
+        if let Some(ip) = subnet_broadcast {
+            self.send_who_is(low, high, Some(DataLinkAddress::Ip(SocketAddr::new(ip, 47808))))?;
+        }
+        Ok(())
+    }
+
+    /// Broadcasts Who-Has for an object, optionally limited to a device
+    /// instance range, globally and (when set) on the bound subnet.
+    pub fn broadcast_who_has(&mut self, limits: Option<(u32, u32)>, search: &ObjectSearch, subnet_broadcast: Option<IpAddr>) -> Result<()> {
+        let apdu = Apdu::UnconfirmedRequest {
+            service_choice: who_has::WHO_HAS,
+            service_data: who_has::encode_request(limits, search)?,
+        };
+        self.send_apdu(&apdu, &BacnetAddress::global_broadcast(), false)?;
+        if let Some(ip) = subnet_broadcast {
+            self.send_apdu(&apdu, &BacnetAddress::local(DataLinkAddress::Ip(SocketAddr::new(ip, 47808))), false)?;
+        }
+        Ok(())
+    }
+
+    /// Broadcasts Who-Is-Router-To-Network to learn which routers serve which networks.
+    pub fn send_who_is_router_to_network(&mut self, network: Option<u16>) -> Result<()> {
+        let frame = npdu::encode_who_is_router_to_network(network);
+        self.datalink.send_frame(&frame, &DataLinkAddress::Broadcast)?;
         Ok(())
     }
 
-    pub fn send_read_property(&mut self, dest: &DataLinkAddress, obj_id: bacnet_rs::object::ObjectIdentifier, prop_id: u32) -> Result<u8> {
-        let req = ReadPropertyRequest::new(obj_id, prop_id);
+    pub fn send_read_property(&mut self, dest: &BacnetAddress, obj_id: ObjectId, prop_id: u32, array_index: Option<u32>) -> Result<u8> {
+        use bacnet_rs::encoding::*;
+
         let mut data = Vec::new();
-        req.encode(&mut data).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        data.extend(encode_context_object_id(obj_id.object_type, obj_id.instance, 0).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        data.extend(encode_context_enumerated(prop_id, 1).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        if let Some(index) = array_index {
+            data.extend(encode_context_unsigned(index, 2).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        }
 
-        let invoke_id = self.next_invoke_id();
-        let apdu = Apdu::ConfirmedRequest {
-            segmented: false,
-            more_follows: false,
-            segmented_response_accepted: true,
-            max_segments: MaxSegments::Unspecified,
-            max_response_size: MaxApduSize::Up1476,
-            invoke_id,
-            sequence_number: None,
-            proposed_window_size: None,
-            service_choice: 12, // ReadProperty
-            service_data: data,
-        };
+        self.send_confirmed(dest, 12, data) // ReadProperty
+    }
 
-        let encoded = apdu.encode();
-        self.datalink.send_frame(&encoded, dest)?;
-        
-        Ok(invoke_id)
+    /// Sends a ReadPropertyMultiple for the given objects and properties.
+    pub fn send_read_property_multiple(&mut self, dest: &BacnetAddress, specs: &[ReadAccessSpec]) -> Result<u8> {
+        let data = rpm::encode_request(specs)?;
+        self.send_confirmed(dest, rpm::SERVICE_CHOICE, data)
     }
 
-    pub fn send_write_property(&mut self, dest: &DataLinkAddress, obj_id: bacnet_rs::object::ObjectIdentifier, prop_id: u32, value: bacnet_rs::object::PropertyValue) -> Result<u8> {
+    pub fn send_write_property(&mut self, dest: &BacnetAddress, obj_id: ObjectId, prop_id: u32, value: bacnet_rs::object::PropertyValue, priority: Option<u8>) -> Result<u8> {
         use bacnet_rs::encoding::*;
 
         let mut data = Vec::new();
         // 1. Object ID (Context 0)
-        data.extend(encode_context_object_id(obj_id.object_type as u16, obj_id.instance, 0).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        data.extend(encode_context_object_id(obj_id.object_type, obj_id.instance, 0).map_err(|e| anyhow::anyhow!(e.to_string()))?);
         // 2. Property ID (Context 1)
         data.extend(encode_context_enumerated(prop_id, 1).map_err(|e| anyhow::anyhow!(e.to_string()))?);
         // 3. Value (Context 3)
@@ -82,27 +120,56 @@ impl BacnetClient {
         match &value {
             bacnet_rs::object::PropertyValue::Real(f) => encode_real(&mut data, *f).map_err(|e| anyhow::anyhow!(e.to_string()))?,
             bacnet_rs::object::PropertyValue::Boolean(b) => encode_boolean(&mut data, *b).map_err(|e| anyhow::anyhow!(e.to_string()))?,
+            bacnet_rs::object::PropertyValue::Enumerated(v) => encode_enumerated(&mut data, *v).map_err(|e| anyhow::anyhow!(e.to_string()))?,
+            bacnet_rs::object::PropertyValue::UnsignedInteger(v) => encode_unsigned(&mut data, *v).map_err(|e| anyhow::anyhow!(e.to_string()))?,
+            // Relinquishes the priority
+            bacnet_rs::object::PropertyValue::Null => data.push(0x00),
             _ => anyhow::bail!("Unsupported value type for WriteProperty"),
         }
         data.push(0x3F); // Closing Tag 3
+        // 4. Priority (Context 4)
+        if let Some(priority) = priority {
+            data.extend(encode_context_unsigned(priority as u32, 4).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        }
 
-        let invoke_id = self.next_invoke_id();
+        self.send_confirmed(dest, 15, data) // WriteProperty
+    }
+
+    /// Subscribes to unconfirmed COV notifications for an object. A lifetime
+    /// of `None` cancels the subscription.
+    pub fn send_subscribe_cov(&mut self, dest: &BacnetAddress, process_id: u32, obj_id: ObjectId, lifetime_secs: Option<u32>) -> Result<u8> {
+        use bacnet_rs::encoding::*;
+
+        let mut data = Vec::new();
+        data.extend(encode_context_unsigned(process_id, 0).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        data.extend(encode_context_object_id(obj_id.object_type, obj_id.instance, 1).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        if let Some(lifetime) = lifetime_secs {
+            data.extend(encode_context_unsigned(0, 2).map_err(|e| anyhow::anyhow!(e.to_string()))?); // issueConfirmedNotifications = false
+            data.extend(encode_context_unsigned(lifetime, 3).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        }
+
+        self.send_confirmed(dest, 5, data) // SubscribeCOV
+    }
+
+    /// Wraps service data in an unsegmented confirmed request and returns its invoke ID.
+    /// Segmented responses are refused since the receive path cannot reassemble
+    /// them; callers size requests from the device's capabilities instead.
+    fn send_confirmed(&mut self, dest: &BacnetAddress, service_choice: u8, service_data: Vec<u8>) -> Result<u8> {
+        let invoke_id = self.transactions.allocate(dest)?;
         let apdu = Apdu::ConfirmedRequest {
             segmented: false,
             more_follows: false,
-            segmented_response_accepted: true,
+            segmented_response_accepted: false,
             max_segments: MaxSegments::Unspecified,
             max_response_size: MaxApduSize::Up1476,
             invoke_id,
             sequence_number: None,
             proposed_window_size: None,
-            service_choice: 15, // WriteProperty
-            service_data: data,
+            service_choice,
+            service_data,
         };
 
-        let encoded = apdu.encode();
-        self.datalink.send_frame(&encoded, dest)?;
-        
+        self.send_apdu(&apdu, dest, true)?;
         Ok(invoke_id)
     }
 }
@@ -0,0 +1,399 @@
+use anyhow::Result;
+use serde::Serialize;
+
+/// A decoded BACnet tag header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tag {
+    pub number: u8,
+    pub context: bool,
+    pub kind: TagKind,
+    /// Length of the tag header itself
+    pub header_len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagKind {
+    /// Primitive value with the given content length (or boolean value for application booleans)
+    Primitive(usize),
+    Opening,
+    Closing,
+}
+
+pub fn decode_tag(data: &[u8]) -> Result<Tag> {
+    let first = *data.first().ok_or_else(|| anyhow::anyhow!("Empty tag"))?;
+    let mut pos = 1;
+    let mut number = first >> 4;
+    if number == 0x0F {
+        number = *data.get(pos).ok_or_else(|| anyhow::anyhow!("Truncated extended tag"))?;
+        pos += 1;
+    }
+    let context = first & 0x08 != 0;
+    let lvt = first & 0x07;
+
+    let kind = match lvt {
+        6 if context => TagKind::Opening,
+        7 if context => TagKind::Closing,
+        5 => {
+            let len_byte = *data.get(pos).ok_or_else(|| anyhow::anyhow!("Truncated tag length"))?;
+            pos += 1;
+            let len = match len_byte {
+                254 => {
+                    let b = data.get(pos..pos + 2).ok_or_else(|| anyhow::anyhow!("Truncated tag length"))?;
+                    pos += 2;
+                    u16::from_be_bytes([b[0], b[1]]) as usize
+                }
+                255 => {
+                    let b = data.get(pos..pos + 4).ok_or_else(|| anyhow::anyhow!("Truncated tag length"))?;
+                    pos += 4;
+                    u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize
+                }
+                n => n as usize,
+            };
+            TagKind::Primitive(len)
+        }
+        n => TagKind::Primitive(n as usize),
+    };
+
+    Ok(Tag { number, context, kind, header_len: pos })
+}
+
+/// Returns the content between an opening tag `number` at the start of `data`
+/// and its matching closing tag, plus the total bytes consumed. Nested
+/// constructed values are skipped over.
+pub fn enclosed(data: &[u8], number: u8) -> Result<(&[u8], usize)> {
+    let open = decode_tag(data)?;
+    if open.kind != TagKind::Opening || open.number != number {
+        anyhow::bail!("Expected opening tag {}", number);
+    }
+    let start = open.header_len;
+    let mut pos = start;
+    let mut depth = 0usize;
+    loop {
+        let rest = data.get(pos..).filter(|r| !r.is_empty()).ok_or_else(|| anyhow::anyhow!("Missing closing tag {}", number))?;
+        let tag = decode_tag(rest)?;
+        match tag.kind {
+            TagKind::Opening => depth += 1,
+            TagKind::Closing if depth == 0 => {
+                if tag.number != number {
+                    anyhow::bail!("Expected closing tag {}, found {}", number, tag.number);
+                }
+                return Ok((&data[start..pos], pos + tag.header_len));
+            }
+            TagKind::Closing => depth -= 1,
+            // Application booleans have no content
+            TagKind::Primitive(_) if !tag.context && tag.number == 1 => {}
+            TagKind::Primitive(len) => pos += len,
+        }
+        pos += tag.header_len;
+    }
+}
+
+/// A single primitive BACnet application value.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value")]
+pub enum BacnetValue {
+    Null,
+    Boolean(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Real(f32),
+    Double(f64),
+    OctetString(String),
+    CharacterString(String),
+    BitString(Vec<bool>),
+    Enumerated(u32),
+    Date { year: Option<u16>, month: Option<u8>, day: Option<u8>, weekday: Option<u8> },
+    Time { hour: Option<u8>, minute: Option<u8>, second: Option<u8>, hundredths: Option<u8> },
+    ObjectIdentifier { object_type: u16, instance: u32 },
+}
+
+impl BacnetValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            BacnetValue::CharacterString(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bits(&self) -> Option<&[bool]> {
+        match self {
+            BacnetValue::BitString(bits) => Some(bits),
+            _ => None,
+        }
+    }
+
+    /// Numeric view of a present value: reals, integers, enumerations
+    /// (binary and multi-state states) and booleans.
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            BacnetValue::Real(v) => Some(*v),
+            BacnetValue::Double(v) => Some(*v as f32),
+            BacnetValue::Unsigned(v) => Some(*v as f32),
+            BacnetValue::Signed(v) => Some(*v as f32),
+            BacnetValue::Enumerated(v) => Some(*v as f32),
+            BacnetValue::Boolean(v) => Some(if *v { 1.0 } else { 0.0 }),
+            _ => None,
+        }
+    }
+}
+
+fn unsigned_from(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64)
+}
+
+fn signed_from(bytes: &[u8]) -> i64 {
+    if bytes.is_empty() {
+        return 0;
+    }
+    let unsigned = unsigned_from(bytes);
+    let shift = 64 - 8 * bytes.len().min(8) as u32;
+    ((unsigned << shift) as i64) >> shift
+}
+
+fn unspecified(b: u8) -> Option<u8> {
+    if b == 0xFF { None } else { Some(b) }
+}
+
+/// Decodes one application-tagged value, returning it and the bytes consumed.
+pub fn decode_application_value(data: &[u8]) -> Result<(BacnetValue, usize)> {
+    let tag = decode_tag(data)?;
+    if tag.context {
+        anyhow::bail!("Expected application tag, found context tag {}", tag.number);
+    }
+    let len = match tag.kind {
+        TagKind::Primitive(len) => len,
+        _ => anyhow::bail!("Unexpected opening/closing tag"),
+    };
+
+    // Booleans carry their value in the length field and have no content
+    if tag.number == 1 {
+        return Ok((BacnetValue::Boolean(len != 0), tag.header_len));
+    }
+
+    let start = tag.header_len;
+    let content = data
+        .get(start..start + len)
+        .ok_or_else(|| anyhow::anyhow!("Truncated value for application tag {}", tag.number))?;
+
+    let value = match tag.number {
+        0 => BacnetValue::Null,
+        2 => BacnetValue::Unsigned(unsigned_from(content)),
+        3 => BacnetValue::Signed(signed_from(content)),
+        4 => {
+            let b: [u8; 4] = content.try_into().map_err(|_| anyhow::anyhow!("Invalid REAL length {}", len))?;
+            BacnetValue::Real(f32::from_be_bytes(b))
+        }
+        5 => {
+            let b: [u8; 8] = content.try_into().map_err(|_| anyhow::anyhow!("Invalid DOUBLE length {}", len))?;
+            BacnetValue::Double(f64::from_be_bytes(b))
+        }
+        6 => BacnetValue::OctetString(hex::encode(content)),
+        7 => BacnetValue::CharacterString(decode_character_string(content)),
+        8 => {
+            let unused = *content.first().unwrap_or(&0) as usize;
+            let bytes = content.get(1..).unwrap_or(&[]);
+            let total = (bytes.len() * 8).saturating_sub(unused);
+            let bits = (0..total).map(|i| bytes[i / 8] & (0x80 >> (i % 8)) != 0).collect();
+            BacnetValue::BitString(bits)
+        }
+        9 => BacnetValue::Enumerated(unsigned_from(content) as u32),
+        10 => {
+            if len != 4 {
+                anyhow::bail!("Invalid DATE length {}", len);
+            }
+            BacnetValue::Date {
+                year: unspecified(content[0]).map(|y| 1900 + y as u16),
+                month: unspecified(content[1]),
+                day: unspecified(content[2]),
+                weekday: unspecified(content[3]),
+            }
+        }
+        11 => {
+            if len != 4 {
+                anyhow::bail!("Invalid TIME length {}", len);
+            }
+            BacnetValue::Time {
+                hour: unspecified(content[0]),
+                minute: unspecified(content[1]),
+                second: unspecified(content[2]),
+                hundredths: unspecified(content[3]),
+            }
+        }
+        12 => {
+            if len != 4 {
+                anyhow::bail!("Invalid OBJECT IDENTIFIER length {}", len);
+            }
+            let raw = u32::from_be_bytes([content[0], content[1], content[2], content[3]]);
+            BacnetValue::ObjectIdentifier { object_type: (raw >> 22) as u16, instance: raw & 0x3FFFFF }
+        }
+        n => anyhow::bail!("Reserved application tag {}", n),
+    };
+
+    Ok((value, start + len))
+}
+
+/// Decodes a sequence of application-tagged values, e.g. a whole array.
+pub fn decode_application_values(mut data: &[u8]) -> Result<Vec<BacnetValue>> {
+    let mut values = Vec::new();
+    while !data.is_empty() {
+        let (value, consumed) = decode_application_value(data)?;
+        values.push(value);
+        data = &data[consumed..];
+    }
+    Ok(values)
+}
+
+/// One element of a property value, decoded from its tags alone.
+/// Context-tagged primitives cannot be typed without the property's
+/// definition, so their content is kept as hex.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ValueNode {
+    Application(BacnetValue),
+    Context { tag: u8, raw: String },
+    /// Values between an opening and closing context tag
+    Constructed { tag: u8, items: Vec<ValueNode> },
+}
+
+/// Decodes any sequence of application, context and constructed values,
+/// e.g. a whole property value, into a tree of `ValueNode`s.
+pub fn decode_value_nodes(mut data: &[u8]) -> Result<Vec<ValueNode>> {
+    let mut nodes = Vec::new();
+    while !data.is_empty() {
+        let tag = decode_tag(data)?;
+        let consumed = match tag.kind {
+            TagKind::Opening => {
+                let (content, consumed) = enclosed(data, tag.number)?;
+                nodes.push(ValueNode::Constructed { tag: tag.number, items: decode_value_nodes(content)? });
+                consumed
+            }
+            TagKind::Closing => anyhow::bail!("Unexpected closing tag {}", tag.number),
+            TagKind::Primitive(len) if tag.context => {
+                let content = data
+                    .get(tag.header_len..tag.header_len + len)
+                    .ok_or_else(|| anyhow::anyhow!("Truncated value for context tag {}", tag.number))?;
+                nodes.push(ValueNode::Context { tag: tag.number, raw: hex::encode(content) });
+                tag.header_len + len
+            }
+            TagKind::Primitive(_) => {
+                let (value, consumed) = decode_application_value(data)?;
+                nodes.push(ValueNode::Application(value));
+                consumed
+            }
+        };
+        data = &data[consumed..];
+    }
+    Ok(nodes)
+}
+
+/// Character set 0 is UTF-8 (formerly ANSI X3.4); UCS-2 and ISO 8859-1 are
+/// also seen in the field. Other sets are decoded lossily.
+fn decode_character_string(content: &[u8]) -> String {
+    let (charset, bytes) = match content.split_first() {
+        Some((c, rest)) => (*c, rest),
+        None => return String::new(),
+    };
+    match charset {
+        4 => {
+            let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        5 => bytes.iter().map(|b| *b as char).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_extended_tag_numbers() {
+        let tag = decode_tag(&[0xF9, 0x40, 0x01]).unwrap();
+        assert_eq!(tag, Tag { number: 64, context: true, kind: TagKind::Primitive(1), header_len: 2 });
+        assert!(decode_tag(&[0xF9]).is_err());
+    }
+
+    #[test]
+    fn decodes_extended_lengths() {
+        let tag = decode_tag(&[0x65, 0x10]).unwrap();
+        assert_eq!((tag.kind, tag.header_len), (TagKind::Primitive(16), 2));
+        let tag = decode_tag(&[0x65, 0xFE, 0x01, 0x2C]).unwrap();
+        assert_eq!((tag.kind, tag.header_len), (TagKind::Primitive(300), 4));
+        let tag = decode_tag(&[0x65, 0xFF, 0x00, 0x01, 0x00, 0x00]).unwrap();
+        assert_eq!((tag.kind, tag.header_len), (TagKind::Primitive(65536), 6));
+        assert!(decode_tag(&[0x65, 0xFE, 0x01]).is_err());
+        assert!(decode_tag(&[0x65]).is_err());
+
+        let mut data = vec![0x75, 0xFE, 0x01, 0x2C, 0x00];
+        data.extend([b'x'; 299]);
+        let (value, consumed) = decode_application_value(&data).unwrap();
+        assert_eq!(value, BacnetValue::CharacterString("x".repeat(299)));
+        assert_eq!(consumed, data.len());
+        assert!(decode_application_value(&data[..100]).is_err());
+    }
+
+    #[test]
+    fn decodes_character_sets() {
+        let utf8 = decode_application_value(&[0x74, 0x00, 0xC3, 0xA9, b'A']).unwrap().0;
+        assert_eq!(utf8, BacnetValue::CharacterString("éA".into()));
+        let ucs2 = decode_application_value(&[0x75, 0x05, 0x04, 0x00, b'h', 0x00, b'i']).unwrap().0;
+        assert_eq!(ucs2, BacnetValue::CharacterString("hi".into()));
+        let latin1 = decode_application_value(&[0x73, 0x05, 0xE9, b'A']).unwrap().0;
+        assert_eq!(latin1, BacnetValue::CharacterString("éA".into()));
+        // Unknown sets are decoded as lossy UTF-8
+        let unknown = decode_application_value(&[0x74, 0x03, b'a', b'b', 0xFF]).unwrap().0;
+        assert_eq!(unknown, BacnetValue::CharacterString("ab\u{FFFD}".into()));
+        assert_eq!(decode_application_value(&[0x70]).unwrap().0, BacnetValue::CharacterString(String::new()));
+    }
+
+    #[test]
+    fn decodes_primitive_values() {
+        let values = decode_application_values(&[0x00, 0x11, 0x21, 0x05, 0x31, 0xFF, 0x44, 0x41, 0xAC, 0x00, 0x00, 0x91, 0x01]).unwrap();
+        assert_eq!(
+            values,
+            [BacnetValue::Null, BacnetValue::Boolean(true), BacnetValue::Unsigned(5), BacnetValue::Signed(-1), BacnetValue::Real(21.5), BacnetValue::Enumerated(1)]
+        );
+        let (value, _) = decode_application_value(&[0xC4, 0x02, 0x00, 0x04, 0xD2]).unwrap();
+        assert_eq!(value, BacnetValue::ObjectIdentifier { object_type: 8, instance: 1234 });
+        assert!(decode_application_value(&[0x19, 0x55]).is_err());
+        assert!(decode_application_value(&[0x43, 0x41, 0xAC, 0x00]).is_err());
+    }
+
+    #[test]
+    fn finds_the_matching_closing_tag() {
+        // [3] { [0] { 5 } true } followed by another value
+        let data = [0x3E, 0x0E, 0x21, 0x05, 0x0F, 0x11, 0x3F, 0x21, 0x07];
+        let (content, consumed) = enclosed(&data, 3).unwrap();
+        assert_eq!(content, &data[1..6]);
+        assert_eq!(consumed, 7);
+
+        assert!(enclosed(&data, 2).is_err());
+        assert!(enclosed(&[0x3E, 0x21, 0x05, 0x2F], 3).is_err());
+        assert!(enclosed(&[0x3E, 0x0E, 0x21, 0x05, 0x0F], 3).is_err());
+    }
+
+    #[test]
+    fn decodes_nested_constructed_values() {
+        // [0] { 08:00 21.5 [1] { [2] 0x55 } } 7
+        let data = [0x0E, 0xB4, 0x08, 0x00, 0x00, 0x00, 0x44, 0x41, 0xAC, 0x00, 0x00, 0x1E, 0x29, 0x55, 0x1F, 0x0F, 0x21, 0x07];
+        let nodes = decode_value_nodes(&data).unwrap();
+        let time = BacnetValue::Time { hour: Some(8), minute: Some(0), second: Some(0), hundredths: Some(0) };
+        assert_eq!(
+            nodes,
+            [
+                ValueNode::Constructed {
+                    tag: 0,
+                    items: vec![
+                        ValueNode::Application(time),
+                        ValueNode::Application(BacnetValue::Real(21.5)),
+                        ValueNode::Constructed { tag: 1, items: vec![ValueNode::Context { tag: 2, raw: "55".into() }] },
+                    ],
+                },
+                ValueNode::Application(BacnetValue::Unsigned(7)),
+            ]
+        );
+        assert!(decode_value_nodes(&[0x0F]).is_err());
+        assert!(decode_value_nodes(&[0x0E, 0x21, 0x05]).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! COV subscription constants and COV notification decoding.
+
+use crate::common::types::ValueStatus;
+use crate::core::bacnet::codec::{self, TagKind};
+use crate::core::bacnet::property_value;
+use anyhow::Result;
+use bacnet_rs::encoding;
+use bacnet_rs::object::PropertyIdentifier;
+
+pub const UNCONFIRMED_COV_NOTIFICATION: u8 = 2;
+
+/// A decoded (Unconfirmed)COVNotification.
+#[derive(Debug, Clone)]
+pub struct CovNotification {
+    pub process_id: u32,
+    pub device_id: u32,
+    pub object_type: u16,
+    pub instance: u32,
+    pub time_remaining: u32,
+    /// Property identifier and raw application-tagged value of each reported property
+    pub values: Vec<(u32, Vec<u8>)>,
+}
+
+impl CovNotification {
+    /// The reported Present_Value, if it is numeric.
+    pub fn present_value(&self) -> Option<f32> {
+        let (_, data) = self.values.iter().find(|(prop, _)| *prop == PropertyIdentifier::PresentValue as u32)?;
+        codec::decode_application_value(data).ok()?.0.as_f32()
+    }
+
+    /// Status properties reported with the value (usually only Status_Flags).
+    pub fn status(&self) -> ValueStatus {
+        property_value::value_status(self.values.iter().map(|(prop, data)| (*prop, data.as_slice())))
+    }
+}
+
+pub fn decode_notification(data: &[u8]) -> Result<CovNotification> {
+    let err = |e: encoding::EncodingError| anyhow::anyhow!(e.to_string());
+    let mut pos = 0;
+    let (process_id, consumed) = encoding::decode_context_unsigned(data, 0).map_err(err)?;
+    pos += consumed;
+    let ((_, device_id), consumed) = encoding::decode_context_object_id(&data[pos..], 1).map_err(err)?;
+    pos += consumed;
+    let ((object_type, instance), consumed) = encoding::decode_context_object_id(&data[pos..], 2).map_err(err)?;
+    pos += consumed;
+    let (time_remaining, consumed) = encoding::decode_context_unsigned(&data[pos..], 3).map_err(err)?;
+    pos += consumed;
+    let (list, _) = codec::enclosed(&data[pos..], 4)?;
+
+    let mut values = Vec::new();
+    let mut pos = 0;
+    while pos < list.len() {
+        let (prop, consumed) = encoding::decode_context_enumerated(&list[pos..], 0).map_err(err)?;
+        pos += consumed;
+        let tag = codec::decode_tag(&list[pos..])?;
+        if tag.context && tag.number == 1 && matches!(tag.kind, TagKind::Primitive(_)) {
+            let (_, consumed) = encoding::decode_context_unsigned(&list[pos..], 1).map_err(err)?;
+            pos += consumed;
+        }
+        let (value, consumed) = codec::enclosed(&list[pos..], 2)?;
+        pos += consumed;
+        values.push((prop, value.to_vec()));
+        // Optional priority
+        if pos < list.len() {
+            let tag = codec::decode_tag(&list[pos..])?;
+            if tag.context && tag.number == 3 {
+                let (_, consumed) = encoding::decode_context_unsigned(&list[pos..], 3).map_err(err)?;
+                pos += consumed;
+            }
+        }
+    }
+
+    Ok(CovNotification { process_id, device_id, object_type, instance, time_remaining, values })
+}
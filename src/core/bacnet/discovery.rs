@@ -1,50 +1,95 @@
 use bacnet_rs::{
     app::Apdu,
-    service::{IAmRequest, UnconfirmedServiceChoice, ReadPropertyResponse},
-    object::{ObjectType, ObjectIdentifier},
+    service::{IAmRequest, UnconfirmedServiceChoice},
     encoding,
 };
-use crate::common::types::BacnetDevice;
+use crate::common::types::{BacnetDevice, Segmentation};
+use crate::core::bacnet::object_id::ObjectId;
 use anyhow::Result;
 
 /// Parses an I-Am response and returns a BacnetDevice if successful.
+/// Max APDU length, segmentation and vendor are kept for request sizing.
 pub fn parse_i_am(apdu: &Apdu) -> Result<Option<BacnetDevice>> {
     if let Apdu::UnconfirmedRequest { service_choice, service_data } = apdu {
         if *service_choice == UnconfirmedServiceChoice::IAm as u8 {
             let i_am = IAmRequest::decode(service_data)
                 .map_err(|e| anyhow::anyhow!(e.to_string()))?;
             
+            let vendor_id = i_am.vendor_identifier as u16;
             return Ok(Some(BacnetDevice {
                 instance: i_am.device_identifier.instance,
                 address: "Unknown".to_string(), // In real usage, we'd get this from the DataLink source
+                network: None,
+                mac: None,
                 name: format!("Device {}", i_am.device_identifier.instance),
+                max_apdu_length_accepted: i_am.max_apdu_length_accepted,
+                segmentation_supported: Segmentation::from_u32(i_am.segmentation_supported),
+                vendor_id,
+                vendor_name: bacnet_rs::vendor::get_vendor_name(vendor_id).map(str::to_string),
+                model_name: None,
+                firmware_revision: None,
+                application_software_version: None,
+                location: None,
+                description: None,
+                protocol_services_supported: None,
+                database_revision: None,
             }));
         }
     }
     Ok(None)
 }
 
-pub fn parse_read_property_response(apdu: &Apdu) -> Result<Option<ReadPropertyResponse>> {
-    if let Apdu::ComplexAck { service_choice, service_data, .. } = apdu {
-        if *service_choice == 12 { // ReadProperty
-            let resp = ReadPropertyResponse::decode(service_data)
-                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-            return Ok(Some(resp));
-        }
+/// A decoded ReadProperty-ACK.
+#[derive(Debug, Clone)]
+pub struct ReadPropertyAck {
+    pub object_id: ObjectId,
+    pub property_identifier: u32,
+    pub property_array_index: Option<u32>,
+    /// Raw application-tagged value
+    pub property_value: Vec<u8>,
+}
+
+/// Decodes ReadProperty-ACK service data. The value is everything between the
+/// opening and the final closing tag 3, so values containing 0x3F bytes survive.
+pub fn decode_read_property_ack(data: &[u8]) -> Result<ReadPropertyAck> {
+    let mut pos = 0;
+    let ((obj_type, instance), consumed) = encoding::decode_context_object_id(data, 0)
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    pos += consumed;
+    let (property_identifier, consumed) = encoding::decode_context_enumerated(&data[pos..], 1)
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    pos += consumed;
+
+    let mut property_array_index = None;
+    if pos < data.len() && (data[pos] & 0xF8) == 0x28 {
+        let (index, consumed) = encoding::decode_context_unsigned(&data[pos..], 2)
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        property_array_index = Some(index);
+        pos += consumed;
     }
-    Ok(None)
+
+    if data.get(pos) != Some(&0x3E) || data.last() != Some(&0x3F) || data.len() < pos + 2 {
+        anyhow::bail!("Malformed ReadProperty-ACK property value");
+    }
+
+    Ok(ReadPropertyAck {
+        object_id: ObjectId::new(obj_type, instance),
+        property_identifier,
+        property_array_index,
+        property_value: data[pos + 1..data.len() - 1].to_vec(),
+    })
 }
 
-pub fn parse_object_list(data: &[u8]) -> Result<Vec<ObjectIdentifier>> {
+/// Decodes an Object_List (or one element of it). Unknown and proprietary
+/// object types are kept; anything that is not an object identifier fails.
+pub fn parse_object_list(data: &[u8]) -> Result<Vec<ObjectId>> {
     let mut objects = Vec::new();
     let mut pos = 0;
     while pos < data.len() {
-        if let Ok(((obj_type, instance), consumed)) = encoding::decode_object_identifier(&data[pos..]) {
-            objects.push(ObjectIdentifier::new(ObjectType::try_from(obj_type).unwrap(), instance));
-            pos += consumed;
-        } else {
-            break;
-        }
+        let ((obj_type, instance), consumed) = encoding::decode_object_identifier(&data[pos..])
+            .map_err(|e| anyhow::anyhow!("Malformed object list at byte {}: {}", pos, e))?;
+        objects.push(ObjectId::new(obj_type, instance));
+        pos += consumed;
     }
     Ok(objects)
 }
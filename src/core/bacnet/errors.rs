@@ -0,0 +1,190 @@
+//! Error, Reject and Abort PDU decoding.
+//!
+//! `bacnet_rs` decodes the Error PDU's class and code as raw bytes, but on the
+//! wire they are application-tagged enumerations, so all three PDUs are
+//! decoded here from the raw APDU.
+
+use crate::common::types::{AbortReason, ErrorClass, ErrorCode, RejectReason, RequestError};
+use crate::core::bacnet::codec::{self, BacnetValue};
+use anyhow::Result;
+
+const PDU_ERROR: u8 = 5;
+const PDU_REJECT: u8 = 6;
+const PDU_ABORT: u8 = 7;
+
+/// Implements `from_u32` and the standard hyphenated name for an enumeration
+/// carried on the wire as an unsigned value. Values not listed (including
+/// proprietary ones) map to `Other`.
+macro_rules! wire_enum {
+    ($name:ident { $($variant:ident = $value:literal => $text:literal,)* }) => {
+        impl $name {
+            pub fn from_u32(value: u32) -> Self {
+                match value {
+                    $($value => $name::$variant,)*
+                    other => $name::Other(other),
+                }
+            }
+
+            pub fn name(&self) -> String {
+                match self {
+                    $($name::$variant => $text.to_string(),)*
+                    $name::Other(value) => value.to_string(),
+                }
+            }
+        }
+    };
+}
+
+wire_enum! {
+    ErrorClass {
+        Device = 0 => "device",
+        Object = 1 => "object",
+        Property = 2 => "property",
+        Resources = 3 => "resources",
+        Security = 4 => "security",
+        Services = 5 => "services",
+        Vt = 6 => "vt",
+        Communication = 7 => "communication",
+    }
+}
+
+wire_enum! {
+    ErrorCode {
+        ConfigurationInProgress = 2 => "configuration-in-progress",
+        DeviceBusy = 3 => "device-busy",
+        InconsistentParameters = 7 => "inconsistent-parameters",
+        InvalidDataType = 9 => "invalid-data-type",
+        MissingRequiredParameter = 16 => "missing-required-parameter",
+        NoSpaceForObject = 18 => "no-space-for-object",
+        NoSpaceToWriteProperty = 20 => "no-space-to-write-property",
+        OperationalProblem = 25 => "operational-problem",
+        PasswordFailure = 26 => "password-failure",
+        ReadAccessDenied = 27 => "read-access-denied",
+        ServiceRequestDenied = 29 => "service-request-denied",
+        Timeout = 30 => "timeout",
+        UnknownObject = 31 => "unknown-object",
+        UnknownProperty = 32 => "unknown-property",
+        UnsupportedObjectType = 36 => "unsupported-object-type",
+        ValueOutOfRange = 37 => "value-out-of-range",
+        WriteAccessDenied = 40 => "write-access-denied",
+        CharacterSetNotSupported = 41 => "character-set-not-supported",
+        InvalidArrayIndex = 42 => "invalid-array-index",
+        CovSubscriptionFailed = 43 => "cov-subscription-failed",
+        NotCovProperty = 44 => "not-cov-property",
+        OptionalFunctionalityNotSupported = 45 => "optional-functionality-not-supported",
+        DatatypeNotSupported = 47 => "datatype-not-supported",
+        PropertyIsNotAnArray = 50 => "property-is-not-an-array",
+    }
+}
+
+wire_enum! {
+    RejectReason {
+        BufferOverflow = 1 => "buffer-overflow",
+        InconsistentParameters = 2 => "inconsistent-parameters",
+        InvalidParameterDataType = 3 => "invalid-parameter-data-type",
+        InvalidTag = 4 => "invalid-tag",
+        MissingRequiredParameter = 5 => "missing-required-parameter",
+        ParameterOutOfRange = 6 => "parameter-out-of-range",
+        TooManyArguments = 7 => "too-many-arguments",
+        UndefinedEnumeration = 8 => "undefined-enumeration",
+        UnrecognizedService = 9 => "unrecognized-service",
+    }
+}
+
+wire_enum! {
+    AbortReason {
+        BufferOverflow = 1 => "buffer-overflow",
+        InvalidApduInThisState = 2 => "invalid-apdu-in-this-state",
+        PreemptedByHigherPriorityTask = 3 => "preempted-by-higher-priority-task",
+        SegmentationNotSupported = 4 => "segmentation-not-supported",
+        SecurityError = 5 => "security-error",
+        InsufficientSecurity = 6 => "insufficient-security",
+        WindowSizeOutOfRange = 7 => "window-size-out-of-range",
+        ApplicationExceededReplyTime = 8 => "application-exceeded-reply-time",
+        OutOfResources = 9 => "out-of-resources",
+        TsmTimeout = 10 => "tsm-timeout",
+        ApduTooLong = 11 => "apdu-too-long",
+    }
+}
+
+/// A decoded Error, Reject or Abort PDU.
+#[derive(Debug, Clone)]
+pub struct FailurePdu {
+    /// Service choice of the failed request; only Error PDUs carry it
+    pub service_choice: Option<u8>,
+    pub error: RequestError,
+}
+
+pub fn decode(apdu: &[u8]) -> Result<FailurePdu> {
+    if apdu.len() < 3 {
+        anyhow::bail!("Truncated APDU");
+    }
+    match apdu[0] >> 4 {
+        PDU_ERROR => {
+            let (class, consumed) = codec::decode_application_value(&apdu[3..])?;
+            let (code, _) = codec::decode_application_value(&apdu[3 + consumed..])?;
+            let (BacnetValue::Enumerated(class), BacnetValue::Enumerated(code)) = (class, code) else {
+                anyhow::bail!("Malformed Error PDU");
+            };
+            Ok(FailurePdu {
+                service_choice: Some(apdu[2]),
+                error: RequestError::Error { class: ErrorClass::from_u32(class), code: ErrorCode::from_u32(code) },
+            })
+        }
+        PDU_REJECT => Ok(FailurePdu {
+            service_choice: None,
+            error: RequestError::Reject(RejectReason::from_u32(apdu[2] as u32)),
+        }),
+        PDU_ABORT => Ok(FailurePdu {
+            service_choice: None,
+            error: RequestError::Abort { reason: AbortReason::from_u32(apdu[2] as u32), server: apdu[0] & 0x01 != 0 },
+        }),
+        other => anyhow::bail!("PDU type {} is not Error, Reject or Abort", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_error_pdus() {
+        // ReadProperty (12): property/unknown-property
+        let pdu = decode(&[0x50, 0x01, 0x0C, 0x91, 0x02, 0x91, 0x20]).unwrap();
+        assert_eq!(pdu.service_choice, Some(12));
+        assert_eq!(pdu.error, RequestError::Error { class: ErrorClass::Property, code: ErrorCode::UnknownProperty });
+
+        // Codes beyond one octet and unknown to the gateway
+        let pdu = decode(&[0x50, 0x01, 0x0F, 0x91, 0x02, 0x92, 0x01, 0x2C]).unwrap();
+        assert_eq!(pdu.error, RequestError::Error { class: ErrorClass::Property, code: ErrorCode::Other(300) });
+    }
+
+    #[test]
+    fn decodes_reject_pdus() {
+        let pdu = decode(&[0x60, 0x01, 0x09]).unwrap();
+        assert_eq!(pdu.service_choice, None);
+        assert_eq!(pdu.error, RequestError::Reject(RejectReason::UnrecognizedService));
+    }
+
+    #[test]
+    fn decodes_abort_pdus_with_their_sender() {
+        let pdu = decode(&[0x71, 0x01, 0x04]).unwrap();
+        assert_eq!(pdu.error, RequestError::Abort { reason: AbortReason::SegmentationNotSupported, server: true });
+        let pdu = decode(&[0x70, 0x01, 0x0A]).unwrap();
+        assert_eq!(pdu.error, RequestError::Abort { reason: AbortReason::TsmTimeout, server: false });
+    }
+
+    #[test]
+    fn rejects_short_and_malformed_pdus() {
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[0x60, 0x01]).is_err());
+        // Error PDU without its code
+        assert!(decode(&[0x50, 0x01, 0x0C, 0x91, 0x02]).is_err());
+        // Error PDU with a length running past the end
+        assert!(decode(&[0x50, 0x01, 0x0C, 0x91, 0x02, 0x92, 0x01]).is_err());
+        // Class and code that are not enumerated
+        assert!(decode(&[0x50, 0x01, 0x0C, 0x21, 0x02, 0x21, 0x20]).is_err());
+        // Complex-ACK
+        assert!(decode(&[0x30, 0x01, 0x0C, 0x00]).is_err());
+    }
+}
@@ -1 +1,13 @@
-pub mod client; pub mod discovery; pub mod services;
+pub mod client;
+pub mod codec;
+pub mod cov;
+pub mod discovery;
+pub mod errors;
+pub mod npdu;
+pub mod object_id;
+pub mod properties;
+pub mod property_value;
+pub mod rpm;
+pub mod services;
+pub mod transaction;
+pub mod who_has;
@@ -0,0 +1,240 @@
+//! Network layer (NPDU) handling.
+//!
+//! Every frame carries an NPDU. Devices behind a BACnet router are recorded
+//! with the source network (SNET) and MAC (SADR) their I-Am arrived with,
+//! and requests to them carry the matching DNET/DADR and go to the router
+//! learned for that network from I-Am-Router-To-Network replies.
+
+use bacnet_rs::{
+    datalink::DataLinkAddress,
+    network::{NetworkAddress, NetworkLayerMessage, NetworkMessageType, Npdu},
+};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// Global broadcast network number (DNET = 0xFFFF).
+pub const GLOBAL_NETWORK: u16 = 0xFFFF;
+
+/// Network number -> router address, learned from I-Am-Router-To-Network.
+pub type RoutingTable = Arc<Mutex<HashMap<u16, SocketAddr>>>;
+
+/// A complete BACnet address: the BACnet/IP next hop plus, for devices
+/// behind a router, the remote network number and MAC (DNET/DADR or SNET/SADR).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacnetAddress {
+    pub datalink: DataLinkAddress,
+    pub remote: Option<NetworkAddress>,
+}
+
+impl BacnetAddress {
+    pub fn local(datalink: DataLinkAddress) -> Self {
+        Self { datalink, remote: None }
+    }
+
+    pub fn routed(datalink: DataLinkAddress, network: u16, mac: Vec<u8>) -> Self {
+        Self { datalink, remote: Some(NetworkAddress::new(network, mac)) }
+    }
+
+    /// Global broadcast, forwarded by routers to every network.
+    pub fn global_broadcast() -> Self {
+        Self::routed(DataLinkAddress::Broadcast, GLOBAL_NETWORK, Vec::new())
+    }
+
+    /// Builds the address for a device record. Devices behind a router are
+    /// reached through the router learned for their network, falling back to
+    /// the address the device's I-Am arrived from.
+    pub fn resolve(address: &str, network: Option<u16>, mac: Option<&str>, routes: &RoutingTable) -> Option<Self> {
+        let mut next_hop = match address.parse::<SocketAddr>() {
+            Ok(addr) => Some(addr),
+            Err(_) => {
+                log::error!("Invalid device address: {}", address);
+                None
+            }
+        };
+        match network {
+            Some(network) => {
+                if let Some(router) = routes.lock().unwrap().get(&network) {
+                    next_hop = Some(*router);
+                }
+                let mac = match mac.map(hex::decode) {
+                    Some(Ok(mac)) => mac,
+                    Some(Err(e)) => {
+                        log::error!("Invalid MAC for network {}: {}", network, e);
+                        return None;
+                    }
+                    None => Vec::new(),
+                };
+                next_hop.map(|hop| Self::routed(DataLinkAddress::Ip(hop), network, mac))
+            }
+            None => next_hop.map(|hop| Self::local(DataLinkAddress::Ip(hop))),
+        }
+    }
+
+    pub fn network(&self) -> Option<u16> {
+        self.remote.as_ref().map(|r| r.network)
+    }
+
+    pub fn mac_hex(&self) -> Option<String> {
+        self.remote.as_ref().map(|r| hex::encode(&r.address))
+    }
+
+    /// The next-hop address as stored in device records, e.g. `10.0.0.5:47808`.
+    pub fn datalink_string(&self) -> String {
+        match &self.datalink {
+            DataLinkAddress::Ip(addr) => addr.to_string(),
+            other => format!("{:?}", other),
+        }
+    }
+}
+
+impl fmt::Display for BacnetAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.datalink {
+            DataLinkAddress::Ip(addr) => write!(f, "{}", addr)?,
+            other => write!(f, "{:?}", other)?,
+        }
+        if let Some(remote) = &self.remote {
+            write!(f, " via net {} mac {}", remote.network, hex::encode(&remote.address))?;
+        }
+        Ok(())
+    }
+}
+
+/// Decoded network layer content of a received frame.
+pub enum NpduPayload<'a> {
+    /// An APDU, with the originating address (SNET/SADR if routed).
+    Apdu(&'a [u8]),
+    /// A network layer message (Who-Is-Router-To-Network, I-Am-Router-To-Network, ...).
+    NetworkMessage(NetworkLayerMessage),
+}
+
+/// Strips the NPDU header from a received frame. The returned address carries
+/// the source network/MAC when the frame was relayed by a router.
+pub fn decode(data: &[u8], src: DataLinkAddress) -> Result<(BacnetAddress, NpduPayload<'_>)> {
+    let (npdu, consumed) = Npdu::decode(data).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let source = BacnetAddress { datalink: src, remote: npdu.source.clone() };
+    let payload = &data[consumed..];
+
+    if npdu.is_network_message() {
+        let msg = NetworkLayerMessage::decode(payload).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        Ok((source, NpduPayload::NetworkMessage(msg)))
+    } else {
+        Ok((source, NpduPayload::Apdu(payload)))
+    }
+}
+
+/// Builds the NPDU header for an APDU sent to `dest`.
+pub fn encode_header(dest: &BacnetAddress, expecting_reply: bool) -> Vec<u8> {
+    let mut npdu = Npdu::new();
+    npdu.control.expecting_reply = expecting_reply;
+    if let Some(remote) = &dest.remote {
+        npdu.control.destination_present = true;
+        npdu.destination = Some(remote.clone());
+        npdu.hop_count = Some(255);
+    }
+    npdu.encode()
+}
+
+/// Builds a complete Who-Is-Router-To-Network message, optionally for one network.
+pub fn encode_who_is_router_to_network(network: Option<u16>) -> Vec<u8> {
+    let mut npdu = Npdu::new();
+    npdu.control.network_message = true;
+    let mut buffer = npdu.encode();
+    let data = network.map(|n| n.to_be_bytes().to_vec()).unwrap_or_default();
+    buffer.extend(NetworkLayerMessage::new(NetworkMessageType::WhoIsRouterToNetwork, data).encode());
+    buffer
+}
+
+/// Extracts the list of reachable network numbers from an I-Am-Router-To-Network.
+pub fn parse_i_am_router_to_network(msg: &NetworkLayerMessage) -> Option<Vec<u16>> {
+    if msg.message_type != NetworkMessageType::IAmRouterToNetwork {
+        return None;
+    }
+    Some(
+        msg.data
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// I-Am of device 1234: max APDU 1476, segmentation none, vendor 15.
+    const I_AM: [u8; 15] = [0x10, 0x00, 0xC4, 0x02, 0x00, 0x04, 0xD2, 0x22, 0x05, 0xC4, 0x91, 0x03, 0x21, 0x0F, 0x00];
+
+    fn router() -> SocketAddr {
+        "10.0.0.1:47808".parse().unwrap()
+    }
+
+    #[test]
+    fn encodes_local_and_routed_headers() {
+        let local = BacnetAddress::local(DataLinkAddress::Ip(router()));
+        assert_eq!(encode_header(&local, true), [0x01, 0x04]);
+        assert_eq!(encode_header(&local, false), [0x01, 0x00]);
+
+        let routed = BacnetAddress::routed(DataLinkAddress::Ip(router()), 5, vec![0x0A]);
+        assert_eq!(encode_header(&routed, true), [0x01, 0x24, 0x00, 0x05, 0x01, 0x0A, 0xFF]);
+        assert_eq!(encode_header(&BacnetAddress::global_broadcast(), false), [0x01, 0x20, 0xFF, 0xFF, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn round_trips_an_apdu() {
+        let dest = BacnetAddress::local(DataLinkAddress::Ip(router()));
+        let mut frame = encode_header(&dest, false);
+        frame.extend(I_AM);
+        let (source, payload) = decode(&frame, DataLinkAddress::Ip(router())).unwrap();
+        assert_eq!(source, dest);
+        assert!(matches!(payload, NpduPayload::Apdu(apdu) if apdu == I_AM));
+    }
+
+    #[test]
+    fn decodes_a_routed_i_am_with_its_source() {
+        // SNET 5, SADR 0x0A
+        let mut frame = vec![0x01, 0x08, 0x00, 0x05, 0x01, 0x0A];
+        frame.extend(I_AM);
+        let (source, payload) = decode(&frame, DataLinkAddress::Ip(router())).unwrap();
+        assert_eq!(source.network(), Some(5));
+        assert_eq!(source.mac_hex().as_deref(), Some("0a"));
+        assert_eq!(source.datalink, DataLinkAddress::Ip(router()));
+        assert!(matches!(payload, NpduPayload::Apdu(apdu) if apdu == I_AM));
+    }
+
+    #[test]
+    fn encodes_who_is_router_to_network() {
+        assert_eq!(encode_who_is_router_to_network(None), [0x01, 0x80, 0x00]);
+        assert_eq!(encode_who_is_router_to_network(Some(5)), [0x01, 0x80, 0x00, 0x00, 0x05]);
+    }
+
+    #[test]
+    fn parses_i_am_router_to_network_replies() {
+        let frame = [0x01, 0x80, 0x01, 0x00, 0x05, 0x01, 0x2C];
+        let (_, payload) = decode(&frame, DataLinkAddress::Ip(router())).unwrap();
+        let NpduPayload::NetworkMessage(msg) = payload else { panic!("expected a network message") };
+        assert_eq!(parse_i_am_router_to_network(&msg), Some(vec![5, 300]));
+
+        let who_is = NetworkLayerMessage::new(NetworkMessageType::WhoIsRouterToNetwork, vec![0x00, 0x05]);
+        assert_eq!(parse_i_am_router_to_network(&who_is), None);
+    }
+
+    #[test]
+    fn resolves_routed_devices_through_their_router() {
+        let routes: RoutingTable = Arc::default();
+        routes.lock().unwrap().insert(5, router());
+        let dest = BacnetAddress::resolve("10.0.0.9:47808", Some(5), Some("0a"), &routes).unwrap();
+        assert_eq!(dest, BacnetAddress::routed(DataLinkAddress::Ip(router()), 5, vec![0x0A]));
+
+        // Unknown networks are reached through the address the I-Am came from
+        let dest = BacnetAddress::resolve("10.0.0.9:47808", Some(6), None, &routes).unwrap();
+        assert_eq!(dest.datalink, DataLinkAddress::Ip("10.0.0.9:47808".parse().unwrap()));
+        assert_eq!(dest.network(), Some(6));
+
+        assert!(BacnetAddress::resolve("10.0.0.9:47808", Some(5), Some("zz"), &routes).is_none());
+        assert!(BacnetAddress::resolve("not an address", None, None, &routes).is_none());
+    }
+}
@@ -0,0 +1,182 @@
+//! Object identifiers as carried on the wire.
+//!
+//! `bacnet_rs::object::ObjectType` only covers the standard types up to 30, so
+//! `ObjectIdentifier` cannot hold newer standard types (Network_Port, the
+//! value objects) or vendor-proprietary ones (128 and up). The gateway keeps
+//! the raw type number instead.
+
+use crate::common::types::object_id_string;
+use crate::core::bacnet::properties;
+use anyhow::{bail, Result};
+use bacnet_rs::object::{ObjectType, PropertyIdentifier, PropertyValue};
+
+/// Types with a Priority_Array: analog, binary and multi-state outputs and values.
+const COMMANDABLE_TYPES: &[u16] = &[1, 2, 4, 5, 14, 19];
+/// Binary inputs, outputs and values, whose Present_Value is enumerated.
+const BINARY_TYPES: &[u16] = &[3, 4, 5];
+/// Multi-state inputs, outputs and values, whose Present_Value is unsigned.
+const MULTI_STATE_TYPES: &[u16] = &[13, 14, 19];
+
+/// BACnetObjectType names, indexed by type number.
+const OBJECT_TYPES: &[&str] = &[
+    "AnalogInput",
+    "AnalogOutput",
+    "AnalogValue",
+    "BinaryInput",
+    "BinaryOutput",
+    "BinaryValue",
+    "Calendar",
+    "Command",
+    "Device",
+    "EventEnrollment",
+    "File",
+    "Group",
+    "Loop",
+    "MultiStateInput",
+    "MultiStateOutput",
+    "NotificationClass",
+    "Program",
+    "Schedule",
+    "Averaging",
+    "MultiStateValue",
+    "TrendLog",
+    "LifeSafetyPoint",
+    "LifeSafetyZone",
+    "Accumulator",
+    "PulseConverter",
+    "EventLog",
+    "GlobalGroup",
+    "TrendLogMultiple",
+    "LoadControl",
+    "StructuredView",
+    "AccessDoor",
+    "Timer",
+    "AccessCredential",
+    "AccessPoint",
+    "AccessRights",
+    "AccessUser",
+    "AccessZone",
+    "CredentialDataInput",
+    "NetworkSecurity",
+    "BitstringValue",
+    "CharacterstringValue",
+    "DatePatternValue",
+    "DateValue",
+    "DatetimePatternValue",
+    "DatetimeValue",
+    "IntegerValue",
+    "LargeAnalogValue",
+    "OctetstringValue",
+    "PositiveIntegerValue",
+    "TimePatternValue",
+    "TimeValue",
+    "NotificationForwarder",
+    "AlertEnrollment",
+    "Channel",
+    "LightingOutput",
+    "BinaryLightingOutput",
+    "NetworkPort",
+    "ElevatorGroup",
+    "Escalator",
+    "Lift",
+    "Staging",
+    "AuditLog",
+    "AuditReporter",
+    "Color",
+    "ColorTemperature",
+];
+
+/// First object type number reserved for vendor-proprietary types.
+pub const FIRST_PROPRIETARY_OBJECT_TYPE: u16 = 128;
+
+/// Name of an object type; `Proprietary{n}` for vendor types and
+/// `ObjectType{n}` for standard types newer than this table.
+pub fn object_type_name(object_type: u16) -> String {
+    match OBJECT_TYPES.get(object_type as usize) {
+        Some(name) => name.to_string(),
+        None if object_type >= FIRST_PROPRIETARY_OBJECT_TYPE => format!("Proprietary{}", object_type),
+        None => format!("ObjectType{}", object_type),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId {
+    pub object_type: u16,
+    pub instance: u32,
+}
+
+impl ObjectId {
+    pub fn new(object_type: u16, instance: u32) -> Self {
+        Self { object_type, instance }
+    }
+
+    pub fn device(instance: u32) -> Self {
+        Self::new(ObjectType::Device as u16, instance)
+    }
+
+    /// Whether writes to Present_Value go through a Priority_Array.
+    pub fn is_commandable(&self) -> bool {
+        COMMANDABLE_TYPES.contains(&self.object_type)
+    }
+
+    /// Encodes a numeric value for writing to a property. Present_Value and
+    /// Relinquish_Default take the object's datatype: active/inactive as
+    /// Enumerated for binary objects, the state number as Unsigned for
+    /// multi-state objects, and Real otherwise.
+    pub fn write_value(&self, property: u32, value: f32) -> Result<PropertyValue> {
+        let typed = property == PropertyIdentifier::PresentValue as u32 || property == properties::RELINQUISH_DEFAULT;
+        if typed && BINARY_TYPES.contains(&self.object_type) {
+            if value != 0.0 && value != 1.0 {
+                bail!("{} takes 0 (inactive) or 1 (active), not {}", self, value);
+            }
+            return Ok(PropertyValue::Enumerated(value as u32));
+        }
+        if typed && MULTI_STATE_TYPES.contains(&self.object_type) {
+            if value.fract() != 0.0 || value < 1.0 || value > u32::MAX as f32 {
+                bail!("{} takes a state number from 1, not {}", self, value);
+            }
+            return Ok(PropertyValue::UnsignedInteger(value as u32));
+        }
+        Ok(PropertyValue::Real(value))
+    }
+
+    /// The type as a `bacnet_rs` enum, for the types it knows.
+    pub fn known_type(&self) -> Option<ObjectType> {
+        ObjectType::try_from(self.object_type).ok()
+    }
+}
+
+impl std::fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&object_id_string(self.object_type, self.instance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESENT_VALUE: u32 = PropertyIdentifier::PresentValue as u32;
+
+    #[test]
+    fn present_value_follows_the_object_type() {
+        assert!(matches!(ObjectId::new(4, 1).write_value(PRESENT_VALUE, 1.0), Ok(PropertyValue::Enumerated(1))));
+        assert!(matches!(ObjectId::new(5, 1).write_value(PRESENT_VALUE, 0.0), Ok(PropertyValue::Enumerated(0))));
+        assert!(matches!(ObjectId::new(19, 1).write_value(PRESENT_VALUE, 3.0), Ok(PropertyValue::UnsignedInteger(3))));
+        assert!(matches!(ObjectId::new(14, 1).write_value(properties::RELINQUISH_DEFAULT, 2.0), Ok(PropertyValue::UnsignedInteger(2))));
+        assert!(matches!(ObjectId::new(2, 1).write_value(PRESENT_VALUE, 21.5), Ok(PropertyValue::Real(v)) if v == 21.5));
+    }
+
+    #[test]
+    fn rejects_values_outside_the_datatype() {
+        assert!(ObjectId::new(5, 1).write_value(PRESENT_VALUE, 2.0).is_err());
+        assert!(ObjectId::new(4, 1).write_value(PRESENT_VALUE, 0.5).is_err());
+        assert!(ObjectId::new(19, 1).write_value(PRESENT_VALUE, 0.0).is_err());
+        assert!(ObjectId::new(14, 1).write_value(PRESENT_VALUE, 1.5).is_err());
+    }
+
+    #[test]
+    fn other_properties_are_written_as_real() {
+        assert!(matches!(ObjectId::new(5, 1).write_value(PropertyIdentifier::Bias as u32, 2.0), Ok(PropertyValue::Real(_))));
+    }
+}
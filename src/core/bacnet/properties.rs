@@ -0,0 +1,122 @@
+//! Property identifiers missing from `bacnet_rs::object::PropertyIdentifier`.
+
+pub const DESCRIPTION: u32 = 28;
+pub const INACTIVE_TEXT: u32 = 46;
+pub const LOCATION: u32 = 58;
+pub const PROTOCOL_SERVICES_SUPPORTED: u32 = 97;
+pub const RELIABILITY: u32 = 103;
+pub const RELINQUISH_DEFAULT: u32 = 104;
+pub const STATE_TEXT: u32 = 110;
+pub const STATUS_FLAGS: u32 = 111;
+pub const UNITS: u32 = 117;
+pub const WEEKLY_SCHEDULE: u32 = 123;
+
+/// BACnetConfirmedServiceChoice values.
+pub const CONFIRMED_SERVICES: &[&str] = &[
+    "AcknowledgeAlarm",
+    "ConfirmedCOVNotification",
+    "ConfirmedEventNotification",
+    "GetAlarmSummary",
+    "GetEnrollmentSummary",
+    "SubscribeCOV",
+    "AtomicReadFile",
+    "AtomicWriteFile",
+    "AddListElement",
+    "RemoveListElement",
+    "CreateObject",
+    "DeleteObject",
+    "ReadProperty",
+    "ReadPropertyConditional",
+    "ReadPropertyMultiple",
+    "WriteProperty",
+    "WritePropertyMultiple",
+    "DeviceCommunicationControl",
+    "ConfirmedPrivateTransfer",
+    "ConfirmedTextMessage",
+    "ReinitializeDevice",
+    "VT-Open",
+    "VT-Close",
+    "VT-Data",
+    "Authenticate",
+    "RequestKey",
+    "ReadRange",
+    "LifeSafetyOperation",
+    "SubscribeCOVProperty",
+    "GetEventInformation",
+    "SubscribeCOVPropertyMultiple",
+    "ConfirmedCOVNotificationMultiple",
+    "ConfirmedAuditNotification",
+    "AuditLogQuery",
+];
+
+/// Bit positions of BACnetServicesSupported (Protocol_Services_Supported).
+pub const SERVICES_SUPPORTED: &[&str] = &[
+    "AcknowledgeAlarm",
+    "ConfirmedCOVNotification",
+    "ConfirmedEventNotification",
+    "GetAlarmSummary",
+    "GetEnrollmentSummary",
+    "SubscribeCOV",
+    "AtomicReadFile",
+    "AtomicWriteFile",
+    "AddListElement",
+    "RemoveListElement",
+    "CreateObject",
+    "DeleteObject",
+    "ReadProperty",
+    "ReadPropertyConditional",
+    "ReadPropertyMultiple",
+    "WriteProperty",
+    "WritePropertyMultiple",
+    "DeviceCommunicationControl",
+    "ConfirmedPrivateTransfer",
+    "ConfirmedTextMessage",
+    "ReinitializeDevice",
+    "VtOpen",
+    "VtClose",
+    "VtData",
+    "Authenticate",
+    "RequestKey",
+    "I-Am",
+    "I-Have",
+    "UnconfirmedCOVNotification",
+    "UnconfirmedEventNotification",
+    "UnconfirmedPrivateTransfer",
+    "UnconfirmedTextMessage",
+    "TimeSynchronization",
+    "Who-Has",
+    "Who-Is",
+    "ReadRange",
+    "UtcTimeSynchronization",
+    "LifeSafetyOperation",
+    "SubscribeCOVProperty",
+    "GetEventInformation",
+    "WriteGroup",
+    "SubscribeCOVPropertyMultiple",
+    "ConfirmedCOVNotificationMultiple",
+    "UnconfirmedCOVNotificationMultiple",
+    "ConfirmedAuditNotification",
+    "AuditLogQuery",
+    "UnconfirmedAuditNotification",
+    "Who-Am-I",
+    "You-Are",
+];
+
+pub fn confirmed_service_name(choice: u8) -> String {
+    match CONFIRMED_SERVICES.get(choice as usize) {
+        Some(name) => name.to_string(),
+        None => format!("Service{}", choice),
+    }
+}
+
+/// Names of the services whose bits are set in a Protocol_Services_Supported bitstring.
+pub fn supported_service_names(bits: &[bool]) -> Vec<String> {
+    bits.iter()
+        .enumerate()
+        .filter(|(_, set)| **set)
+        .map(|(i, _)| match SERVICES_SUPPORTED.get(i) {
+            Some(name) => name.to_string(),
+            None => format!("Service{}", i),
+        })
+        .collect()
+}
@@ -0,0 +1,139 @@
+//! Structured JSON view of property values of any type.
+
+use crate::common::types::{StatusFlags, ValueStatus};
+use crate::core::bacnet::codec::{self, BacnetValue, ValueNode};
+use crate::core::bacnet::properties;
+use anyhow::Result;
+use bacnet_rs::object::PropertyIdentifier;
+use serde_json::{json, Value};
+
+const DAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+/// Decodes the value of `property` into JSON. Priority_Array, Weekly_Schedule
+/// and Status_Flags get their defined structure; anything else is shown as
+/// its decoded tag tree, with a single element unwrapped.
+pub fn to_json(property: u32, array_index: Option<u32>, data: &[u8]) -> Result<Value> {
+    let nodes = codec::decode_value_nodes(data)?;
+    let value = match (property, array_index) {
+        (p, None) if p == PropertyIdentifier::PriorityArray as u32 => priority_array(&nodes),
+        (properties::WEEKLY_SCHEDULE, None) => {
+            let days = nodes.iter().zip(DAYS).map(|(node, day)| json!({ "day": day, "entries": daily_schedule(node) }));
+            Value::Array(days.collect())
+        }
+        (properties::WEEKLY_SCHEDULE, Some(index)) if index > 0 && nodes.len() == 1 => daily_schedule(&nodes[0]),
+        (properties::STATUS_FLAGS, _) => match nodes.as_slice() {
+            [ValueNode::Application(BacnetValue::BitString(bits))] => serde_json::to_value(StatusFlags::from_bits(bits))?,
+            _ => generic(&nodes),
+        },
+        _ => generic(&nodes),
+    };
+    Ok(value)
+}
+
+fn generic(nodes: &[ValueNode]) -> Value {
+    match nodes {
+        [node] => json!(node),
+        _ => json!(nodes),
+    }
+}
+
+/// The 16 command priorities, relinquished slots as `null`.
+fn priority_array(nodes: &[ValueNode]) -> Value {
+    let slots = nodes.iter().enumerate().map(|(i, node)| {
+        let value = match node {
+            ValueNode::Application(BacnetValue::Null) => Value::Null,
+            node => json!(node),
+        };
+        json!({ "priority": i + 1, "value": value })
+    });
+    Value::Array(slots.collect())
+}
+
+/// A BACnetDailySchedule: the day's time/value pairs.
+fn daily_schedule(node: &ValueNode) -> Value {
+    match node {
+        ValueNode::Constructed { tag: 0, items } => {
+            let entries = items.chunks(2).map(|pair| json!({ "time": pair[0], "value": pair.get(1) }));
+            Value::Array(entries.collect())
+        }
+        node => json!(node),
+    }
+}
+
+/// Collects Status_Flags, Reliability and Out_Of_Service from properties read
+/// or notified with a value. Other and undecodable properties are ignored.
+pub fn value_status<'a>(values: impl IntoIterator<Item = (u32, &'a [u8])>) -> ValueStatus {
+    let mut status = ValueStatus::default();
+    for (property, data) in values {
+        let Ok((value, _)) = codec::decode_application_value(data) else { continue };
+        match (property, value) {
+            (properties::STATUS_FLAGS, BacnetValue::BitString(bits)) => status.status_flags = Some(StatusFlags::from_bits(&bits)),
+            (properties::RELIABILITY, BacnetValue::Enumerated(reliability)) => status.reliability = Some(reliability),
+            (p, BacnetValue::Boolean(oos)) if p == PropertyIdentifier::OutOfService as u32 => status.out_of_service = Some(oos),
+            _ => {}
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_priority_arrays() {
+        // Null at every priority but 8, which holds 21.5
+        let mut data = vec![0x00; 7];
+        data.extend([0x44, 0x41, 0xAC, 0x00, 0x00]);
+        data.extend([0x00; 8]);
+        let value = to_json(PropertyIdentifier::PriorityArray as u32, None, &data).unwrap();
+        let slots = value.as_array().unwrap();
+        assert_eq!(slots.len(), 16);
+        assert_eq!(slots[0], json!({ "priority": 1, "value": null }));
+        assert_eq!(slots[7], json!({ "priority": 8, "value": { "type": "Real", "value": 21.5 } }));
+    }
+
+    #[test]
+    fn shapes_weekly_schedules() {
+        // Monday 08:00 active, then six empty days
+        let mut data = vec![0x0E, 0xB4, 0x08, 0x00, 0x00, 0x00, 0x91, 0x01, 0x0F];
+        for _ in 0..6 {
+            data.extend([0x0E, 0x0F]);
+        }
+        let value = to_json(properties::WEEKLY_SCHEDULE, None, &data).unwrap();
+        let days = value.as_array().unwrap();
+        assert_eq!(days.len(), 7);
+        assert_eq!(days[0]["day"], "Monday");
+        assert_eq!(days[0]["entries"][0]["value"], json!({ "type": "Enumerated", "value": 1 }));
+        assert_eq!(days[0]["entries"][0]["time"]["value"]["hour"], 8);
+        assert_eq!(days[6], json!({ "day": "Sunday", "entries": [] }));
+
+        let monday = to_json(properties::WEEKLY_SCHEDULE, Some(1), &data[..9]).unwrap();
+        assert_eq!(monday, days[0]["entries"]);
+    }
+
+    #[test]
+    fn unwraps_single_values() {
+        assert_eq!(to_json(PropertyIdentifier::PresentValue as u32, None, &[0x21, 0x05]).unwrap(), json!({ "type": "Unsigned", "value": 5 }));
+        let flags = to_json(properties::STATUS_FLAGS, None, &[0x82, 0x04, 0x60]).unwrap();
+        assert_eq!(flags, json!({ "in_alarm": false, "fault": true, "overridden": true, "out_of_service": false }));
+        assert!(to_json(PropertyIdentifier::PresentValue as u32, None, &[0x0E]).is_err());
+    }
+
+    #[test]
+    fn collects_value_status() {
+        let flags = [0x82, 0x04, 0x40];
+        let reliability = [0x91, 0x07];
+        let out_of_service = [0x11];
+        let status = value_status([
+            (properties::STATUS_FLAGS, &flags[..]),
+            (properties::RELIABILITY, &reliability[..]),
+            (PropertyIdentifier::OutOfService as u32, &out_of_service[..]),
+            (PropertyIdentifier::PresentValue as u32, &[0x21, 0x01][..]),
+            (properties::RELIABILITY, &[0x91][..]),
+        ]);
+        assert_eq!(status.status_flags, Some(StatusFlags { fault: true, ..StatusFlags::default() }));
+        assert_eq!(status.reliability, Some(7));
+        assert_eq!(status.out_of_service, Some(true));
+    }
+}
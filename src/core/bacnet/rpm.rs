@@ -0,0 +1,115 @@
+//! ReadPropertyMultiple request encoding and ACK decoding.
+
+use crate::core::bacnet::codec::{self, BacnetValue, TagKind};
+use crate::core::bacnet::object_id::ObjectId;
+use anyhow::Result;
+use bacnet_rs::encoding;
+
+/// Service choice for ReadPropertyMultiple.
+pub const SERVICE_CHOICE: u8 = 14;
+
+/// Properties to read from one object.
+#[derive(Debug, Clone)]
+pub struct ReadAccessSpec {
+    pub object_id: ObjectId,
+    pub properties: Vec<u32>,
+}
+
+/// Error class and code returned for a single property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PropertyAccessError {
+    pub error_class: u32,
+    pub error_code: u32,
+}
+
+/// One property of a ReadPropertyMultiple-ACK. The value is the raw
+/// application-tagged content, as returned by `BacnetServices::read_property`.
+#[derive(Debug, Clone)]
+pub struct PropertyResult {
+    pub property: u32,
+    pub value: std::result::Result<Vec<u8>, PropertyAccessError>,
+}
+
+/// All properties returned for one object.
+#[derive(Debug, Clone)]
+pub struct ReadAccessResult {
+    pub object_type: u16,
+    pub instance: u32,
+    pub results: Vec<PropertyResult>,
+}
+
+pub fn encode_request(specs: &[ReadAccessSpec]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for spec in specs {
+        data.extend(
+            encoding::encode_context_object_id(spec.object_id.object_type, spec.object_id.instance, 0)
+                .map_err(|e| anyhow::anyhow!(e.to_string()))?,
+        );
+        data.push(0x1E); // Opening Tag 1
+        for prop in &spec.properties {
+            data.extend(encoding::encode_context_enumerated(*prop, 0).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        }
+        data.push(0x1F); // Closing Tag 1
+    }
+    Ok(data)
+}
+
+pub fn decode_ack(data: &[u8]) -> Result<Vec<ReadAccessResult>> {
+    let mut objects = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let ((object_type, instance), consumed) = encoding::decode_context_object_id(&data[pos..], 0)
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        pos += consumed;
+        let (list, consumed) = codec::enclosed(&data[pos..], 1)?;
+        pos += consumed;
+        objects.push(ReadAccessResult { object_type, instance, results: decode_results(list)? });
+    }
+    Ok(objects)
+}
+
+fn decode_results(data: &[u8]) -> Result<Vec<PropertyResult>> {
+    let mut results = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let (property, consumed) = encoding::decode_context_enumerated(&data[pos..], 2)
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        pos += consumed;
+
+        // Array index is only echoed when one was requested, which we never do
+        let tag = codec::decode_tag(&data[pos..])?;
+        if tag.context && tag.number == 3 && matches!(tag.kind, TagKind::Primitive(_)) {
+            let (_, consumed) = encoding::decode_context_unsigned(&data[pos..], 3)
+                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            pos += consumed;
+        }
+
+        let tag = codec::decode_tag(&data[pos..])?;
+        let value = match tag.number {
+            4 => {
+                let (value, consumed) = codec::enclosed(&data[pos..], 4)?;
+                pos += consumed;
+                Ok(value.to_vec())
+            }
+            5 => {
+                let (error, consumed) = codec::enclosed(&data[pos..], 5)?;
+                pos += consumed;
+                Err(decode_access_error(error)?)
+            }
+            n => anyhow::bail!("Unexpected tag {} in ReadPropertyMultiple-ACK", n),
+        };
+        results.push(PropertyResult { property, value });
+    }
+    Ok(results)
+}
+
+fn decode_access_error(data: &[u8]) -> Result<PropertyAccessError> {
+    let (class, consumed) = codec::decode_application_value(data)?;
+    let (code, _) = codec::decode_application_value(&data[consumed..])?;
+    match (class, code) {
+        (BacnetValue::Enumerated(error_class), BacnetValue::Enumerated(error_code)) => {
+            Ok(PropertyAccessError { error_class, error_code })
+        }
+        _ => anyhow::bail!("Malformed property access error"),
+    }
+}
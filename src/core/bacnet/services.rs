@@ -0,0 +1,105 @@
+use crate::core::bacnet::client::BacnetClient;
+use crate::core::bacnet::discovery;
+use crate::core::bacnet::npdu::BacnetAddress;
+use crate::core::bacnet::rpm::{self, ReadAccessResult, ReadAccessSpec};
+use crate::core::bacnet::transaction::TransactionManager;
+use anyhow::Result;
+use crate::core::bacnet::object_id::ObjectId;
+use bacnet_rs::object::PropertyValue;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// APDU timeout and retry count used for confirmed requests.
+const APDU_TIMEOUT: Duration = Duration::from_secs(3);
+const APDU_RETRIES: u32 = 2;
+
+/// The device did not answer a confirmed request at all, as opposed to
+/// answering with an error.
+#[derive(Debug)]
+pub struct NoResponse {
+    pub attempts: u32,
+}
+
+impl std::fmt::Display for NoResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No response after {} attempts", self.attempts)
+    }
+}
+
+impl std::error::Error for NoResponse {}
+
+/// Async request/response layer on top of `BacnetClient`. Each call sends a
+/// confirmed request and waits for the matching acknowledgement from the
+/// receiver thread.
+#[derive(Clone)]
+pub struct BacnetServices {
+    client: Arc<Mutex<BacnetClient>>,
+    transactions: Arc<TransactionManager>,
+}
+
+impl BacnetServices {
+    pub fn new(client: Arc<Mutex<BacnetClient>>, transactions: Arc<TransactionManager>) -> Self {
+        Self { client, transactions }
+    }
+
+    /// Sends a confirmed request built by `send` to `dest` and waits for the
+    /// reply, retrying on timeout. `send` returns the invoke ID it used.
+    async fn transact<F>(&self, dest: &BacnetAddress, mut send: F) -> Result<Vec<u8>>
+    where
+        F: FnMut(&mut BacnetClient) -> Result<u8>,
+    {
+        for attempt in 0..=APDU_RETRIES {
+            let (invoke_id, rx) = {
+                let mut client = self.client.lock().unwrap();
+                let invoke_id = send(&mut client)?;
+                (invoke_id, self.transactions.register(dest, invoke_id))
+            };
+            match tokio::time::timeout(APDU_TIMEOUT, rx).await {
+                Ok(Ok(result)) => return result,
+                Ok(Err(_)) => anyhow::bail!("Transaction {} dropped", invoke_id),
+                Err(_) => {
+                    self.transactions.cancel(dest, invoke_id);
+                    log::debug!("Transaction {} timed out (attempt {})", invoke_id, attempt + 1);
+                }
+            }
+        }
+        Err(NoResponse { attempts: APDU_RETRIES + 1 }.into())
+    }
+
+    /// Reads a property and returns its raw (application-tagged) value bytes.
+    pub async fn read_property(&self, dest: &BacnetAddress, obj_id: ObjectId, prop_id: u32, array_index: Option<u32>) -> Result<Vec<u8>> {
+        let ack = self
+            .transact(dest, |client| client.send_read_property(dest, obj_id, prop_id, array_index))
+            .await?;
+        let ack = discovery::decode_read_property_ack(&ack)?;
+        // A late reply to an earlier request can arrive on a reused invoke ID
+        if ack.object_id != obj_id || ack.property_identifier != prop_id || ack.property_array_index != array_index {
+            anyhow::bail!("ReadProperty-ACK for {} property {} does not match the request", ack.object_id, ack.property_identifier);
+        }
+        Ok(ack.property_value)
+    }
+
+    /// Reads several properties of several objects in one ReadPropertyMultiple.
+    /// Per-property errors are reported in the results rather than failing the call.
+    pub async fn read_property_multiple(&self, dest: &BacnetAddress, specs: &[ReadAccessSpec]) -> Result<Vec<ReadAccessResult>> {
+        let ack = self
+            .transact(dest, |client| client.send_read_property_multiple(dest, specs))
+            .await?;
+        rpm::decode_ack(&ack)
+    }
+
+    /// Writes a property, at `priority` if given. Fails with `RequestError` if
+    /// the device refuses the write.
+    pub async fn write_property(&self, dest: &BacnetAddress, obj_id: ObjectId, prop_id: u32, value: PropertyValue, priority: Option<u8>) -> Result<()> {
+        self.transact(dest, |client| client.send_write_property(dest, obj_id, prop_id, value.clone(), priority))
+            .await?;
+        Ok(())
+    }
+
+    /// Subscribes to unconfirmed COV notifications for `lifetime_secs`.
+    pub async fn subscribe_cov(&self, dest: &BacnetAddress, process_id: u32, obj_id: ObjectId, lifetime_secs: u32) -> Result<()> {
+        self.transact(dest, |client| client.send_subscribe_cov(dest, process_id, obj_id, Some(lifetime_secs)))
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use crate::core::bacnet::npdu::BacnetAddress;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+/// Outcome of a confirmed request as delivered by the receiver thread.
+pub type TransactionResult = Result<Vec<u8>>;
+
+struct Pending {
+    peer: BacnetAddress,
+    reply: oneshot::Sender<TransactionResult>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u8,
+    /// Pending requests by invoke ID; the same ID may be in flight to several peers
+    pending: HashMap<u8, Vec<Pending>>,
+}
+
+impl State {
+    fn in_flight(&self, peer: &BacnetAddress, invoke_id: u8) -> bool {
+        self.pending.get(&invoke_id).is_some_and(|p| p.iter().any(|p| p.peer == *peer))
+    }
+
+    fn take(&mut self, peer: &BacnetAddress, invoke_id: u8) -> Option<Pending> {
+        let pending = self.pending.get_mut(&invoke_id)?;
+        let index = pending.iter().position(|p| p.peer == *peer)?;
+        let entry = pending.swap_remove(index);
+        if pending.is_empty() {
+            self.pending.remove(&invoke_id);
+        }
+        Some(entry)
+    }
+}
+
+/// Tracks outstanding confirmed requests by peer and invoke ID so that
+/// acknowledgements picked up by the receiver thread can be handed back to
+/// the requesting task. Invoke IDs are only unique per peer, so a reply must
+/// come from the address the request was sent to.
+#[derive(Default)]
+pub struct TransactionManager {
+    state: Mutex<State>,
+}
+
+impl TransactionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Picks the next invoke ID that is not in flight to `peer`.
+    pub fn allocate(&self, peer: &BacnetAddress) -> Result<u8> {
+        let mut state = self.state.lock().unwrap();
+        let start = state.next_id;
+        let invoke_id = (0..=u8::MAX)
+            .map(|offset| start.wrapping_add(offset))
+            .find(|&id| !state.in_flight(peer, id))
+            .ok_or_else(|| anyhow::anyhow!("No free invoke ID for {}", peer))?;
+        state.next_id = invoke_id.wrapping_add(1);
+        Ok(invoke_id)
+    }
+
+    /// Registers an invoke ID. Must be called while the client lock taken for
+    /// sending is still held, so the reply cannot be received first.
+    pub fn register(&self, peer: &BacnetAddress, invoke_id: u8) -> oneshot::Receiver<TransactionResult> {
+        let (tx, rx) = oneshot::channel();
+        let mut state = self.state.lock().unwrap();
+        state.take(peer, invoke_id);
+        state.pending.entry(invoke_id).or_default().push(Pending { peer: peer.clone(), reply: tx });
+        rx
+    }
+
+    pub fn cancel(&self, peer: &BacnetAddress, invoke_id: u8) {
+        self.state.lock().unwrap().take(peer, invoke_id);
+    }
+
+    /// Completes a pending transaction. Returns false if nobody was waiting
+    /// for this invoke ID from `peer`, so the caller can fall back to
+    /// unsolicited handling.
+    pub fn resolve(&self, peer: &BacnetAddress, invoke_id: u8, result: TransactionResult) -> bool {
+        match self.state.lock().unwrap().take(peer, invoke_id) {
+            Some(pending) => {
+                let _ = pending.reply.send(result);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bacnet_rs::datalink::DataLinkAddress;
+
+    fn peer(host: u8) -> BacnetAddress {
+        BacnetAddress::local(DataLinkAddress::Ip(([10, 0, 0, host], 47808).into()))
+    }
+
+    #[test]
+    fn replies_are_matched_on_peer_and_invoke_id() {
+        let transactions = TransactionManager::new();
+        let (a, b) = (peer(1), peer(2));
+        let mut rx_a = transactions.register(&a, 7);
+        let mut rx_b = transactions.register(&b, 7);
+
+        assert!(transactions.resolve(&b, 7, Ok(vec![2])));
+        assert_eq!(rx_b.try_recv().unwrap().unwrap(), vec![2]);
+        assert!(rx_a.try_recv().is_err());
+
+        // A repeated or stray reply with the same ID is not a's answer
+        assert!(!transactions.resolve(&b, 7, Ok(vec![3])));
+        assert!(!transactions.resolve(&peer(3), 7, Ok(vec![3])));
+        assert!(rx_a.try_recv().is_err());
+
+        assert!(transactions.resolve(&a, 7, Ok(vec![1])));
+        assert_eq!(rx_a.try_recv().unwrap().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn allocation_skips_ids_in_flight_to_the_peer() {
+        let transactions = TransactionManager::new();
+        let (a, b) = (peer(1), peer(2));
+        let _rx = transactions.register(&a, 0);
+        let _rx = transactions.register(&a, 1);
+        assert_eq!(transactions.allocate(&a).unwrap(), 2);
+        // Other peers may reuse IDs in flight to a
+        for _ in 3..=u8::MAX {
+            transactions.allocate(&a).unwrap();
+        }
+        assert_eq!(transactions.allocate(&b).unwrap(), 0);
+        assert_eq!(transactions.allocate(&a).unwrap(), 2);
+    }
+
+    #[test]
+    fn allocation_fails_when_every_id_is_in_flight() {
+        let transactions = TransactionManager::new();
+        let a = peer(1);
+        let mut pending = Vec::new();
+        for _ in 0..=u8::MAX {
+            let invoke_id = transactions.allocate(&a).unwrap();
+            pending.push(transactions.register(&a, invoke_id));
+        }
+        assert!(transactions.allocate(&a).is_err());
+        assert!(transactions.allocate(&peer(2)).is_ok());
+    }
+}
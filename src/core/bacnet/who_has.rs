@@ -0,0 +1,74 @@
+//! Who-Has request encoding and I-Have decoding.
+
+use crate::common::types::{FoundObject, IHave, ObjectSearch, ObjectSearchResult};
+use crate::core::bacnet::codec::{self, BacnetValue};
+use anyhow::Result;
+use bacnet_rs::encoding;
+
+pub const I_HAVE: u8 = 1;
+pub const WHO_HAS: u8 = 7;
+
+/// A decoded I-Have.
+#[derive(Debug, Clone)]
+pub struct IHaveRequest {
+    pub device_id: u32,
+    pub object_type: u16,
+    pub instance: u32,
+    pub object_name: String,
+}
+
+pub fn encode_request(limits: Option<(u32, u32)>, search: &ObjectSearch) -> Result<Vec<u8>> {
+    let err = |e: encoding::EncodingError| anyhow::anyhow!(e.to_string());
+    let mut data = Vec::new();
+    if let Some((low, high)) = limits {
+        data.extend(encoding::encode_context_unsigned(low, 0).map_err(err)?);
+        data.extend(encoding::encode_context_unsigned(high, 1).map_err(err)?);
+    }
+    match search {
+        ObjectSearch::Id { object_type, instance } => {
+            data.extend(encoding::encode_context_object_id(*object_type, *instance, 2).map_err(err)?);
+        }
+        ObjectSearch::Name(name) => {
+            // Character set 0 (UTF-8) followed by the string
+            encoding::encode_context_tag(&mut data, 3, name.len() + 1).map_err(err)?;
+            data.push(0);
+            data.extend_from_slice(name.as_bytes());
+        }
+    }
+    Ok(data)
+}
+
+pub fn decode_i_have(data: &[u8]) -> Result<IHaveRequest> {
+    match codec::decode_application_values(data)?.as_slice() {
+        [BacnetValue::ObjectIdentifier { instance: device_id, .. }, BacnetValue::ObjectIdentifier { object_type, instance }, BacnetValue::CharacterString(object_name)] => {
+            Ok(IHaveRequest {
+                device_id: *device_id,
+                object_type: *object_type,
+                instance: *instance,
+                object_name: object_name.clone(),
+            })
+        }
+        other => anyhow::bail!("Malformed I-Have: {:?}", other),
+    }
+}
+
+/// Groups I-Have replies by device, dropping repeats.
+pub fn aggregate_i_haves(i_haves: &[IHave]) -> Vec<ObjectSearchResult> {
+    let mut results: Vec<ObjectSearchResult> = Vec::new();
+    for i_have in i_haves {
+        let object = FoundObject { object_type: i_have.object_type, instance: i_have.instance, name: i_have.object_name.clone() };
+        match results.iter_mut().find(|r| r.device_id == i_have.device_id && r.address == i_have.address) {
+            Some(result) if result.objects.contains(&object) => {}
+            Some(result) => result.objects.push(object),
+            None => results.push(ObjectSearchResult {
+                device_id: i_have.device_id,
+                address: i_have.address.clone(),
+                network: i_have.network,
+                mac: i_have.mac.clone(),
+                objects: vec![object],
+            }),
+        }
+    }
+    results.sort_by_key(|r| r.device_id);
+    results
+}
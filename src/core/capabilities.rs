@@ -0,0 +1,62 @@
+//! Per-device capability profiles, derived from what a device announced in
+//! its I-Am and Protocol_Services_Supported.
+
+use crate::common::types::{BacnetDevice, DeviceCapabilities};
+
+/// Largest APDU the gateway accepts; responses are never segmented.
+const GATEWAY_MAX_APDU: usize = 1476;
+
+/// Smallest Max_APDU_Length_Accepted allowed by the standard.
+const MIN_APDU: usize = 50;
+
+impl BacnetDevice {
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            services_known: self.protocol_services_supported.is_some(),
+            read_property_multiple: self.supports_service("ReadPropertyMultiple"),
+            subscribe_cov: self.supports_service("SubscribeCOV"),
+            segmentation: self.segmentation_supported,
+            max_apdu: self.max_apdu_length_accepted,
+        }
+    }
+}
+
+impl DeviceCapabilities {
+    /// Largest response the device can send us in one unsegmented APDU.
+    pub fn max_response(&self) -> usize {
+        (self.max_apdu as usize).clamp(MIN_APDU, GATEWAY_MAX_APDU)
+    }
+
+    /// Number of objects per ReadPropertyMultiple so that the ACK, at roughly
+    /// `bytes_per_object`, stays unsegmented.
+    pub fn objects_per_request(&self, bytes_per_object: usize) -> usize {
+        // ComplexAck header plus some slack for longer than estimated values
+        let budget = self.max_response().saturating_sub(16);
+        (budget / bytes_per_object.max(1)).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(max_apdu: u32) -> DeviceCapabilities {
+        DeviceCapabilities { max_apdu, ..Default::default() }
+    }
+
+    #[test]
+    fn chunks_fit_the_device_max_apdu() {
+        assert_eq!(caps(480).objects_per_request(40), 11);
+        assert_eq!(caps(1476).objects_per_request(40), 36);
+    }
+
+    #[test]
+    fn max_apdu_is_clamped_to_what_the_gateway_accepts() {
+        // Devices announcing more than 1476 still answer unsegmented within it
+        assert_eq!(caps(65535).objects_per_request(40), caps(1476).objects_per_request(40));
+        // A bogus tiny Max_APDU still allows one object per request
+        assert_eq!(caps(0).objects_per_request(40), 1);
+        assert_eq!(caps(480).objects_per_request(1000), 1);
+        assert_eq!(caps(480).objects_per_request(0), 464);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::common::types::{BacnetDevice, ConflictKind, DeviceConflict, Event, Segmentation};
+use crate::core::bacnet::codec;
+use crate::core::bacnet::npdu::{BacnetAddress, RoutingTable};
+use crate::core::bacnet::object_id::ObjectId;
+use crate::core::bacnet::properties;
+use crate::core::bacnet::services::BacnetServices;
+use crate::core::persistence::db::{now_ms, Database};
+use bacnet_rs::object::PropertyIdentifier;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex as TokioMutex};
+use tokio::time::Instant;
+
+/// Two I-Ams for one instance from different addresses (or for different
+/// instances from one address) within this window are a conflict rather than
+/// a device that moved or was renumbered.
+const CONFLICT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Known devices by instance, shared with tasks that need device capabilities.
+pub type DeviceMap = Arc<TokioMutex<HashMap<u32, BacnetDevice>>>;
+
+/// Keeps the device map up to date from I-Am events, persists it and the
+/// discovered object lists, and reads identity and capability properties from
+/// each device the first time it is seen. Emits `DeviceAdded` and
+/// `DeviceAddressChanged` by comparing each I-Am with the stored device, and
+/// `DeviceConflict` for duplicate instances and addresses. Of a duplicated
+/// instance the device map keeps the address seen first.
+pub struct DeviceManager {
+    services: BacnetServices,
+    db: Database,
+    event_tx: broadcast::Sender<Event>,
+    routing_table: RoutingTable,
+    devices: DeviceMap,
+    /// Devices enriched since startup
+    enriched: HashSet<u32>,
+    /// Latest I-Am per instance and address, with when it arrived
+    sightings: HashMap<u32, HashMap<String, (BacnetDevice, Instant)>>,
+    conflicts: HashMap<(ConflictKind, String), DeviceConflict>,
+}
+
+impl DeviceManager {
+    pub async fn new(services: BacnetServices, db: Database, event_tx: broadcast::Sender<Event>, routing_table: RoutingTable) -> Self {
+        let devices = match db.load_devices().await {
+            Ok(devices) => devices.into_iter().map(|d| (d.instance, d)).collect(),
+            Err(e) => {
+                log::error!("Failed to load persisted devices: {}", e);
+                HashMap::new()
+            }
+        };
+        Self {
+            services,
+            db,
+            event_tx,
+            routing_table,
+            devices: Arc::new(TokioMutex::new(devices)),
+            enriched: HashSet::new(),
+            sightings: HashMap::new(),
+            conflicts: HashMap::new(),
+        }
+    }
+
+    pub fn devices(&self) -> DeviceMap {
+        Arc::clone(&self.devices)
+    }
+
+    pub async fn run(mut self, mut event_rx: broadcast::Receiver<Event>) {
+        loop {
+            match event_rx.recv().await {
+                Ok(Event::DeviceDiscovered(i_am)) => self.on_i_am(i_am).await,
+                Ok(Event::DeviceRemoved { device_id }) => {
+                    // Re-read the identity should the device come back
+                    self.enriched.remove(&device_id);
+                }
+                Ok(Event::DeviceObjectsDiscovered { device_id, objects }) => {
+                    if let Err(e) = self.db.save_objects(device_id, &objects).await {
+                        log::error!("Failed to persist objects of device {}: {}", device_id, e);
+                    }
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("Device manager lagged, skipped {} events", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    async fn on_i_am(&mut self, i_am: BacnetDevice) {
+        let duplicate_instance = self.check_conflicts(&i_am);
+        let (device, old_route) = {
+            let mut devices = self.devices.lock().await;
+            match devices.get_mut(&i_am.instance) {
+                Some(device) if duplicate_instance && device.route() != i_am.route() => {
+                    // Keep the address seen first; the duplicate is kept in the conflict
+                    return;
+                }
+                Some(device) => {
+                    let old_route = device.route();
+                    device.update_from_i_am(&i_am);
+                    (device.clone(), Some(old_route))
+                }
+                None => {
+                    devices.insert(i_am.instance, i_am.clone());
+                    (i_am.clone(), None)
+                }
+            }
+        };
+        match old_route {
+            None => {
+                log::info!("New device {} at {}", device.instance, device.route());
+                let _ = self.event_tx.send(Event::DeviceAdded(device.clone()));
+            }
+            Some(old_address) if old_address != device.route() => {
+                log::info!("Device {} moved from {} to {}", device.instance, old_address, device.route());
+                let _ = self.event_tx.send(Event::DeviceAddressChanged {
+                    device_id: device.instance,
+                    old_address,
+                    new_address: device.route(),
+                });
+                let _ = self.event_tx.send(Event::DeviceUpdated(device.clone()));
+            }
+            Some(_) => {}
+        }
+        if let Err(e) = self.db.upsert_device(&device).await {
+            log::error!("Failed to persist device {}: {}", device.instance, e);
+        }
+
+        if self.enriched.insert(device.instance) {
+            let services = self.services.clone();
+            let db = self.db.clone();
+            let event_tx = self.event_tx.clone();
+            let devices = Arc::clone(&self.devices);
+            let dest = BacnetAddress::resolve(&device.address, device.network, device.mac.as_deref(), &self.routing_table);
+            tokio::spawn(async move {
+                let Some(dest) = dest else { return };
+                let info = read_identity(&services, &dest, device.instance).await;
+                let updated = {
+                    let mut devices = devices.lock().await;
+                    let Some(entry) = devices.get_mut(&device.instance) else { return };
+                    info.apply(entry);
+                    entry.clone()
+                };
+                log::info!("Enriched device {}: {:?}", updated.instance, updated);
+                if let Err(e) = db.upsert_device(&updated).await {
+                    log::error!("Failed to persist device {}: {}", updated.instance, e);
+                }
+                let _ = event_tx.send(Event::DeviceUpdated(updated));
+            });
+        }
+    }
+
+    /// Records the I-Am and reports duplicate instances and addresses.
+    /// Returns true if the instance currently answers from several addresses.
+    fn check_conflicts(&mut self, i_am: &BacnetDevice) -> bool {
+        let now = Instant::now();
+        let route = i_am.route();
+        let sightings = self.sightings.entry(i_am.instance).or_default();
+        sightings.retain(|_, (_, at)| now.duration_since(*at) < CONFLICT_WINDOW);
+        sightings.insert(route.clone(), (i_am.clone(), now));
+
+        let duplicate_instance = sightings.len() > 1;
+        if duplicate_instance {
+            let devices = sightings.values().map(|(device, _)| device.clone()).collect();
+            self.report_conflict(ConflictKind::DuplicateInstance, i_am.instance.to_string(), devices);
+        }
+
+        let at_route: Vec<BacnetDevice> = self
+            .sightings
+            .values()
+            .filter_map(|by_route| by_route.get(&route))
+            .filter(|(_, at)| now.duration_since(*at) < CONFLICT_WINDOW)
+            .map(|(device, _)| device.clone())
+            .collect();
+        if at_route.len() > 1 {
+            self.report_conflict(ConflictKind::DuplicateAddress, route, at_route);
+        }
+        duplicate_instance
+    }
+
+    /// Emits `DeviceConflict` when a conflict is new or gains an entry.
+    fn report_conflict(&mut self, kind: ConflictKind, key: String, devices: Vec<BacnetDevice>) {
+        let now = now_ms();
+        let conflict = self.conflicts.entry((kind, key.clone())).or_insert_with(|| DeviceConflict {
+            kind,
+            key: key.clone(),
+            devices: Vec::new(),
+            first_seen_ms: now,
+            last_seen_ms: now,
+        });
+        let mut grew = false;
+        for device in devices {
+            if !conflict.devices.iter().any(|d| d.instance == device.instance && d.route() == device.route()) {
+                conflict.devices.push(device);
+                grew = true;
+            }
+        }
+        conflict.last_seen_ms = now;
+        if grew {
+            let entries: Vec<String> = conflict.devices.iter().map(|d| format!("{}@{}", d.instance, d.route())).collect();
+            log::warn!("{:?} conflict on {}: {}", kind, key, entries.join(", "));
+            let _ = self.event_tx.send(Event::DeviceConflict(conflict.clone()));
+        }
+    }
+}
+
+/// Identity properties read from a Device object. Unreadable ones stay `None`.
+#[derive(Debug, Default)]
+struct DeviceIdentity {
+    object_name: Option<String>,
+    vendor_name: Option<String>,
+    model_name: Option<String>,
+    firmware_revision: Option<String>,
+    application_software_version: Option<String>,
+    location: Option<String>,
+    description: Option<String>,
+    protocol_services_supported: Option<Vec<String>>,
+    max_apdu_length_accepted: Option<u32>,
+    segmentation_supported: Option<Segmentation>,
+}
+
+impl DeviceIdentity {
+    fn apply(self, device: &mut BacnetDevice) {
+        if let Some(name) = self.object_name {
+            device.name = name;
+        }
+        // The vendor ID table is authoritative; only fall back to the device's own claim
+        if device.vendor_name.is_none() {
+            device.vendor_name = self.vendor_name;
+        }
+        device.model_name = self.model_name.or(device.model_name.take());
+        device.firmware_revision = self.firmware_revision.or(device.firmware_revision.take());
+        device.application_software_version = self.application_software_version.or(device.application_software_version.take());
+        device.location = self.location.or(device.location.take());
+        device.description = self.description.or(device.description.take());
+        device.protocol_services_supported = self.protocol_services_supported.or(device.protocol_services_supported.take());
+        // The Device object is authoritative over what I-Am advertised
+        if let Some(max_apdu) = self.max_apdu_length_accepted {
+            device.max_apdu_length_accepted = max_apdu;
+        }
+        if let Some(segmentation) = self.segmentation_supported {
+            device.segmentation_supported = segmentation;
+        }
+    }
+}
+
+async fn read_identity(services: &BacnetServices, dest: &BacnetAddress, instance: u32) -> DeviceIdentity {
+    let device_id = ObjectId::device(instance);
+    let read_string = |prop_id: u32| async move {
+        match services.read_property(dest, device_id, prop_id, None).await {
+            Ok(data) => match codec::decode_application_value(&data) {
+                Ok((value, _)) => value.as_str().map(str::to_string),
+                Err(e) => {
+                    log::debug!("Device {} property {} undecodable: {}", instance, prop_id, e);
+                    None
+                }
+            },
+            Err(e) => {
+                log::debug!("Device {} property {} unreadable: {}", instance, prop_id, e);
+                None
+            }
+        }
+    };
+
+    let protocol_services_supported = match services.read_property(dest, device_id, properties::PROTOCOL_SERVICES_SUPPORTED, None).await {
+        Ok(data) => codec::decode_application_value(&data)
+            .ok()
+            .and_then(|(value, _)| value.as_bits().map(properties::supported_service_names)),
+        Err(e) => {
+            log::debug!("Device {} Protocol_Services_Supported unreadable: {}", instance, e);
+            None
+        }
+    };
+
+    let read_unsigned = |prop_id: u32| async move {
+        match services.read_property(dest, device_id, prop_id, None).await {
+            Ok(data) => match codec::decode_application_value(&data) {
+                Ok((codec::BacnetValue::Unsigned(v), _)) => Some(v as u32),
+                Ok((codec::BacnetValue::Enumerated(v), _)) => Some(v),
+                _ => None,
+            },
+            Err(e) => {
+                log::debug!("Device {} property {} unreadable: {}", instance, prop_id, e);
+                None
+            }
+        }
+    };
+
+    DeviceIdentity {
+        object_name: read_string(PropertyIdentifier::ObjectName as u32).await,
+        vendor_name: read_string(PropertyIdentifier::VendorName as u32).await,
+        model_name: read_string(PropertyIdentifier::ModelName as u32).await,
+        firmware_revision: read_string(PropertyIdentifier::FirmwareRevision as u32).await,
+        application_software_version: read_string(PropertyIdentifier::ApplicationSoftwareVersion as u32).await,
+        location: read_string(properties::LOCATION).await,
+        description: read_string(properties::DESCRIPTION).await,
+        protocol_services_supported,
+        max_apdu_length_accepted: read_unsigned(PropertyIdentifier::MaxApduLengthAccepted as u32).await,
+        segmentation_supported: read_unsigned(PropertyIdentifier::SegmentationSupported as u32).await.map(Segmentation::from_u32),
+    }
+}
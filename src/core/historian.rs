@@ -0,0 +1,124 @@
+use crate::common::config::HistoryConfig;
+use crate::common::types::{object_id_string, Event, HistorySample, PointConfig};
+use crate::core::persistence::db::{now_ms, Database};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify};
+
+/// Buffered samples that trigger a flush before the flush interval
+const FLUSH_SAMPLES: usize = 2000;
+
+const HOUR_MS: i64 = 3_600_000;
+const DAY_MS: i64 = 24 * HOUR_MS;
+
+/// Records point updates of mapped points in the database. Samples are
+/// buffered and inserted in batches, every `flush_interval_ms` or sooner
+/// after `FLUSH_SAMPLES`. At each maintenance, whole buckets of raw samples
+/// past a point's raw retention are downsampled into count/min/max/avg/last
+/// buckets without bad-quality samples, and everything past its retention
+/// is deleted. Per-point `history` policies override the config defaults.
+pub struct Historian {
+    pub db: Database,
+    pub config: HistoryConfig,
+    /// Notified when the point map changes
+    pub reload: Arc<Notify>,
+}
+
+impl Historian {
+    pub async fn run(self, mut event_rx: broadcast::Receiver<Event>) {
+        let mut points = self.load_points().await;
+        let mut buffer = Vec::new();
+        let mut flush = tokio::time::interval(Duration::from_millis(self.config.flush_interval_ms.max(1)));
+        let maintenance_interval = Duration::from_secs(self.config.maintenance_interval_secs.max(1));
+        let mut maintenance = tokio::time::interval_at(tokio::time::Instant::now() + maintenance_interval, maintenance_interval);
+        maintenance.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                event = event_rx.recv() => match event {
+                    Ok(Event::PointUpdate { device_id, object_id, value, quality, .. }) => {
+                        if let Some(point_id) = points.get(&(device_id, object_id)) {
+                            buffer.push(HistorySample { point_id: *point_id, timestamp_ms: now_ms(), value, quality });
+                            if buffer.len() >= FLUSH_SAMPLES {
+                                self.flush(&mut buffer);
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(missed)) => log::warn!("Historian missed {} events", missed),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = flush.tick() => self.flush(&mut buffer),
+                _ = maintenance.tick() => self.maintain().await,
+                _ = self.reload.notified() => points = self.load_points().await,
+            }
+        }
+        self.flush(&mut buffer);
+    }
+
+    /// IDs of the points with history enabled, by device and object.
+    async fn load_points(&self) -> HashMap<(u32, String), i64> {
+        match self.db.load_points().await {
+            Ok(points) => points
+                .into_iter()
+                .filter(|p| p.history.enabled)
+                .map(|p| ((p.device_id, object_id_string(p.object_type, p.instance)), p.id))
+                .collect(),
+            Err(e) => {
+                log::error!("Failed to load point map: {}", e);
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Inserts the buffered samples in the background, so a slow insert does
+    /// not hold up the event stream.
+    fn flush(&self, buffer: &mut Vec<HistorySample>) {
+        if buffer.is_empty() {
+            return;
+        }
+        let samples = std::mem::take(buffer);
+        let db = self.db.clone();
+        tokio::spawn(async move {
+            if let Err(e) = db.insert_history(&samples).await {
+                log::error!("Failed to store {} history samples: {}", samples.len(), e);
+            }
+        });
+    }
+
+    /// Downsamples and expires the history of every point.
+    async fn maintain(&self) {
+        let points = match self.db.load_points().await {
+            Ok(points) => points,
+            Err(e) => {
+                log::error!("Failed to load point map: {}", e);
+                return;
+            }
+        };
+        let now = now_ms();
+        let bucket_ms = self.config.bucket_secs.max(1) as i64 * 1000;
+        let mut downsampled = 0;
+        for point in &points {
+            let (raw_retention_ms, retention_ms) = self.retention(point);
+            // Only whole buckets are downsampled
+            let raw_cutoff = (now - raw_retention_ms) / bucket_ms * bucket_ms;
+            match self.db.downsample_history(point.id, raw_cutoff, bucket_ms).await {
+                Ok(moved) => downsampled += moved,
+                Err(e) => log::error!("Failed to downsample history of point {}: {}", point.id, e),
+            }
+            if let Err(e) = self.db.expire_history(point.id, now - retention_ms).await {
+                log::error!("Failed to expire history of point {}: {}", point.id, e);
+            }
+        }
+        if downsampled > 0 {
+            log::info!("Downsampled {} history samples", downsampled);
+        }
+    }
+
+    /// Raw and total retention of a point in milliseconds.
+    fn retention(&self, point: &PointConfig) -> (i64, i64) {
+        let raw_hours = point.history.raw_retention_hours.unwrap_or(self.config.raw_retention_hours);
+        let days = point.history.retention_days.unwrap_or(self.config.retention_days);
+        (raw_hours as i64 * HOUR_MS, days as i64 * DAY_MS)
+    }
+}
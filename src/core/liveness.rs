@@ -0,0 +1,182 @@
+use crate::common::config::LivenessConfig;
+use crate::common::types::{DeviceStatus, Event};
+use crate::core::bacnet::client::BacnetClient;
+use crate::core::bacnet::npdu::{BacnetAddress, RoutingTable};
+use crate::core::bacnet::object_id::ObjectId;
+use crate::core::bacnet::services::{BacnetServices, NoResponse};
+use crate::core::devices::DeviceMap;
+use crate::core::persistence::db::now_ms;
+use bacnet_rs::object::PropertyIdentifier;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+const TICK: Duration = Duration::from_secs(1);
+
+struct Health {
+    /// `None` until the first answer or the failure threshold
+    online: Option<bool>,
+    last_seen_ms: Option<i64>,
+    consecutive_failures: u32,
+    /// When the device is next probed
+    next_check: Instant,
+    backoff: Duration,
+    probing: bool,
+}
+
+/// Tracks the last successful communication and consecutive unanswered
+/// requests per device, and marks devices offline after a threshold.
+/// Devices not heard from within the check interval are probed; offline
+/// devices are probed with exponential backoff. Cheap to clone.
+#[derive(Clone)]
+pub struct Liveness {
+    health: Arc<Mutex<HashMap<u32, Health>>>,
+    event_tx: broadcast::Sender<Event>,
+    config: LivenessConfig,
+}
+
+impl Liveness {
+    pub fn new(event_tx: broadcast::Sender<Event>, config: LivenessConfig) -> Self {
+        Self { health: Arc::new(Mutex::new(HashMap::new())), event_tx, config }
+    }
+
+    /// Whether requests to the device are worth sending. Devices never heard
+    /// from count as online.
+    pub fn is_online(&self, device_id: u32) -> bool {
+        self.health.lock().unwrap().get(&device_id).and_then(|h| h.online) != Some(false)
+    }
+
+    pub fn record_success(&self, device_id: u32) {
+        let mut health = self.health.lock().unwrap();
+        let h = health.entry(device_id).or_insert_with(|| self.new_health());
+        h.consecutive_failures = 0;
+        h.last_seen_ms = Some(now_ms());
+        h.next_check = Instant::now() + Duration::from_secs(self.config.check_interval_secs);
+        h.backoff = self.min_backoff();
+        if h.online != Some(true) {
+            h.online = Some(true);
+            log::info!("Device {} is online", device_id);
+            let _ = self.event_tx.send(Event::DeviceStatusChanged(status(device_id, h)));
+        }
+    }
+
+    /// Records a request the device did not answer. Errors returned by the
+    /// device are not failures; use `record_result` to tell them apart.
+    pub fn record_failure(&self, device_id: u32) {
+        let mut health = self.health.lock().unwrap();
+        let h = health.entry(device_id).or_insert_with(|| self.new_health());
+        h.consecutive_failures += 1;
+        if h.online == Some(false) {
+            h.next_check = Instant::now() + h.backoff;
+            h.backoff = (h.backoff * 2).min(Duration::from_secs(self.config.probe_backoff_max_secs));
+        } else if h.consecutive_failures >= self.config.offline_after_failures.max(1) {
+            h.online = Some(false);
+            h.next_check = Instant::now() + h.backoff;
+            log::warn!("Device {} is offline after {} unanswered requests", device_id, h.consecutive_failures);
+            let _ = self.event_tx.send(Event::DeviceStatusChanged(status(device_id, h)));
+        } else {
+            h.next_check = Instant::now();
+        }
+    }
+
+    /// Records the outcome of a confirmed request: any answer, including an
+    /// error, is a success.
+    pub fn record_result<T>(&self, device_id: u32, result: &anyhow::Result<T>) {
+        match result {
+            Err(e) if e.is::<NoResponse>() => self.record_failure(device_id),
+            _ => self.record_success(device_id),
+        }
+    }
+
+    fn new_health(&self) -> Health {
+        Health {
+            online: None,
+            last_seen_ms: None,
+            consecutive_failures: 0,
+            next_check: Instant::now(),
+            backoff: self.min_backoff(),
+            probing: false,
+        }
+    }
+
+    fn min_backoff(&self) -> Duration {
+        Duration::from_secs(self.config.probe_backoff_min_secs.max(1))
+    }
+
+    /// Follows I-Am and removal events and probes devices that are due.
+    pub async fn run(self, services: BacnetServices, client: Arc<Mutex<BacnetClient>>, devices: DeviceMap, routing_table: RoutingTable, mut event_rx: broadcast::Receiver<Event>) {
+        let mut tick = tokio::time::interval(TICK);
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                event = event_rx.recv() => match event {
+                    Ok(Event::DeviceDiscovered(device)) => self.record_success(device.instance),
+                    Ok(Event::DeviceRemoved { device_id }) => {
+                        self.health.lock().unwrap().remove(&device_id);
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(n)) => log::warn!("Liveness monitor lagged, skipped {} events", n),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = tick.tick() => {
+                    for (device_id, dest) in self.due_devices(&devices, &routing_table).await {
+                        let liveness = self.clone();
+                        let services = services.clone();
+                        let client = Arc::clone(&client);
+                        tokio::spawn(async move {
+                            if let Some(dest) = dest {
+                                liveness.probe(&services, &client, &dest, device_id).await;
+                            }
+                            if let Some(h) = liveness.health.lock().unwrap().get_mut(&device_id) {
+                                h.probing = false;
+                            }
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Devices whose check is due, marked as being probed.
+    async fn due_devices(&self, devices: &DeviceMap, routing_table: &RoutingTable) -> Vec<(u32, Option<BacnetAddress>)> {
+        let devices = devices.lock().await;
+        let mut health = self.health.lock().unwrap();
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for device in devices.values() {
+            let h = health.entry(device.instance).or_insert_with(|| self.new_health());
+            if !h.probing && h.next_check <= now {
+                h.probing = true;
+                let dest = BacnetAddress::resolve(&device.address, device.network, device.mac.as_deref(), routing_table);
+                due.push((device.instance, dest));
+            }
+        }
+        due
+    }
+
+    /// Sends a targeted Who-Is (to devices on the local network) and reads
+    /// Object_Name. An I-Am reply is recorded through the event bus.
+    async fn probe(&self, services: &BacnetServices, client: &Arc<Mutex<BacnetClient>>, dest: &BacnetAddress, device_id: u32) {
+        if dest.remote.is_none() {
+            let sent = client.lock().unwrap().send_who_is(Some(device_id), Some(device_id), Some(dest.datalink.clone()));
+            if let Err(e) = sent {
+                log::debug!("Probe Who-Is to device {} failed: {}", device_id, e);
+            }
+        }
+        let object_id = ObjectId::device(device_id);
+        let result = services.read_property(dest, object_id, PropertyIdentifier::ObjectName as u32, None).await;
+        log::debug!("Liveness probe of device {}: {}", device_id, if result.is_ok() { "answered" } else { "failed" });
+        self.record_result(device_id, &result);
+    }
+}
+
+fn status(device_id: u32, h: &Health) -> DeviceStatus {
+    DeviceStatus {
+        device_id,
+        online: h.online.unwrap_or(true),
+        last_seen_ms: h.last_seen_ms,
+        consecutive_failures: h.consecutive_failures,
+    }
+}
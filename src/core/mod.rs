@@ -1,38 +1,110 @@
+pub mod acquisition;
+pub mod audit;
 pub mod bacnet;
+pub mod capabilities;
+pub mod devices;
+pub mod historian;
+pub mod liveness;
 pub mod mqtt;
 pub mod network;
+pub mod objects;
+pub mod overrides;
 pub mod persistence;
+pub mod readback;
+pub mod rediscovery;
+pub mod sweep;
+pub mod timed_overrides;
+pub mod write_policy;
 
-use crate::common::types::{Command, Event};
+use crate::common::config::GatewayConfig;
+use crate::common::types::{object_id_string, BacnetRouter, Command, Event, IHave, PriorityArrayReading, PropertyReading, RequestError, RequestFailure, TimedOverride, WriteOutcome, MAX_DEVICE_INSTANCE};
+use crate::core::acquisition::{Acquisition, COV_PROCESS_ID};
 use crate::core::bacnet::client::BacnetClient;
+use crate::core::bacnet::cov;
 use crate::core::bacnet::discovery;
+use crate::core::bacnet::errors;
+use crate::core::bacnet::properties;
+use crate::core::bacnet::property_value;
+use crate::core::bacnet::who_has;
+use crate::core::bacnet::npdu::{self, BacnetAddress, NpduPayload, RoutingTable};
+use crate::core::bacnet::object_id::ObjectId;
+use crate::core::bacnet::services::BacnetServices;
+use crate::core::bacnet::transaction::TransactionManager;
+use crate::core::devices::{DeviceManager, DeviceMap};
+use crate::core::historian::Historian;
+use crate::core::liveness::Liveness;
+use crate::core::overrides::{GatewayCommands, OverrideMonitor};
+use crate::core::persistence::db::{now_ms, Database};
+use crate::core::rediscovery::Rediscovery;
+use crate::core::sweep::Sweep;
+use crate::core::timed_overrides::TimedOverrides;
 use crate::core::network::interface;
 use tokio::sync::{mpsc, broadcast};
 use anyhow::Result;
 use bacnet_rs::app::Apdu;
-use bacnet_rs::object::{PropertyIdentifier, ObjectIdentifier, ObjectType, PropertyValue};
+use bacnet_rs::object::PropertyIdentifier;
 use bacnet_rs::datalink::DataLink;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 pub struct Core {
     cmd_rx: mpsc::Receiver<Command>,
     event_tx: broadcast::Sender<Event>,
     bacnet_client: Option<Arc<Mutex<BacnetClient>>>,
+    services: Option<BacnetServices>,
+    devices: Option<DeviceMap>,
+    /// Wakes the acquisition engine to reload the point map
+    points_reload: Option<Arc<tokio::sync::Notify>>,
+    /// Wakes the historian to reload the point map
+    history_reload: Arc<tokio::sync::Notify>,
+    broadcast_addr: Option<std::net::IpAddr>,
+    routing_table: RoutingTable,
+    /// Priorities commanded by the gateway's own writes
+    commands: GatewayCommands,
+    /// Relinquishes timed overrides; set once an interface is bound
+    timed_overrides: Option<TimedOverrides>,
+    db: Database,
+    config: GatewayConfig,
+    /// Cancel flag of the running Who-Is sweep
+    sweep_cancel: Option<Arc<std::sync::atomic::AtomicBool>>,
     pub shutdown: Arc<std::sync::atomic::AtomicBool>,
+    /// Gateway-wide write switch, shared with the web UI
+    pub read_only: Arc<std::sync::atomic::AtomicBool>,
 }
 
 impl Core {
-    pub fn new(cmd_rx: mpsc::Receiver<Command>, event_tx: broadcast::Sender<Event>) -> Self {
+    pub fn new(cmd_rx: mpsc::Receiver<Command>, event_tx: broadcast::Sender<Event>, db: Database, config: GatewayConfig) -> Self {
+        let read_only = Arc::new(std::sync::atomic::AtomicBool::new(config.writes.read_only));
         Self { 
             cmd_rx, 
             event_tx,
             bacnet_client: None,
+            services: None,
+            devices: None,
+            points_reload: None,
+            history_reload: Arc::new(tokio::sync::Notify::new()),
+            broadcast_addr: None,
+            routing_table: Arc::new(Mutex::new(HashMap::new())),
+            commands: GatewayCommands::default(),
+            timed_overrides: None,
+            db,
+            config,
+            sweep_cancel: None,
             shutdown: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            read_only,
         }
     }
 
     pub async fn run(&mut self) -> Result<()> {
         log::info!("Starting Core Engine...");
+        if self.config.history.enabled {
+            let historian = Historian {
+                db: self.db.clone(),
+                config: self.config.history.clone(),
+                reload: Arc::clone(&self.history_reload),
+            };
+            tokio::spawn(historian.run(self.event_tx.subscribe()));
+        }
 
         loop {
             if self.shutdown.load(std::sync::atomic::Ordering::SeqCst) {
@@ -52,6 +124,41 @@ impl Core {
                         Command::StartDiscovery => {
                             self.start_discovery().await?;
                         }
+                        Command::DiscoverRange { low, high } => {
+                            if low > high || high > MAX_DEVICE_INSTANCE {
+                                self.event_tx.send(Event::StatusMessage(format!("Error: invalid instance range {}..={}", low, high)))?;
+                            } else if let Some(client_mutex) = &self.bacnet_client {
+                                let mut client = client_mutex.lock().unwrap();
+                                match client.broadcast_who_is(Some(low), Some(high), self.broadcast_addr) {
+                                    Ok(()) => {
+                                        let _ = self.event_tx.send(Event::StatusMessage(format!("Who-Is sent for instances {}..={}", low, high)));
+                                    }
+                                    Err(e) => log::error!("Ranged Who-Is failed: {}", e),
+                                }
+                            } else {
+                                self.event_tx.send(Event::StatusMessage("Error: No interface bound".to_string()))?;
+                            }
+                        }
+                        Command::SweepDiscovery { low, high, chunk_size, chunk_delay_ms } => {
+                            self.start_sweep(low, high, chunk_size, chunk_delay_ms)?;
+                        }
+                        Command::StopDiscovery => {
+                            if let Some(cancel) = self.sweep_cancel.take() {
+                                cancel.store(true, std::sync::atomic::Ordering::SeqCst);
+                            }
+                        }
+                        Command::DiscoverRouters => {
+                            if let Some(client_mutex) = &self.bacnet_client {
+                                let mut client = client_mutex.lock().unwrap();
+                                if let Err(e) = client.send_who_is_router_to_network(None) {
+                                    log::error!("Who-Is-Router-To-Network failed: {}", e);
+                                } else {
+                                    let _ = self.event_tx.send(Event::StatusMessage("Who-Is-Router-To-Network sent".to_string()));
+                                }
+                            } else {
+                                self.event_tx.send(Event::StatusMessage("Error: No interface bound".to_string()))?;
+                            }
+                        }
                         Command::Ping { interface, target } => {
                             if !interface.is_empty() {
                                 self.bind_interface(&interface).await?;
@@ -74,53 +181,289 @@ impl Core {
                                 });
                             }
                         }
-                        Command::DiscoverObjects { interface, device_id, address } => {
+                        Command::DiscoverObjects { interface, device_id, address, network, mac } => {
                             if !interface.is_empty() {
                                 self.bind_interface(&interface).await?;
                             }
-                            if let Some(client_mutex) = &self.bacnet_client {
-                                let client_arc = Arc::clone(client_mutex);
+                            if let (Some(services), Some(devices)) = (&self.services, &self.devices) {
+                                let services = services.clone();
+                                let devices = Arc::clone(devices);
                                 let event_tx = self.event_tx.clone();
+                                let dest = self.resolve_address(&address, network, mac.as_deref());
                                 tokio::spawn(async move {
-                                    let mut client = client_arc.lock().unwrap();
-                                    if let Ok(target_addr) = address.parse::<std::net::SocketAddr>() {
-                                        let dest = bacnet_rs::datalink::DataLinkAddress::Ip(target_addr);
-                                        let obj_id = ObjectIdentifier::new(ObjectType::Device, device_id);
-                                        if let Err(e) = client.send_read_property(&dest, obj_id, PropertyIdentifier::ObjectList as u32) {
-                                            log::error!("ReadProperty failed: {}", e);
-                                        } else {
-                                            let _ = event_tx.send(Event::StatusMessage(format!("Requested object list from device {}", device_id)));
+                                    let Some(dest) = dest else { return };
+                                    let caps = devices
+                                        .lock()
+                                        .await
+                                        .get(&device_id)
+                                        .map(|d| d.capabilities())
+                                        .unwrap_or_default();
+                                    let _ = event_tx.send(Event::StatusMessage(format!("Reading objects of device {}", device_id)));
+                                    match objects::discover_objects(&services, &dest, device_id, &caps).await {
+                                        Ok(objects) => {
+                                            log::info!("Discovered {} objects on device {}", objects.len(), device_id);
+                                            let _ = event_tx.send(Event::DeviceObjectsDiscovered { device_id, objects });
+                                        }
+                                        Err(e) => {
+                                            log::error!("Object discovery on device {} failed: {}", device_id, e);
+                                            let _ = event_tx.send(Event::StatusMessage(format!("Object discovery on device {} failed: {}", device_id, e)));
                                         }
                                     }
                                 });
                             }
                         }
-                        Command::WriteProperty { device_id, address, object_type, instance, property, value } => {
-                            if let Some(client_mutex) = &self.bacnet_client {
-                                let client_arc = Arc::clone(client_mutex);
+                        Command::WriteProperty { device_id, address, network, mac, object_type, instance, property, value, priority, duration_secs, verify, tolerance, origin } => {
+                            if let (Some(services), Some(devices)) = (&self.services, &self.devices) {
+                                let services = services.clone();
+                                let devices = Arc::clone(devices);
                                 let event_tx = self.event_tx.clone();
+                                let commands = self.commands.clone();
+                                let db = self.db.clone();
+                                let read_only = self.read_only.load(std::sync::atomic::Ordering::SeqCst);
+                                let dest = self.resolve_address(&address, network, mac.as_deref());
                                 tokio::spawn(async move {
-                                    let mut client = client_arc.lock().unwrap();
-                                    if let Ok(target_addr) = address.parse::<std::net::SocketAddr>() {
-                                        let dest = bacnet_rs::datalink::DataLinkAddress::Ip(target_addr);
-                                        let obj_id = ObjectIdentifier::new(ObjectType::try_from(object_type).unwrap_or(ObjectType::AnalogValue), instance);
-                                        
-                                        // Attempt to parse value as float for now
-                                        if let Ok(val) = value.parse::<f32>() {
-                                            if let Err(e) = client.send_write_property(&dest, obj_id, property, PropertyValue::Real(val)) {
-                                                log::error!("WriteProperty failed: {}", e);
-                                            } else {
-                                                let _ = event_tx.send(Event::StatusMessage(format!("Sent WriteProperty to {}: {} = {}", device_id, obj_id.instance, val)));
+                                    let obj_id = ObjectId::new(object_type, instance);
+                                    let object_id = object_id_string(object_type, instance);
+                                    let commanded = property == PropertyIdentifier::PresentValue as u32 && obj_id.is_commandable();
+                                    let point = db.find_point(device_id, object_type, instance).await.unwrap_or_else(|e| {
+                                        log::error!("Failed to look up point of device {} {}: {}", device_id, obj_id, e);
+                                        None
+                                    });
+                                    let device = devices.lock().await.get(&device_id).cloned();
+                                    let rejection = write_policy::authorize(
+                                        read_only,
+                                        device.as_ref(),
+                                        (&address, network, mac.as_deref()),
+                                        point.as_ref(),
+                                        property,
+                                        priority.unwrap_or(16),
+                                        value.parse().ok(),
+                                    )
+                                    .err();
+                                    let mut latency_ms = None;
+                                    let mut verification = None;
+                                    let encoded = value
+                                        .parse::<f32>()
+                                        .map_err(|_| anyhow::anyhow!("Failed to parse write value: {}", value))
+                                        .and_then(|val| obj_id.write_value(property, val));
+                                    let result = match (dest, encoded) {
+                                        _ if priority.is_some_and(|p| !(1..=16).contains(&p)) => Err(anyhow::anyhow!("Invalid priority {:?}", priority)),
+                                        _ if rejection.is_some() => Err(anyhow::anyhow!("Write rejected: {}", rejection.as_deref().unwrap_or_default())),
+                                        _ if duration_secs.is_some() && (priority.is_none() || !commanded) => {
+                                            Err(anyhow::anyhow!("Timed writes need a priority and a commandable Present_Value"))
+                                        }
+                                        (Some(dest), Ok(encoded)) => {
+                                            let started = std::time::Instant::now();
+                                            let result = services.write_property(&dest, obj_id, property, encoded.clone(), priority).await;
+                                            latency_ms = Some(started.elapsed().as_millis() as u64);
+                                            if verify && result.is_ok() {
+                                                let tolerance = tolerance.unwrap_or(readback::DEFAULT_TOLERANCE);
+                                                verification = Some(readback::verify_write(&services, &dest, obj_id, property, priority, &encoded, tolerance).await);
+                                            }
+                                            result
+                                        }
+                                        (None, _) => Err(anyhow::anyhow!("Cannot route to {}", address)),
+                                        // Only numeric values are written for now
+                                        (_, Err(e)) => Err(e),
+                                    };
+                                    let mut expires_ms = None;
+                                    if result.is_ok() && commanded {
+                                        // Without a priority the device applies its default, normally 16
+                                        let slot = priority.unwrap_or(16);
+                                        commands.record(device_id, obj_id, slot, false);
+                                        if let Some(duration_secs) = duration_secs {
+                                            let created_ms = now_ms();
+                                            let timed = TimedOverride {
+                                                id: 0,
+                                                device_id,
+                                                object_type,
+                                                instance,
+                                                priority: slot,
+                                                value: value.clone(),
+                                                created_ms,
+                                                expires_ms: created_ms.saturating_add(duration_secs.saturating_mul(1000) as i64),
+                                            };
+                                            match db.upsert_timed_override(&timed).await {
+                                                Ok(timed) => {
+                                                    expires_ms = Some(timed.expires_ms);
+                                                    let _ = event_tx.send(Event::TimedOverrideStarted(timed));
+                                                }
+                                                Err(e) => log::error!("Failed to persist timed override of device {} {}: {}", device_id, obj_id, e),
                                             }
                                         } else {
-                                            log::error!("Failed to parse write value: {}", value);
+                                            // A permanent write replaces a pending relinquish at the same priority
+                                            match db.delete_timed_override_at(device_id, object_type, instance, slot).await {
+                                                Ok(true) => log::info!("Write to device {} {} at priority {} ends its timed override", device_id, obj_id, slot),
+                                                Ok(false) => {}
+                                                Err(e) => log::error!("Failed to drop timed override of device {} {}: {}", device_id, obj_id, e),
+                                            }
+                                        }
+                                    }
+                                    if let Some(reason) = &rejection {
+                                        log::warn!("Rejected write of {} to device {} {}: {}", value, device_id, object_id, reason);
+                                    }
+                                    let message = match &result {
+                                        Ok(()) => match &verification {
+                                            Some(v) if !v.passed => {
+                                                log::warn!("WriteProperty {} on device {} read back {:?}, expected {}", object_id, device_id, v.readback, value);
+                                                format!("WriteProperty {} = {} on device {} acknowledged but read back {:?}", object_id, value, device_id, v.readback)
+                                            }
+                                            _ => format!("WriteProperty {} = {} on device {} acknowledged", object_id, value, device_id),
+                                        },
+                                        Err(e) => {
+                                            log::error!("WriteProperty {} on device {} failed: {}", object_id, device_id, e);
+                                            format!("WriteProperty {} = {} on device {} failed: {}", object_id, value, device_id, e)
+                                        }
+                                    };
+                                    let _ = event_tx.send(Event::StatusMessage(message));
+                                    let outcome = WriteOutcome {
+                                        device_id,
+                                        object_id,
+                                        property,
+                                        value,
+                                        priority,
+                                        expires_ms,
+                                        latency_ms,
+                                        verification,
+                                        rejected: rejection.is_some(),
+                                        error: result.as_ref().err().map(|e| e.to_string()),
+                                        failure: result.as_ref().err().and_then(|e| e.downcast_ref::<RequestError>()).copied(),
+                                    };
+                                    audit::record(&db, audit::write_entry(&origin, &outcome)).await;
+                                    let _ = event_tx.send(Event::WriteCompleted(outcome));
+                                });
+                            } else {
+                                let outcome = WriteOutcome {
+                                    device_id,
+                                    object_id: object_id_string(object_type, instance),
+                                    property,
+                                    value,
+                                    priority,
+                                    expires_ms: None,
+                                    latency_ms: None,
+                                    verification: None,
+                                    rejected: false,
+                                    error: Some("No interface bound".to_string()),
+                                    failure: None,
+                                };
+                                let db = self.db.clone();
+                                let entry = audit::write_entry(&origin, &outcome);
+                                tokio::spawn(async move { audit::record(&db, entry).await });
+                                self.event_tx.send(Event::WriteCompleted(outcome))?;
+                            }
+                        }
+                        Command::CancelTimedOverride { id, origin } => {
+                            if let Some(timed_overrides) = &self.timed_overrides {
+                                let timed_overrides = timed_overrides.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = timed_overrides.cancel(id, &origin).await {
+                                        log::error!("Cancelling timed override {} failed: {}", id, e);
+                                    }
+                                });
+                            } else {
+                                self.event_tx.send(Event::StatusMessage("Error: No interface bound".to_string()))?;
+                            }
+                        }
+                        Command::ReadPriorityArray { device_id, address, network, mac, object_type, instance } => {
+                            let object_id = ObjectId::new(object_type, instance);
+                            let mut reading = PriorityArrayReading {
+                                device_id,
+                                object_id: object_id.to_string(),
+                                priority_array: None,
+                                error: None,
+                            };
+                            match &self.services {
+                                _ if !object_id.is_commandable() => {
+                                    reading.error = Some(format!("Object {} is not commandable", object_id));
+                                    self.event_tx.send(Event::PriorityArrayRead(reading))?;
+                                }
+                                Some(services) => {
+                                    let services = services.clone();
+                                    let event_tx = self.event_tx.clone();
+                                    let commands = self.commands.clone();
+                                    let dest = self.resolve_address(&address, network, mac.as_deref());
+                                    tokio::spawn(async move {
+                                        let result = match dest {
+                                            Some(dest) => overrides::read_priority_array(&services, &dest, device_id, object_id, &commands).await,
+                                            None => Err(anyhow::anyhow!("Cannot route to {}", address)),
+                                        };
+                                        match result {
+                                            Ok(priority_array) => reading.priority_array = Some(priority_array),
+                                            Err(e) => reading.error = Some(e.to_string()),
+                                        }
+                                        let _ = event_tx.send(Event::PriorityArrayRead(reading));
+                                    });
+                                }
+                                None => {
+                                    reading.error = Some("No interface bound".to_string());
+                                    self.event_tx.send(Event::PriorityArrayRead(reading))?;
+                                }
+                            }
+                        }
+                        Command::ReadProperty { device_id, address, network, mac, object_type, instance, property, array_index } => {
+                            let mut reading = PropertyReading {
+                                device_id,
+                                object_id: object_id_string(object_type, instance),
+                                property,
+                                array_index,
+                                value: None,
+                                raw: None,
+                                error: None,
+                                failure: None,
+                            };
+                            if let Some(services) = &self.services {
+                                let services = services.clone();
+                                let event_tx = self.event_tx.clone();
+                                let dest = self.resolve_address(&address, network, mac.as_deref());
+                                tokio::spawn(async move {
+                                    let result = match dest {
+                                        Some(dest) => services.read_property(&dest, ObjectId::new(object_type, instance), property, array_index).await,
+                                        None => Err(anyhow::anyhow!("Cannot route to {}", address)),
+                                    };
+                                    match result {
+                                        Ok(data) => {
+                                            match property_value::to_json(property, array_index, &data) {
+                                                Ok(value) => reading.value = Some(value),
+                                                Err(e) => reading.error = Some(format!("Undecodable value: {}", e)),
+                                            }
+                                            reading.raw = Some(hex::encode(&data));
+                                        }
+                                        Err(e) => {
+                                            log::debug!("ReadProperty {} property {} on device {} failed: {}", reading.object_id, property, device_id, e);
+                                            reading.failure = e.downcast_ref::<RequestError>().copied();
+                                            reading.error = Some(e.to_string());
                                         }
                                     }
+                                    let _ = event_tx.send(Event::PropertyRead(reading));
                                 });
+                            } else {
+                                reading.error = Some("No interface bound".to_string());
+                                self.event_tx.send(Event::PropertyRead(reading))?;
                             }
                         }
-                        _ => {
-                            log::warn!("Command not yet implemented: {:?}", cmd);
+                        Command::WhoHas { search, low, high } => {
+                            let limits = match (low, high) {
+                                (Some(low), Some(high)) => Some((low, high)),
+                                _ => None,
+                            };
+                            if let Some(client_mutex) = &self.bacnet_client {
+                                let mut client = client_mutex.lock().unwrap();
+                                match client.broadcast_who_has(limits, &search, self.broadcast_addr) {
+                                    Ok(()) => {
+                                        let _ = self.event_tx.send(Event::StatusMessage(format!("Who-Has sent for {:?}", search)));
+                                    }
+                                    Err(e) => log::error!("Who-Has failed: {}", e),
+                                }
+                            } else {
+                                self.event_tx.send(Event::StatusMessage("Error: No interface bound".to_string()))?;
+                            }
+                        }
+                        Command::ReloadPoints => {
+                            if let Some(reload) = &self.points_reload {
+                                reload.notify_one();
+                            }
+                            self.history_reload.notify_one();
+                            let _ = self.event_tx.send(Event::PointMapChanged);
                         }
                     }
                 }
@@ -147,18 +490,67 @@ impl Core {
             .ok_or_else(|| anyhow::anyhow!("Interface not found"))?;
 
         log::info!("Interface {} has IP {}", name, iface.ip);
+        if !iface.ip.is_ipv4() {
+            anyhow::bail!("Interface {} has no IPv4 address", name);
+        }
+        self.broadcast_addr = iface.broadcast;
         
         // Prefer specific interface IP, but support 0.0.0.0 if needed
         let addr = std::net::SocketAddr::new(iface.ip, 47808);
         log::info!("Attempting to bind to {}", addr);
         
-        let client = BacnetClient::new(addr)?;
+        let transactions = Arc::new(TransactionManager::new());
+        let client = BacnetClient::new(addr, Arc::clone(&transactions))?;
         let client_arc = Arc::new(Mutex::new(client));
         self.bacnet_client = Some(Arc::clone(&client_arc));
+        let services = BacnetServices::new(Arc::clone(&client_arc), Arc::clone(&transactions));
+
+        let device_manager = DeviceManager::new(services.clone(), self.db.clone(), self.event_tx.clone(), Arc::clone(&self.routing_table)).await;
+        let devices = device_manager.devices();
+        let liveness = Liveness::new(self.event_tx.clone(), self.config.liveness.clone());
+        let acquisition = Acquisition::new(services.clone(), self.db.clone(), self.event_tx.clone(), Arc::clone(&self.routing_table), Arc::clone(&devices), liveness.clone());
+        self.points_reload = Some(acquisition.reload_handle());
+        tokio::spawn(acquisition.run());
+        tokio::spawn(OverrideMonitor {
+            services: services.clone(),
+            db: self.db.clone(),
+            event_tx: self.event_tx.clone(),
+            routing_table: Arc::clone(&self.routing_table),
+            devices: Arc::clone(&devices),
+            liveness: liveness.clone(),
+            commands: self.commands.clone(),
+            config: self.config.overrides.clone(),
+        }.run());
+        let timed_overrides = TimedOverrides {
+            services: services.clone(),
+            db: self.db.clone(),
+            event_tx: self.event_tx.clone(),
+            routing_table: Arc::clone(&self.routing_table),
+            devices: Arc::clone(&devices),
+            liveness: liveness.clone(),
+            commands: self.commands.clone(),
+        };
+        tokio::spawn(timed_overrides.clone().run());
+        self.timed_overrides = Some(timed_overrides);
+        tokio::spawn(liveness.run(services.clone(), Arc::clone(&client_arc), Arc::clone(&devices), Arc::clone(&self.routing_table), self.event_tx.subscribe()));
+        tokio::spawn(Rediscovery {
+            client: Arc::clone(&client_arc),
+            subnet_broadcast: self.broadcast_addr,
+            services: services.clone(),
+            db: self.db.clone(),
+            event_tx: self.event_tx.clone(),
+            routing_table: Arc::clone(&self.routing_table),
+            devices: Arc::clone(&devices),
+            config: self.config.discovery.clone(),
+        }.run());
+        self.devices = Some(devices);
+        self.services = Some(services);
+        tokio::spawn(device_manager.run(self.event_tx.subscribe()));
 
         // Spawn a dedicated thread for receiving frames (blocking I/O)
         let event_tx = self.event_tx.clone();
         let shutdown = Arc::clone(&self.shutdown);
+        let routing_table = Arc::clone(&self.routing_table);
         tokio::task::spawn_blocking(move || {
             log::info!("BACnet receiver thread started for {}", iface.ip);
             let mut last_error_log = std::time::Instant::now() - std::time::Duration::from_secs(60);
@@ -180,35 +572,103 @@ impl Core {
                         let src: bacnet_rs::datalink::DataLinkAddress = src;
                         log::info!("RECEIVED PACKET: {} bytes from {:?}. Hex: {}", data.len(), src, hex::encode(&data));
                         error_count = 0;
-                        if let Ok(apdu) = Apdu::decode(&data) {
+                        let (source, apdu_data) = match npdu::decode(&data, src.clone()) {
+                            Ok((source, NpduPayload::Apdu(apdu_data))) => (source, apdu_data),
+                            Ok((source, NpduPayload::NetworkMessage(msg))) => {
+                                if let (Some(networks), bacnet_rs::datalink::DataLinkAddress::Ip(router)) = (npdu::parse_i_am_router_to_network(&msg), &source.datalink) {
+                                    log::info!("Router {} serves networks {:?}", router, networks);
+                                    let mut table = routing_table.lock().unwrap();
+                                    for network in &networks {
+                                        table.insert(*network, *router);
+                                    }
+                                    let _ = event_tx.send(Event::RouterDiscovered(BacnetRouter {
+                                        address: router.to_string(),
+                                        networks,
+                                    }));
+                                }
+                                continue;
+                            }
+                            Err(e) => {
+                                log::debug!("Dropping frame with invalid NPDU from {:?}: {}", src, e);
+                                continue;
+                            }
+                        };
+                        if let Ok(apdu) = Apdu::decode(apdu_data) {
                             match apdu {
+                                Apdu::UnconfirmedRequest { service_choice, ref service_data } if service_choice == cov::UNCONFIRMED_COV_NOTIFICATION => {
+                                    match cov::decode_notification(service_data) {
+                                        Ok(notification) if notification.process_id == COV_PROCESS_ID => {
+                                            log::debug!("COV notification from device {} ({}s remaining)", notification.device_id, notification.time_remaining);
+                                            if let Some(value) = notification.present_value() {
+                                                let status = notification.status();
+                                                let _ = event_tx.send(Event::PointUpdate {
+                                                    device_id: notification.device_id,
+                                                    object_id: object_id_string(notification.object_type, notification.instance),
+                                                    value,
+                                                    status,
+                                                    quality: status.quality(),
+                                                });
+                                            }
+                                        }
+                                        Ok(_) => {}
+                                        Err(e) => log::debug!("Undecodable COV notification from {}: {}", source, e),
+                                    }
+                                }
+                                Apdu::UnconfirmedRequest { service_choice, ref service_data } if service_choice == who_has::I_HAVE => {
+                                    match who_has::decode_i_have(service_data) {
+                                        Ok(i_have) => {
+                                            log::info!("I-Have {}:{} ({}) from device {}", i_have.object_type, i_have.instance, i_have.object_name, i_have.device_id);
+                                            let _ = event_tx.send(Event::IHave(IHave {
+                                                device_id: i_have.device_id,
+                                                address: source.datalink_string(),
+                                                network: source.network(),
+                                                mac: source.mac_hex(),
+                                                object_type: i_have.object_type,
+                                                instance: i_have.instance,
+                                                object_name: i_have.object_name,
+                                            }));
+                                        }
+                                        Err(e) => log::debug!("Undecodable I-Have from {}: {}", source, e),
+                                    }
+                                }
                                 Apdu::UnconfirmedRequest { .. } => {
                                     if let Ok(Some(mut device)) = discovery::parse_i_am(&apdu) {
-                                        device.address = match src {
-                                            bacnet_rs::datalink::DataLinkAddress::Ip(addr) => addr.to_string(),
-                                            _ => format!("{:?}", src),
-                                        };
-                                        log::info!("Discovered device: {:?} from {:?}", device, src);
+                                        device.address = source.datalink_string();
+                                        device.network = source.network();
+                                        device.mac = source.mac_hex();
+                                        log::info!("Discovered device: {:?} from {}", device, source);
                                         let _ = event_tx.send(Event::DeviceDiscovered(device));
                                     }
                                 }
-                                Apdu::ComplexAck { .. } => {
-                                    if let Ok(Some(resp)) = discovery::parse_read_property_response(&apdu) {
-                                        if resp.property_identifier == PropertyIdentifier::ObjectList as u32 {
-                                            if let Ok(objects) = discovery::parse_object_list(&resp.property_value) {
-                                                log::info!("Discovered {} objects on device {}", objects.len(), resp.object_identifier.instance);
-                                                let _ = event_tx.send(Event::DeviceObjectsDiscovered {
-                                                    device_id: resp.object_identifier.instance,
-                                                    objects: objects.into_iter().map(|id| crate::common::types::BacnetObjectInfo {
-                                                        object_type: id.object_type as u16,
-                                                        instance: id.instance,
-                                                        name: format!("{:?} {}", id.object_type, id.instance),
-                                                    }).collect(),
-                                                });
-                                            }
+                                Apdu::ComplexAck { invoke_id, ref service_data, .. } if transactions.resolve(&source, invoke_id, Ok(service_data.clone())) => {}
+                                Apdu::SimpleAck { invoke_id, service_choice } => {
+                                    let service = properties::confirmed_service_name(service_choice);
+                                    log::debug!("SimpleAck for {} (invoke ID {}) from {}", service, invoke_id, source);
+                                    transactions.resolve(&source, invoke_id, Ok(Vec::new()));
+                                    let _ = event_tx.send(Event::RequestAcknowledged { address: source.to_string(), invoke_id, service });
+                                }
+                                Apdu::Error { invoke_id, .. } | Apdu::Reject { invoke_id, .. } | Apdu::Abort { invoke_id, .. } => {
+                                    match errors::decode(apdu_data) {
+                                        Ok(failure) => {
+                                            let service = failure.service_choice.map(properties::confirmed_service_name);
+                                            log::warn!("{} (invoke ID {}) failed at {}: {}", service.as_deref().unwrap_or("Request"), invoke_id, source, failure.error);
+                                            transactions.resolve(&source, invoke_id, Err(failure.error.into()));
+                                            let _ = event_tx.send(Event::RequestFailed(RequestFailure {
+                                                address: source.to_string(),
+                                                invoke_id,
+                                                service,
+                                                error: failure.error,
+                                            }));
+                                        }
+                                        Err(e) => {
+                                            log::debug!("Undecodable failure PDU from {}: {}", source, e);
+                                            transactions.resolve(&source, invoke_id, Err(anyhow::anyhow!("Request failed at {}: {}", source, e)));
                                         }
                                     }
                                 }
+                                Apdu::ComplexAck { invoke_id, service_choice, .. } => {
+                                    log::debug!("Unsolicited ComplexAck (service {}, invoke ID {}) from {}", service_choice, invoke_id, source);
+                                }
                                 _ => {}
                             }
                         }
@@ -236,26 +696,63 @@ impl Core {
         Ok(())
     }
 
+    fn resolve_address(&self, address: &str, network: Option<u16>, mac: Option<&str>) -> Option<BacnetAddress> {
+        BacnetAddress::resolve(address, network, mac, &self.routing_table)
+    }
+
     async fn start_discovery(&mut self) -> Result<()> {
         if let Some(client_mutex) = &self.bacnet_client {
             let client_arc = Arc::clone(client_mutex);
             let event_tx = self.event_tx.clone();
+            let broadcast_addr = self.broadcast_addr;
             tokio::spawn(async move {
                 let mut client = client_arc.lock().unwrap();
-                // Send both standard broadcast and directed broadcast
-                let _ = client.send_who_is(None, None, None);
-                let local_broadcast = bacnet_rs::datalink::DataLinkAddress::Ip(
-                    "192.168.1.255:47808".parse().unwrap()
-                );
-                if let Err(e) = client.send_who_is(None, None, Some(local_broadcast)) {
-                    log::error!("Directed Who-Is failed: {}", e);
-                } else {
-                    let _ = event_tx.send(Event::StatusMessage("Who-Is broadcasts sent".to_string()));
+                if let Err(e) = client.send_who_is_router_to_network(None) {
+                    log::error!("Who-Is-Router-To-Network failed: {}", e);
+                }
+                // Send both standard broadcast and directed broadcast on the bound subnet
+                if let Err(e) = client.broadcast_who_is(None, None, broadcast_addr) {
+                    log::error!("Who-Is failed: {}", e);
+                    return;
                 }
+                let _ = event_tx.send(Event::StatusMessage("Who-Is broadcasts sent".to_string()));
             });
         } else {
             self.event_tx.send(Event::StatusMessage("Error: No interface bound".to_string()))?;
         }
         Ok(())
     }
+
+    /// Starts a paced, ranged Who-Is sweep, replacing any sweep in progress.
+    fn start_sweep(&mut self, low: Option<u32>, high: Option<u32>, chunk_size: Option<u32>, chunk_delay_ms: Option<u64>) -> Result<()> {
+        let Some(client_mutex) = &self.bacnet_client else {
+            self.event_tx.send(Event::StatusMessage("Error: No interface bound".to_string()))?;
+            return Ok(());
+        };
+        let defaults = &self.config.discovery;
+        let (low, high) = match sweep::sweep_range(low.unwrap_or(defaults.sweep_low), high.unwrap_or(defaults.sweep_high)) {
+            Ok(range) => range,
+            Err(e) => {
+                self.event_tx.send(Event::StatusMessage(format!("Error: {}", e)))?;
+                return Ok(());
+            }
+        };
+        if let Some(previous) = self.sweep_cancel.take() {
+            previous.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+        let cancel = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        self.sweep_cancel = Some(Arc::clone(&cancel));
+        let sweep = Sweep {
+            client: Arc::clone(client_mutex),
+            subnet_broadcast: self.broadcast_addr,
+            event_tx: self.event_tx.clone(),
+            low,
+            high,
+            chunk_size: chunk_size.unwrap_or(defaults.chunk_size),
+            chunk_delay: std::time::Duration::from_millis(chunk_delay_ms.unwrap_or(defaults.chunk_delay_ms)),
+            cancel,
+        };
+        tokio::spawn(sweep.run());
+        Ok(())
+    }
 }
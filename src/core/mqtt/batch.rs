@@ -0,0 +1,103 @@
+use crate::common::config::{MqttBatchMode, MqttConfig};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Collects point updates into JSON arrays, one per device or one for the
+/// gateway. A batch closes when its window has passed since its first
+/// update, or when the updates collected reach the size limit. Entries are
+/// point payloads with `device_id`, `object_type` and `instance` added;
+/// other messages are not batched.
+pub struct Batcher {
+    mode: MqttBatchMode,
+    root: String,
+    window: Duration,
+    max_size: usize,
+    pending: BTreeMap<String, Vec<Value>>,
+    size: usize,
+    deadline: Option<Instant>,
+}
+
+impl Batcher {
+    pub fn new(config: &MqttConfig, root: &str) -> Self {
+        Self {
+            mode: config.batch,
+            root: root.to_string(),
+            window: Duration::from_millis(config.batch_window_ms),
+            max_size: config.batch_max_size.max(1),
+            pending: BTreeMap::new(),
+            size: 0,
+            deadline: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.mode != MqttBatchMode::Off
+    }
+
+    /// When the open batches are due, if there are any.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Adds an update of a device. Returns the batches when the size limit
+    /// is reached.
+    pub fn push(&mut self, device_id: u32, entry: Value) -> Option<Vec<(String, Value)>> {
+        let topic = match self.mode {
+            MqttBatchMode::Device => format!("{}/{}/batch", self.root, device_id),
+            MqttBatchMode::Gateway | MqttBatchMode::Off => format!("{}/batch", self.root),
+        };
+        self.pending.entry(topic).or_default().push(entry);
+        self.size += 1;
+        self.deadline.get_or_insert_with(|| Instant::now() + self.window);
+        (self.size >= self.max_size).then(|| self.take())
+    }
+
+    /// Closes the open batches: topic and JSON array of each.
+    pub fn take(&mut self) -> Vec<(String, Value)> {
+        self.size = 0;
+        self.deadline = None;
+        std::mem::take(&mut self.pending).into_iter().map(|(topic, entries)| (topic, Value::Array(entries))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn batcher(mode: MqttBatchMode, window_ms: u64, max_size: usize) -> Batcher {
+        let config = MqttConfig { batch: mode, batch_window_ms: window_ms, batch_max_size: max_size, ..MqttConfig::default() };
+        Batcher::new(&config, "bacnet/gw1")
+    }
+
+    #[test]
+    fn window_starts_with_the_first_update() {
+        let mut batcher = batcher(MqttBatchMode::Device, 1000, 10);
+        assert_eq!(batcher.deadline(), None);
+        let before = Instant::now();
+        assert!(batcher.push(1, json!(1)).is_none());
+        let deadline = batcher.deadline().unwrap();
+        assert!(deadline >= before + Duration::from_millis(1000));
+        assert!(batcher.push(2, json!(2)).is_none());
+        assert!(batcher.push(1, json!(3)).is_none());
+        assert_eq!(batcher.deadline(), Some(deadline));
+
+        let batches = batcher.take();
+        assert_eq!(batches, [("bacnet/gw1/1/batch".to_string(), json!([1, 3])), ("bacnet/gw1/2/batch".to_string(), json!([2]))]);
+        assert_eq!(batcher.deadline(), None);
+        assert!(batcher.take().is_empty());
+    }
+
+    #[test]
+    fn size_limit_closes_the_batches() {
+        let mut batcher = batcher(MqttBatchMode::Gateway, 60_000, 3);
+        assert!(batcher.push(1, json!(1)).is_none());
+        assert!(batcher.push(2, json!(2)).is_none());
+        let batches = batcher.push(1, json!(3)).unwrap();
+        assert_eq!(batches, [("bacnet/gw1/batch".to_string(), json!([1, 2, 3]))]);
+        assert_eq!(batcher.deadline(), None);
+        assert!(batcher.push(1, json!(4)).is_none());
+    }
+}
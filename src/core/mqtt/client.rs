@@ -0,0 +1,406 @@
+use crate::common::config::MqttConfig;
+use crate::common::types::{object_id_string, AuditSource, Command, Event, BacnetDevice, Origin, PriorityOverride, Quality, TimedOverride, ValueStatus};
+use crate::core::mqtt::batch::Batcher;
+use crate::core::mqtt::filter::{PublishFilters, Sample};
+use crate::core::mqtt::outbox::Outbox;
+use crate::core::persistence::db::{now_ms, Database};
+use bacnet_rs::object::PropertyIdentifier;
+use rumqttc::{AsyncClient, EventLoop, MqttOptions, Packet, Publish, QoS};
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, watch};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Interval of queue limit checks and `MqttQueue` reports
+const QUEUE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Interval of checks for held-back changes and heartbeats
+const FILTER_TICK: Duration = Duration::from_secs(1);
+/// Pause of the replay while the client's request queue is full
+const REPLAY_PAUSE: Duration = Duration::from_millis(200);
+
+/// Publishes core events to the MQTT broker. Point values go to
+/// `bacnet/{gateway_id}/{device_id}/{object_type}/{instance}`, discovery
+/// changes to `bacnet/{gateway_id}/events/{kind}` and device online status,
+/// retained, to `bacnet/{gateway_id}/{device_id}/status`, all with QoS 1.
+/// Point payloads are `{"value", "status_flags", "reliability",
+/// "out_of_service", "quality", "timestamp_ms"}`. Values of faulted points
+/// are flagged by their quality, or dropped with `suppress_faulted`.
+/// Writes (`WriteCommand`, the address taken from the device table) and
+/// timed override cancellations are accepted on
+/// `bacnet/{gateway_id}/commands/{write|cancel_timed_override}`.
+///
+/// While the broker is unreachable messages go to a durable queue (`Outbox`)
+/// and are replayed in order after reconnecting. Point updates can be
+/// batched (`Batcher`) instead of published one by one, and are filtered by
+/// the publish filters of their points (`PublishFilters`).
+pub struct MqttBridge {
+    client: AsyncClient,
+    eventloop: EventLoop,
+    root: String,
+    config: MqttConfig,
+}
+
+impl MqttBridge {
+    pub fn new(config: &MqttConfig) -> Self {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(config.keep_alive_secs.max(5)));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+        }
+        let (client, eventloop) = AsyncClient::new(options, 256);
+        Self { client, eventloop, root: format!("bacnet/{}", config.gateway_id), config: config.clone() }
+    }
+
+    pub async fn run(self, event_tx: broadcast::Sender<Event>, cmd_tx: mpsc::Sender<Command>, db: Database) {
+        let Self { client, mut eventloop, root, config } = self;
+        let mut event_rx = event_tx.subscribe();
+        let (connected_tx, mut connected_rx) = watch::channel(false);
+        let commands_client = client.clone();
+        let commands_root = root.clone();
+        let commands_db = db.clone();
+        tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(rumqttc::Event::Incoming(Packet::ConnAck(_))) => {
+                        log::info!("Connected to MQTT broker");
+                        connected_tx.send_replace(true);
+                        let filter = format!("{}/commands/+", commands_root);
+                        if let Err(e) = commands_client.try_subscribe(&filter, QoS::AtLeastOnce) {
+                            log::warn!("Failed to subscribe to {}: {}", filter, e);
+                        }
+                    }
+                    Ok(rumqttc::Event::Incoming(Packet::Publish(publish))) => {
+                        if let Err(e) = handle_command(&commands_root, &publish, &cmd_tx, &commands_db).await {
+                            log::warn!("Rejected MQTT command on {}: {}", publish.topic, e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::warn!("MQTT connection error: {}", e);
+                        connected_tx.send_replace(false);
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        });
+
+        let mut filters = PublishFilters::default();
+        load_filters(&mut filters, &db).await;
+        let mut filter_tick = tokio::time::interval(FILTER_TICK);
+        let mut publisher = Publisher { client, outbox: Outbox::open(db.clone(), &config).await, connected: false };
+        let mut batcher = Batcher::new(&config, &root);
+        let mut queue_check = tokio::time::interval(QUEUE_CHECK_INTERVAL);
+        // When the next replay step may run; `None` runs it right away
+        let mut replay_at = None;
+        loop {
+            let batch_deadline = batcher.deadline();
+            tokio::select! {
+                event = event_rx.recv() => {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            log::warn!("MQTT bridge lagged, skipped {} events", n);
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+                    if let Event::PointUpdate { device_id, object_id, quality: Quality::Bad, .. } = &event {
+                        if config.suppress_faulted {
+                            log::debug!("Not publishing faulted point {} of device {}", object_id, device_id);
+                            continue;
+                        }
+                    }
+                    match &event {
+                        Event::PointUpdate { device_id, object_id, value, status, quality } => {
+                            let sample = Sample { value: *value, status: *status, quality: *quality };
+                            if filters.accept(*device_id, object_id, sample) {
+                                publish_point(&mut publisher, &mut batcher, &root, *device_id, object_id, sample).await;
+                            }
+                        }
+                        Event::PointMapChanged => load_filters(&mut filters, &db).await,
+                        event => {
+                            if let Some((topic, payload, retain)) = message(&root, event) {
+                                publisher.publish(&topic, &payload, retain).await;
+                            }
+                        }
+                    }
+                }
+                _ = filter_tick.tick() => {
+                    for (device_id, object_id, sample) in filters.due() {
+                        publish_point(&mut publisher, &mut batcher, &root, device_id, &object_id, sample).await;
+                    }
+                }
+                _ = sleep_until(batch_deadline), if batch_deadline.is_some() => {
+                    for (topic, payload) in batcher.take() {
+                        publisher.publish(&topic, &payload, false).await;
+                    }
+                }
+                Ok(()) = connected_rx.changed() => {
+                    publisher.connected = *connected_rx.borrow_and_update();
+                    let _ = event_tx.send(Event::MqttQueue(publisher.outbox.status(publisher.connected)));
+                }
+                _ = sleep_until(replay_at), if publisher.connected && !publisher.outbox.is_empty() => {
+                    replay_at = None;
+                    match publisher.outbox.replay(&publisher.client).await {
+                        Ok(0) => replay_at = Some(tokio::time::Instant::now() + REPLAY_PAUSE),
+                        Ok(_) if publisher.outbox.is_empty() => {
+                            log::info!("Replayed all queued MQTT messages");
+                            let _ = event_tx.send(Event::MqttQueue(publisher.outbox.status(publisher.connected)));
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            log::error!("Failed to replay queued MQTT messages: {}", e);
+                            replay_at = Some(tokio::time::Instant::now() + RECONNECT_DELAY);
+                        }
+                    }
+                }
+                _ = queue_check.tick() => {
+                    publisher.outbox.trim().await;
+                    let _ = event_tx.send(Event::MqttQueue(publisher.outbox.status(publisher.connected)));
+                }
+            }
+        }
+    }
+}
+
+async fn load_filters(filters: &mut PublishFilters, db: &Database) {
+    match db.load_points().await {
+        Ok(points) => filters.load(&points),
+        Err(e) => log::error!("Failed to load point map: {}", e),
+    }
+}
+
+/// Publishes a point update to the point's topic, or adds it to a batch.
+async fn publish_point(publisher: &mut Publisher, batcher: &mut Batcher, root: &str, device_id: u32, object_id: &str, sample: Sample) {
+    let Some((object_type, instance)) = object_id.split_once(':') else { return };
+    let mut payload = point_payload(sample.value, &sample.status, sample.quality);
+    if !batcher.enabled() {
+        publisher.publish(&format!("{}/{}/{}/{}", root, device_id, object_type, instance), &payload, false).await;
+        return;
+    }
+    payload["device_id"] = json!(device_id);
+    payload["object_type"] = json!(object_type.parse::<u16>().ok());
+    payload["instance"] = json!(instance.parse::<u32>().ok());
+    for (topic, payload) in batcher.push(device_id, payload).unwrap_or_default() {
+        publisher.publish(&topic, &payload, false).await;
+    }
+}
+
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    if let Some(deadline) = deadline {
+        tokio::time::sleep_until(deadline).await;
+    }
+}
+
+/// Publishes directly while the broker is connected and nothing is queued,
+/// so messages stay in order; queues otherwise.
+struct Publisher {
+    client: AsyncClient,
+    outbox: Outbox,
+    connected: bool,
+}
+
+impl Publisher {
+    async fn publish(&mut self, topic: &str, payload: &serde_json::Value, retain: bool) {
+        let payload = payload.to_string();
+        if self.connected && self.outbox.is_empty() {
+            match self.client.try_publish(topic, QoS::AtLeastOnce, retain, payload.clone()) {
+                Ok(()) => return,
+                Err(e) => log::debug!("Queueing MQTT message for {}: {}", topic, e),
+            }
+        }
+        self.outbox.push(topic, &payload, retain).await;
+    }
+}
+
+/// A write received over MQTT. The device's address is taken from the
+/// device table.
+#[derive(serde::Deserialize)]
+struct WriteCommand {
+    device_id: u32,
+    object_type: u16,
+    instance: u32,
+    #[serde(default = "present_value")]
+    property: u32,
+    value: serde_json::Value,
+    #[serde(default)]
+    priority: Option<u8>,
+    #[serde(default)]
+    duration_secs: Option<u64>,
+    #[serde(default)]
+    verify: bool,
+    #[serde(default)]
+    tolerance: Option<f32>,
+    /// Recorded in the audit log
+    #[serde(default)]
+    client_id: Option<String>,
+}
+
+fn present_value() -> u32 {
+    PropertyIdentifier::PresentValue as u32
+}
+
+#[derive(serde::Deserialize)]
+struct CancelCommand {
+    id: i64,
+    #[serde(default)]
+    client_id: Option<String>,
+}
+
+async fn handle_command(root: &str, publish: &Publish, cmd_tx: &mpsc::Sender<Command>, db: &Database) -> anyhow::Result<()> {
+    let command = match publish.topic.strip_prefix(root).and_then(|t| t.strip_prefix("/commands/")) {
+        Some("write") => {
+            let write: WriteCommand = serde_json::from_slice(&publish.payload)?;
+            let devices = db.load_devices().await?;
+            let device = devices
+                .into_iter()
+                .find(|d| d.instance == write.device_id)
+                .ok_or_else(|| anyhow::anyhow!("Unknown device {}", write.device_id))?;
+            Command::WriteProperty {
+                device_id: write.device_id,
+                address: device.address,
+                network: device.network,
+                mac: device.mac,
+                object_type: write.object_type,
+                instance: write.instance,
+                property: write.property,
+                value: match write.value {
+                    serde_json::Value::String(value) => value,
+                    value => value.to_string(),
+                },
+                priority: write.priority,
+                duration_secs: write.duration_secs,
+                verify: write.verify,
+                tolerance: write.tolerance,
+                origin: Origin::new(AuditSource::Mqtt, write.client_id),
+            }
+        }
+        Some("cancel_timed_override") => {
+            let cancel: CancelCommand = serde_json::from_slice(&publish.payload)?;
+            Command::CancelTimedOverride { id: cancel.id, origin: Origin::new(AuditSource::Mqtt, cancel.client_id) }
+        }
+        _ => anyhow::bail!("Unknown command"),
+    };
+    cmd_tx.send(command).await?;
+    Ok(())
+}
+
+/// Topic, JSON payload and retain flag for an event, `None` for events that
+/// are not published. Point updates are published by `publish_point`.
+fn message(root: &str, event: &Event) -> Option<(String, serde_json::Value, bool)> {
+    let event_topic = |kind: &str| format!("{}/events/{}", root, kind);
+    match event {
+        Event::DeviceAdded(device) => Some((event_topic("device_added"), device_payload(device), false)),
+        Event::DeviceRemoved { device_id } => Some((
+            event_topic("device_removed"),
+            json!({ "device_id": device_id, "timestamp_ms": now_ms() }),
+            false,
+        )),
+        Event::DeviceAddressChanged { device_id, old_address, new_address } => Some((
+            event_topic("device_address_changed"),
+            json!({ "device_id": device_id, "old_address": old_address, "new_address": new_address, "timestamp_ms": now_ms() }),
+            false,
+        )),
+        Event::ObjectListChanged { device_id, added, removed } => Some((
+            event_topic("object_list_changed"),
+            json!({ "device_id": device_id, "added": added, "removed": removed, "timestamp_ms": now_ms() }),
+            false,
+        )),
+        Event::DeviceConflict(conflict) => Some((
+            event_topic("device_conflict"),
+            json!({
+                "kind": conflict.kind,
+                "key": conflict.key,
+                "devices": conflict.devices.iter().map(device_payload).collect::<Vec<_>>(),
+                "timestamp_ms": conflict.last_seen_ms,
+            }),
+            false,
+        )),
+        Event::DeviceStatusChanged(status) => Some((
+            format!("{}/{}/status", root, status.device_id),
+            json!({
+                "online": status.online,
+                "last_seen_ms": status.last_seen_ms,
+                "consecutive_failures": status.consecutive_failures,
+                "timestamp_ms": now_ms(),
+            }),
+            true,
+        )),
+        Event::OverrideDetected(found) => Some((event_topic("override_detected"), override_payload(found), false)),
+        Event::OverrideCleared(cleared) => Some((event_topic("override_cleared"), override_payload(cleared), false)),
+        Event::WriteCompleted(outcome) => Some((
+            event_topic("write_completed"),
+            json!({
+                "device_id": outcome.device_id,
+                "object_id": outcome.object_id,
+                "property": outcome.property,
+                "value": outcome.value,
+                "priority": outcome.priority,
+                "expires_ms": outcome.expires_ms,
+                "latency_ms": outcome.latency_ms,
+                "verification": outcome.verification,
+                "rejected": outcome.rejected,
+                "error": outcome.error,
+                "timestamp_ms": now_ms(),
+            }),
+            false,
+        )),
+        Event::TimedOverrideStarted(timed) => Some((event_topic("timed_override_started"), timed_override_payload(timed, None), false)),
+        Event::TimedOverrideEnded(end) => Some((
+            event_topic("timed_override_ended"),
+            timed_override_payload(&end.timed_override, Some(json!({ "cancelled": end.cancelled, "error": end.error }))),
+            false,
+        )),
+        _ => None,
+    }
+}
+
+fn point_payload(value: f32, status: &ValueStatus, quality: Quality) -> serde_json::Value {
+    json!({
+        "value": value,
+        "status_flags": status.status_flags.map(|f| f.to_bits()),
+        "reliability": status.reliability,
+        "out_of_service": status.out_of_service,
+        "quality": quality,
+        "timestamp_ms": now_ms(),
+    })
+}
+
+fn override_payload(found: &PriorityOverride) -> serde_json::Value {
+    json!({
+        "device_id": found.device_id,
+        "object_id": found.object_id,
+        "priority": found.priority,
+        "value": found.value,
+        "in_control": found.in_control,
+        "since_ms": found.since_ms,
+        "timestamp_ms": now_ms(),
+    })
+}
+
+/// A timed override, with the fields of `extra` (an object) merged in.
+fn timed_override_payload(timed: &TimedOverride, extra: Option<serde_json::Value>) -> serde_json::Value {
+    let mut payload = json!({
+        "id": timed.id,
+        "device_id": timed.device_id,
+        "object_id": object_id_string(timed.object_type, timed.instance),
+        "priority": timed.priority,
+        "value": timed.value,
+        "expires_ms": timed.expires_ms,
+        "timestamp_ms": now_ms(),
+    });
+    if let (Some(payload), Some(serde_json::Value::Object(extra))) = (payload.as_object_mut(), extra) {
+        payload.extend(extra);
+    }
+    payload
+}
+
+fn device_payload(device: &BacnetDevice) -> serde_json::Value {
+    json!({
+        "device_id": device.instance,
+        "address": device.route(),
+        "vendor_id": device.vendor_id,
+        "vendor_name": device.vendor_name,
+        "timestamp_ms": now_ms(),
+    })
+}
@@ -0,0 +1,218 @@
+use crate::common::types::{object_id_string, PointConfig, PublishFilter, Quality, ValueStatus};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A point update as published.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub value: f32,
+    pub status: ValueStatus,
+    pub quality: Quality,
+}
+
+/// Filter of one point with what it last received and published.
+struct PointFilter {
+    filter: PublishFilter,
+    latest: Option<Sample>,
+    published: Option<(Sample, Instant)>,
+    /// The latest sample passed the value filters but was held back by the
+    /// minimum interval
+    held: bool,
+}
+
+impl PointFilter {
+    /// Whether a sample differs enough from the last published one.
+    fn changed(&self, sample: &Sample) -> bool {
+        let Some((last, _)) = &self.published else { return true };
+        if last.status != sample.status || last.quality != sample.quality {
+            return true;
+        }
+        let delta = (sample.value - last.value).abs();
+        let filter = &self.filter;
+        !(filter.change_only && delta == 0.0)
+            && filter.deadband.is_none_or(|deadband| delta >= deadband)
+            && filter.deadband_percent.is_none_or(|percent| {
+                // No percentage of zero to compare with: any change passes,
+                // within the absolute deadband if there is one
+                if last.value == 0.0 {
+                    delta > 0.0
+                } else {
+                    delta >= last.value.abs() * percent / 100.0
+                }
+            })
+    }
+
+    fn min_interval_passed(&self, now: Instant) -> bool {
+        match (&self.published, self.filter.min_interval_secs) {
+            (Some((_, at)), Some(secs)) => now.duration_since(*at) >= Duration::from_secs(secs as u64),
+            _ => true,
+        }
+    }
+
+    fn heartbeat_due(&self, now: Instant) -> bool {
+        match (&self.published, self.filter.heartbeat_secs) {
+            (Some((_, at)), Some(secs)) => now.duration_since(*at) >= Duration::from_secs(secs as u64),
+            _ => false,
+        }
+    }
+}
+
+/// Publish filters of the mapped points. An update is held back unless it
+/// passes the deadbands (and, with `change_only`, differs at all) compared
+/// with the last published value; a change of quality or status always
+/// passes. A change held back by `min_interval_secs` is published once the
+/// interval has passed, and `heartbeat_secs` republishes the latest value
+/// after that long without a publish. Points without filters are not
+/// tracked and every update of them is published. The filters apply to
+/// MQTT only.
+#[derive(Default)]
+pub struct PublishFilters {
+    points: HashMap<(u32, String), PointFilter>,
+}
+
+impl PublishFilters {
+    /// Takes over the filters of a (re)loaded point map, keeping the state of
+    /// points whose filter did not change.
+    pub fn load(&mut self, points: &[PointConfig]) {
+        let mut previous = std::mem::take(&mut self.points);
+        for point in points.iter().filter(|p| p.publish != PublishFilter::default()) {
+            let key = (point.device_id, object_id_string(point.object_type, point.instance));
+            let filter = match previous.remove(&key) {
+                Some(existing) if existing.filter == point.publish => existing,
+                _ => PointFilter { filter: point.publish.clone(), latest: None, published: None, held: false },
+            };
+            self.points.insert(key, filter);
+        }
+    }
+
+    /// Whether to publish an update now.
+    pub fn accept(&mut self, device_id: u32, object_id: &str, sample: Sample) -> bool {
+        self.accept_at(device_id, object_id, sample, Instant::now())
+    }
+
+    /// Held-back changes whose minimum interval has passed and heartbeats
+    /// that are due, as device, object and sample.
+    pub fn due(&mut self) -> Vec<(u32, String, Sample)> {
+        self.due_at(Instant::now())
+    }
+
+    fn accept_at(&mut self, device_id: u32, object_id: &str, sample: Sample, now: Instant) -> bool {
+        let Some(point) = self.points.get_mut(&(device_id, object_id.to_string())) else { return true };
+        point.latest = Some(sample);
+        point.held = false;
+        if !point.changed(&sample) {
+            return false;
+        }
+        if !point.min_interval_passed(now) {
+            point.held = true;
+            return false;
+        }
+        point.published = Some((sample, now));
+        true
+    }
+
+    fn due_at(&mut self, now: Instant) -> Vec<(u32, String, Sample)> {
+        let mut due = Vec::new();
+        for ((device_id, object_id), point) in &mut self.points {
+            let Some(latest) = point.latest else { continue };
+            if (point.held && point.min_interval_passed(now)) || point.heartbeat_due(now) {
+                point.held = false;
+                point.published = Some((latest, now));
+                due.push((*device_id, object_id.clone(), latest));
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINT: &str = "2:1";
+
+    fn point_filters(filter: PublishFilter) -> PublishFilters {
+        let point = PointFilter { filter, latest: None, published: None, held: false };
+        PublishFilters { points: HashMap::from([((1, POINT.to_string()), point)]) }
+    }
+
+    fn sample(value: f32) -> Sample {
+        Sample { value, status: ValueStatus::default(), quality: Quality::Good }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn unfiltered_points_are_always_published() {
+        let mut filters = point_filters(PublishFilter { deadband: Some(1.0), ..PublishFilter::default() });
+        assert!(filters.accept(1, "2:2", sample(1.0)));
+        assert!(filters.accept(1, "2:2", sample(1.0)));
+    }
+
+    #[test]
+    fn deadbands_hold_back_small_changes() {
+        let now = Instant::now();
+        let mut filters = point_filters(PublishFilter { deadband: Some(0.5), ..PublishFilter::default() });
+        assert!(filters.accept_at(1, POINT, sample(20.0), now));
+        assert!(!filters.accept_at(1, POINT, sample(20.4), now));
+        assert!(filters.accept_at(1, POINT, sample(20.5), now));
+
+        let mut filters = point_filters(PublishFilter { deadband_percent: Some(10.0), ..PublishFilter::default() });
+        assert!(filters.accept_at(1, POINT, sample(50.0), now));
+        assert!(!filters.accept_at(1, POINT, sample(54.0), now));
+        assert!(filters.accept_at(1, POINT, sample(45.0), now));
+    }
+
+    #[test]
+    fn percent_deadband_of_zero_needs_a_change() {
+        let now = Instant::now();
+        let mut filters = point_filters(PublishFilter { deadband_percent: Some(10.0), ..PublishFilter::default() });
+        assert!(filters.accept_at(1, POINT, sample(0.0), now));
+        assert!(!filters.accept_at(1, POINT, sample(0.0), now));
+        assert!(filters.accept_at(1, POINT, sample(0.01), now));
+
+        let mut filters = point_filters(PublishFilter { deadband: Some(1.0), deadband_percent: Some(10.0), ..PublishFilter::default() });
+        assert!(filters.accept_at(1, POINT, sample(0.0), now));
+        assert!(!filters.accept_at(1, POINT, sample(0.5), now));
+        assert!(filters.accept_at(1, POINT, sample(1.0), now));
+    }
+
+    #[test]
+    fn status_changes_pass_the_value_filters() {
+        let now = Instant::now();
+        let mut filters = point_filters(PublishFilter { change_only: true, ..PublishFilter::default() });
+        assert!(filters.accept_at(1, POINT, sample(1.0), now));
+        assert!(!filters.accept_at(1, POINT, sample(1.0), now));
+        assert!(filters.accept_at(1, POINT, Sample { quality: Quality::Bad, ..sample(1.0) }, now));
+    }
+
+    #[test]
+    fn changes_within_the_minimum_interval_are_held() {
+        let start = Instant::now();
+        let mut filters = point_filters(PublishFilter { min_interval_secs: Some(10), ..PublishFilter::default() });
+        assert!(filters.accept_at(1, POINT, sample(1.0), start));
+        assert!(!filters.accept_at(1, POINT, sample(2.0), start + secs(2)));
+        assert!(!filters.accept_at(1, POINT, sample(3.0), start + secs(4)));
+        assert!(filters.due_at(start + secs(9)).is_empty());
+        // Only the latest held change is published
+        assert_eq!(filters.due_at(start + secs(10)), [(1, POINT.to_string(), sample(3.0))]);
+        assert!(filters.due_at(start + secs(30)).is_empty());
+    }
+
+    #[test]
+    fn heartbeats_republish_the_latest_value() {
+        let start = Instant::now();
+        let mut filters = point_filters(PublishFilter { change_only: true, heartbeat_secs: Some(60), ..PublishFilter::default() });
+        assert!(filters.due_at(start + secs(120)).is_empty());
+        assert!(filters.accept_at(1, POINT, sample(1.0), start));
+        assert!(!filters.accept_at(1, POINT, sample(1.0), start + secs(30)));
+        assert!(filters.due_at(start + secs(59)).is_empty());
+        assert_eq!(filters.due_at(start + secs(60)), [(1, POINT.to_string(), sample(1.0))]);
+        // The heartbeat restarts the period
+        assert!(filters.due_at(start + secs(100)).is_empty());
+        assert_eq!(filters.due_at(start + secs(120)).len(), 1);
+    }
+}
@@ -1 +1,4 @@
+pub mod batch;
 pub mod client;
+pub mod filter;
+pub mod outbox;
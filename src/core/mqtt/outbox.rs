@@ -0,0 +1,162 @@
+use crate::common::config::MqttConfig;
+use crate::common::types::MqttQueueStatus;
+use crate::core::persistence::db::{now_ms, Database};
+use anyhow::Result;
+use rumqttc::{AsyncClient, QoS};
+
+/// Messages handed to the client per replay step
+const REPLAY_CHUNK: u32 = 100;
+
+/// Durable queue of MQTT messages published while the broker is
+/// unreachable, kept in the database so it survives restarts. Messages are
+/// replayed in order once the broker is back, and new messages queue behind
+/// them until the queue is empty. Payloads keep the timestamps they were
+/// created with. The queue holds at most `queue_max_messages`, dropping the
+/// oldest first, and nothing older than `queue_max_age_secs`. The bridge
+/// reports its `status` as `MqttQueue` periodically and on connection changes.
+pub struct Outbox {
+    db: Database,
+    max_messages: u64,
+    max_age_ms: i64,
+    depth: u64,
+    oldest_ms: Option<i64>,
+    dropped: u64,
+}
+
+impl Outbox {
+    /// Opens the queue, picking up messages left from a previous run.
+    pub async fn open(db: Database, config: &MqttConfig) -> Self {
+        let (depth, oldest_ms) = db.outbox_stats().await.unwrap_or_else(|e| {
+            log::error!("Failed to read the MQTT queue: {}", e);
+            (0, None)
+        });
+        if depth > 0 {
+            log::info!("{} MQTT messages queued from a previous run", depth);
+        }
+        Self {
+            db,
+            max_messages: config.queue_max_messages.max(1),
+            max_age_ms: config.queue_max_age_secs as i64 * 1000,
+            depth,
+            oldest_ms,
+            dropped: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.depth == 0
+    }
+
+    pub async fn push(&mut self, topic: &str, payload: &str, retain: bool) {
+        let now = now_ms();
+        if let Err(e) = self.db.push_outbox(topic, payload, retain, now).await {
+            log::error!("Dropping MQTT message for {}, queueing failed: {}", topic, e);
+            self.dropped += 1;
+            return;
+        }
+        self.depth += 1;
+        self.oldest_ms.get_or_insert(now);
+        if self.depth > self.max_messages {
+            self.trim().await;
+        }
+    }
+
+    /// Hands the oldest queued messages to the client without waiting for
+    /// room in its request queue, and removes those it took. Returns how
+    /// many it took.
+    pub async fn replay(&mut self, client: &AsyncClient) -> Result<usize> {
+        let messages = self.db.load_outbox(REPLAY_CHUNK).await?;
+        let mut sent = 0;
+        for message in &messages {
+            if let Err(e) = client.try_publish(&message.topic, QoS::AtLeastOnce, message.retain, message.payload.clone()) {
+                log::debug!("Pausing MQTT replay: {}", e);
+                break;
+            }
+            sent += 1;
+        }
+        if let Some(last) = messages[..sent].last() {
+            self.db.delete_outbox_through(last.id).await?;
+        }
+        self.refresh().await?;
+        Ok(sent)
+    }
+
+    /// Drops messages beyond the queue's size and age limits.
+    pub async fn trim(&mut self) {
+        match self.db.trim_outbox(self.max_messages, now_ms() - self.max_age_ms).await {
+            Ok(0) => {}
+            Ok(dropped) => {
+                log::warn!("Dropped {} queued MQTT messages over the queue limits", dropped);
+                self.dropped += dropped;
+            }
+            Err(e) => log::error!("Failed to trim the MQTT queue: {}", e),
+        }
+        if let Err(e) = self.refresh().await {
+            log::error!("Failed to read the MQTT queue: {}", e);
+        }
+    }
+
+    pub fn status(&self, connected: bool) -> MqttQueueStatus {
+        MqttQueueStatus { connected, depth: self.depth, oldest_ms: self.oldest_ms, dropped: self.dropped }
+    }
+
+    async fn refresh(&mut self) -> Result<()> {
+        (self.depth, self.oldest_ms) = self.db.outbox_stats().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rumqttc::MqttOptions;
+
+    async fn outbox(max_messages: u64, max_age_secs: u64) -> Outbox {
+        let db = Database::open_in_memory().await.unwrap();
+        let config = MqttConfig { queue_max_messages: max_messages, queue_max_age_secs: max_age_secs, ..MqttConfig::default() };
+        Outbox::open(db, &config).await
+    }
+
+    async fn queued_topics(outbox: &Outbox) -> Vec<String> {
+        outbox.db.load_outbox(100).await.unwrap().into_iter().map(|m| m.topic).collect()
+    }
+
+    #[tokio::test]
+    async fn replays_in_queue_order() {
+        let mut outbox = outbox(100, 3600).await;
+        for topic in ["a", "b", "c"] {
+            outbox.push(topic, "{}", false).await;
+        }
+        // Room for two requests, so the replay stops after "b"
+        let (client, _eventloop) = AsyncClient::new(MqttOptions::new("test", "localhost", 1883), 2);
+        assert_eq!(outbox.replay(&client).await.unwrap(), 2);
+        assert_eq!(queued_topics(&outbox).await, ["c"]);
+        assert_eq!(outbox.status(true).depth, 1);
+
+        outbox.push("d", "{}", false).await;
+        assert_eq!(queued_topics(&outbox).await, ["c", "d"]);
+    }
+
+    #[tokio::test]
+    async fn drops_the_oldest_beyond_the_size_limit() {
+        let mut outbox = outbox(3, 3600).await;
+        for topic in ["a", "b", "c", "d", "e"] {
+            outbox.push(topic, "{}", false).await;
+        }
+        assert_eq!(queued_topics(&outbox).await, ["c", "d", "e"]);
+        let status = outbox.status(false);
+        assert_eq!((status.depth, status.dropped), (3, 2));
+    }
+
+    #[tokio::test]
+    async fn drops_messages_past_the_age_limit() {
+        let mut outbox = outbox(100, 60).await;
+        outbox.db.push_outbox("old", "{}", false, now_ms() - 120_000).await.unwrap();
+        outbox.push("new", "{}", false).await;
+        outbox.trim().await;
+        assert_eq!(queued_topics(&outbox).await, ["new"]);
+        let status = outbox.status(false);
+        assert_eq!((status.depth, status.dropped), (1, 1));
+        assert!(status.oldest_ms.is_some_and(|oldest| oldest > now_ms() - 60_000));
+    }
+}
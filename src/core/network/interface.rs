@@ -1,24 +1,108 @@
 use anyhow::Result;
-use if_addrs::get_if_addrs;
+use if_addrs::{get_if_addrs, IfAddr};
+use serde::Serialize;
 use std::net::IpAddr;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceAddress {
+    pub ip: IpAddr,
+    pub netmask: IpAddr,
+    pub broadcast: Option<IpAddr>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct NetworkInterface {
     pub name: String,
+    /// Primary address used for binding (first IPv4 address, if any).
     pub ip: IpAddr,
+    pub netmask: Option<IpAddr>,
+    pub broadcast: Option<IpAddr>,
+    pub mac_address: Option<String>,
+    pub is_loopback: bool,
+    pub is_up: bool,
+    pub addresses: Vec<InterfaceAddress>,
 }
 
-/// Discovers all available network interfaces with IPv4 addresses.
+/// Discovers all network interfaces, including loopback and IPv6-only ones.
+/// Addresses reported by the OS are grouped per interface name.
 pub fn list_interfaces() -> Result<Vec<NetworkInterface>> {
-    let addrs = get_if_addrs()?;
-    let interfaces = addrs
-        .into_iter()
-        .filter(|iface| !iface.is_loopback() && iface.addr.ip().is_ipv4())
-        .map(|iface| NetworkInterface {
-            name: iface.name,
-            ip: iface.addr.ip(),
-        })
-        .collect();
+    let mut interfaces: Vec<NetworkInterface> = Vec::new();
+
+    for iface in get_if_addrs()? {
+        let is_loopback = iface.is_loopback();
+        let address = match &iface.addr {
+            IfAddr::V4(v4) => InterfaceAddress {
+                ip: IpAddr::V4(v4.ip),
+                netmask: IpAddr::V4(v4.netmask),
+                broadcast: v4.broadcast.map(IpAddr::V4),
+            },
+            IfAddr::V6(v6) => InterfaceAddress {
+                ip: IpAddr::V6(v6.ip),
+                netmask: IpAddr::V6(v6.netmask),
+                broadcast: v6.broadcast.map(IpAddr::V6),
+            },
+        };
+
+        match interfaces.iter_mut().find(|i| i.name == iface.name) {
+            Some(existing) => {
+                existing.is_loopback |= is_loopback;
+                existing.addresses.push(address);
+            }
+            None => interfaces.push(NetworkInterface {
+                mac_address: read_mac_address(&iface.name),
+                is_up: read_is_up(&iface.name),
+                name: iface.name,
+                ip: address.ip,
+                netmask: None,
+                broadcast: None,
+                is_loopback,
+                addresses: vec![address],
+            }),
+        }
+    }
+
+    for iface in &mut interfaces {
+        let primary = iface
+            .addresses
+            .iter()
+            .find(|a| a.ip.is_ipv4())
+            .unwrap_or(&iface.addresses[0]);
+        iface.ip = primary.ip;
+        iface.netmask = Some(primary.netmask);
+        iface.broadcast = primary.broadcast;
+    }
 
     Ok(interfaces)
 }
+
+#[cfg(target_os = "linux")]
+fn read_mac_address(name: &str) -> Option<String> {
+    let mac = std::fs::read_to_string(format!("/sys/class/net/{}/address", name)).ok()?;
+    let mac = mac.trim();
+    if mac.is_empty() {
+        None
+    } else {
+        Some(mac.to_string())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_mac_address(_name: &str) -> Option<String> {
+    None
+}
+
+/// Reads IFF_UP from the kernel interface flags. Interfaces that report
+/// addresses are assumed up where the flags are not available.
+#[cfg(target_os = "linux")]
+fn read_is_up(name: &str) -> bool {
+    std::fs::read_to_string(format!("/sys/class/net/{}/flags", name))
+        .ok()
+        .and_then(|flags| u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).ok())
+        .map(|flags| flags & 0x1 != 0)
+        .unwrap_or(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_is_up(_name: &str) -> bool {
+    true
+}
@@ -0,0 +1,147 @@
+use crate::common::types::{BacnetObjectInfo, DeviceCapabilities};
+use crate::core::bacnet::codec::{self, BacnetValue};
+use crate::core::bacnet::discovery;
+use crate::core::bacnet::npdu::BacnetAddress;
+use crate::core::bacnet::object_id::{object_type_name, ObjectId};
+use crate::core::bacnet::properties;
+use crate::core::bacnet::rpm::ReadAccessSpec;
+use crate::core::bacnet::services::BacnetServices;
+use anyhow::Result;
+use bacnet_rs::object::{ObjectType, PropertyIdentifier};
+
+/// Estimated ReadPropertyMultiple-ACK size per object for the descriptive
+/// properties (two strings plus units or state texts).
+const DESCRIPTIVE_BYTES_PER_OBJECT: usize = 160;
+
+/// Reads a device's object list and the descriptive properties of every object
+/// (name, description, units or state texts). Uses ReadPropertyMultiple when
+/// the device supports it, in chunks sized to its max APDU, and falls back to
+/// ReadProperty if the device refuses it.
+pub async fn discover_objects(services: &BacnetServices, dest: &BacnetAddress, device_id: u32, caps: &DeviceCapabilities) -> Result<Vec<BacnetObjectInfo>> {
+    let object_ids = read_object_list(services, dest, device_id).await?;
+    let mut use_rpm = caps.read_property_multiple;
+    log::info!("Device {} has {} objects, reading names ({})", device_id, object_ids.len(), if use_rpm { "RPM" } else { "RP" });
+
+    let mut objects = Vec::with_capacity(object_ids.len());
+    for chunk in object_ids.chunks(caps.objects_per_request(DESCRIPTIVE_BYTES_PER_OBJECT)) {
+        let mut infos: Vec<BacnetObjectInfo> = chunk.iter().map(placeholder).collect();
+        if use_rpm {
+            let specs: Vec<ReadAccessSpec> = chunk
+                .iter()
+                .map(|id| ReadAccessSpec { object_id: *id, properties: descriptive_properties(id) })
+                .collect();
+            match services.read_property_multiple(dest, &specs).await {
+                Ok(results) => {
+                    for result in results {
+                        let Some(info) = infos.iter_mut().find(|i| i.object_type == result.object_type && i.instance == result.instance) else {
+                            continue;
+                        };
+                        for prop in result.results {
+                            match prop.value {
+                                Ok(data) => apply_property(info, prop.property, &data),
+                                Err(e) => log::debug!("Object {} {} property {} unreadable: {:?}", info.object_type, info.instance, prop.property, e),
+                            }
+                        }
+                    }
+                    objects.extend(infos);
+                    continue;
+                }
+                Err(e) => {
+                    log::warn!("ReadPropertyMultiple to device {} failed, falling back to ReadProperty: {}", device_id, e);
+                    use_rpm = false;
+                }
+            }
+        }
+        for (info, id) in infos.iter_mut().zip(chunk) {
+            for prop in descriptive_properties(id) {
+                match services.read_property(dest, *id, prop, None).await {
+                    Ok(data) => apply_property(info, prop, &data),
+                    Err(e) => log::debug!("Object {} property {} unreadable: {}", id, prop, e),
+                }
+            }
+        }
+        objects.extend(infos);
+    }
+    Ok(objects)
+}
+
+/// Reads Object_List whole, or element by element for devices that cannot
+/// return it in one unsegmented response.
+pub async fn read_object_list(services: &BacnetServices, dest: &BacnetAddress, device_id: u32) -> Result<Vec<ObjectId>> {
+    let device = ObjectId::device(device_id);
+    let object_list = PropertyIdentifier::ObjectList as u32;
+    match services.read_property(dest, device, object_list, None).await {
+        Ok(data) => discovery::parse_object_list(&data),
+        Err(e) => {
+            log::info!("Object list of device {} not readable at once ({}), reading by index", device_id, e);
+            let data = services.read_property(dest, device, object_list, Some(0)).await?;
+            let count = match codec::decode_application_value(&data)?.0 {
+                BacnetValue::Unsigned(count) => count as u32,
+                other => anyhow::bail!("Unexpected object list length {:?}", other),
+            };
+            let mut objects = Vec::with_capacity(count as usize);
+            for index in 1..=count {
+                let data = services.read_property(dest, device, object_list, Some(index)).await?;
+                objects.extend(discovery::parse_object_list(&data)?);
+            }
+            Ok(objects)
+        }
+    }
+}
+
+fn placeholder(id: &ObjectId) -> BacnetObjectInfo {
+    BacnetObjectInfo {
+        object_type: id.object_type,
+        instance: id.instance,
+        type_name: object_type_name(id.object_type),
+        name: format!("{} {}", object_type_name(id.object_type), id.instance),
+        description: None,
+        units: None,
+        state_text: None,
+        active_text: None,
+        inactive_text: None,
+    }
+}
+
+fn descriptive_properties(id: &ObjectId) -> Vec<u32> {
+    let mut props = vec![PropertyIdentifier::ObjectName as u32, properties::DESCRIPTION];
+    match id.known_type() {
+        Some(ObjectType::AnalogInput | ObjectType::AnalogOutput | ObjectType::AnalogValue) => props.push(properties::UNITS),
+        Some(ObjectType::BinaryInput | ObjectType::BinaryOutput | ObjectType::BinaryValue) => {
+            props.extend([PropertyIdentifier::ActiveText as u32, properties::INACTIVE_TEXT]);
+        }
+        Some(ObjectType::MultiStateInput | ObjectType::MultiStateOutput | ObjectType::MultiStateValue) => {
+            props.push(properties::STATE_TEXT);
+        }
+        _ => {}
+    }
+    props
+}
+
+fn apply_property(info: &mut BacnetObjectInfo, prop: u32, data: &[u8]) {
+    let (object_type, instance) = (info.object_type, info.instance);
+    let string = || match codec::decode_application_value(data) {
+        Ok((value, _)) => value.as_str().map(str::to_string),
+        Err(e) => {
+            log::debug!("Object {} {} property {} undecodable: {}", object_type, instance, prop, e);
+            None
+        }
+    };
+    match prop {
+        p if p == PropertyIdentifier::ObjectName as u32 => {
+            if let Some(name) = string() {
+                info.name = name;
+            }
+        }
+        properties::DESCRIPTION => info.description = string().filter(|d| !d.is_empty()),
+        p if p == PropertyIdentifier::ActiveText as u32 => info.active_text = string(),
+        properties::INACTIVE_TEXT => info.inactive_text = string(),
+        properties::UNITS => info.units = bacnet_rs::property::decode_units(data).map(|(units, _)| units),
+        properties::STATE_TEXT => {
+            info.state_text = codec::decode_application_values(data)
+                .ok()
+                .map(|values| values.iter().filter_map(|v| v.as_str().map(str::to_string)).collect());
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,181 @@
+use crate::common::config::OverrideConfig;
+use crate::common::types::{object_id_string, Event, PriorityArray, PriorityOverride, PrioritySlot};
+use crate::core::bacnet::codec;
+use crate::core::bacnet::npdu::{BacnetAddress, RoutingTable};
+use crate::core::bacnet::object_id::ObjectId;
+use crate::core::bacnet::properties;
+use crate::core::bacnet::services::BacnetServices;
+use crate::core::devices::DeviceMap;
+use crate::core::liveness::Liveness;
+use crate::core::persistence::db::{now_ms, Database};
+use anyhow::Result;
+use bacnet_rs::object::PropertyIdentifier;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+const PRIORITY_LEVELS: usize = 16;
+
+type Commanded = HashMap<(u32, ObjectId), HashSet<u8>>;
+
+/// Priorities the gateway itself commands, per device and object, so they
+/// are not reported as overrides. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct GatewayCommands {
+    commanded: Arc<Mutex<Commanded>>,
+}
+
+impl GatewayCommands {
+    /// Records a successful write at `priority`; a relinquish removes it.
+    pub fn record(&self, device_id: u32, object_id: ObjectId, priority: u8, relinquished: bool) {
+        let mut commanded = self.commanded.lock().unwrap();
+        let priorities = commanded.entry((device_id, object_id)).or_default();
+        if relinquished {
+            priorities.remove(&priority);
+        } else {
+            priorities.insert(priority);
+        }
+    }
+
+    pub fn priorities(&self, device_id: u32, object_id: ObjectId) -> HashSet<u8> {
+        self.commanded.lock().unwrap().get(&(device_id, object_id)).cloned().unwrap_or_default()
+    }
+}
+
+/// Reads Priority_Array and Relinquish_Default. A device without a readable
+/// Relinquish_Default still returns its priorities.
+pub async fn read_priority_array(services: &BacnetServices, dest: &BacnetAddress, device_id: u32, object_id: ObjectId, commands: &GatewayCommands) -> Result<PriorityArray> {
+    let data = services.read_property(dest, object_id, PropertyIdentifier::PriorityArray as u32, None).await?;
+    let values = codec::decode_application_values(&data)?;
+    if values.len() != PRIORITY_LEVELS {
+        anyhow::bail!("Priority_Array of {} has {} entries", object_id, values.len());
+    }
+    let ours = commands.priorities(device_id, object_id);
+    let slots = values
+        .iter()
+        .zip(1u8..)
+        .map(|(value, priority)| PrioritySlot { priority, value: value.as_f32(), by_gateway: ours.contains(&priority) })
+        .collect();
+    let relinquish_default = match services.read_property(dest, object_id, properties::RELINQUISH_DEFAULT, None).await {
+        Ok(data) => codec::decode_application_value(&data).ok().and_then(|(value, _)| value.as_f32()),
+        Err(e) => {
+            log::debug!("Relinquish_Default of {} on device {} unreadable: {}", object_id, device_id, e);
+            None
+        }
+    };
+    Ok(PriorityArray { slots, relinquish_default })
+}
+
+/// Periodically reads the Priority_Array of every mapped commandable point
+/// and reports manual priorities (`manual_priority_max` and higher) held by
+/// someone other than the gateway: `OverrideDetected` when one appears or
+/// changes value, `OverrideCleared` once it is relinquished.
+pub struct OverrideMonitor {
+    pub services: BacnetServices,
+    pub db: Database,
+    pub event_tx: broadcast::Sender<Event>,
+    pub routing_table: RoutingTable,
+    pub devices: DeviceMap,
+    pub liveness: Liveness,
+    pub commands: GatewayCommands,
+    pub config: OverrideConfig,
+}
+
+impl OverrideMonitor {
+    pub async fn run(self) {
+        if self.config.check_interval_secs == 0 {
+            log::info!("Override detection disabled");
+            return;
+        }
+        let mut active: HashMap<(u32, ObjectId), Vec<PriorityOverride>> = HashMap::new();
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.check_interval_secs));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let points = match self.db.load_points().await {
+                Ok(points) => points,
+                Err(e) => {
+                    log::error!("Failed to load point map: {}", e);
+                    continue;
+                }
+            };
+            let objects: HashSet<(u32, ObjectId)> = points
+                .into_iter()
+                .filter(|p| p.enabled)
+                .map(|p| (p.device_id, ObjectId::new(p.object_type, p.instance)))
+                .filter(|(_, object_id)| object_id.is_commandable())
+                .collect();
+            // Unmapped points are no longer watched
+            active.retain(|key, _| objects.contains(key));
+            for (device_id, object_id) in objects {
+                if let Some(found) = self.check(device_id, object_id).await {
+                    let previous = active.remove(&(device_id, object_id)).unwrap_or_default();
+                    active.insert((device_id, object_id), self.report(previous, found));
+                }
+            }
+        }
+    }
+
+    /// Manual priorities held by others, or `None` if the point could not be read.
+    async fn check(&self, device_id: u32, object_id: ObjectId) -> Option<Vec<PriorityOverride>> {
+        if !self.liveness.is_online(device_id) {
+            return None;
+        }
+        let dest = {
+            let devices = self.devices.lock().await;
+            let device = devices.get(&device_id)?;
+            BacnetAddress::resolve(&device.address, device.network, device.mac.as_deref(), &self.routing_table)?
+        };
+        let result = read_priority_array(&self.services, &dest, device_id, object_id, &self.commands).await;
+        self.liveness.record_result(device_id, &result);
+        let priority_array = match result {
+            Ok(priority_array) => priority_array,
+            Err(e) => {
+                log::debug!("Priority_Array of {} on device {} unreadable: {}", object_id, device_id, e);
+                return None;
+            }
+        };
+        let in_control = priority_array.active().map(|slot| slot.priority);
+        let overrides = priority_array
+            .slots
+            .iter()
+            .filter(|slot| slot.priority <= self.config.manual_priority_max && !slot.by_gateway)
+            .filter_map(|slot| {
+                Some(PriorityOverride {
+                    device_id,
+                    object_id: object_id_string(object_id.object_type, object_id.instance),
+                    priority: slot.priority,
+                    value: slot.value?,
+                    in_control: in_control == Some(slot.priority),
+                    since_ms: now_ms(),
+                })
+            })
+            .collect();
+        Some(overrides)
+    }
+
+    /// Emits the differences between the previous and current overrides of a
+    /// point and returns the current ones, keeping when each was first seen.
+    fn report(&self, previous: Vec<PriorityOverride>, mut current: Vec<PriorityOverride>) -> Vec<PriorityOverride> {
+        for found in current.iter_mut() {
+            match previous.iter().find(|p| p.priority == found.priority) {
+                Some(known) => {
+                    found.since_ms = known.since_ms;
+                    if known.value != found.value || known.in_control != found.in_control {
+                        let _ = self.event_tx.send(Event::OverrideDetected(found.clone()));
+                    }
+                }
+                None => {
+                    log::warn!("Device {} {} is overridden at priority {} ({})", found.device_id, found.object_id, found.priority, found.value);
+                    let _ = self.event_tx.send(Event::OverrideDetected(found.clone()));
+                }
+            }
+        }
+        for cleared in previous.into_iter().filter(|p| !current.iter().any(|c| c.priority == p.priority)) {
+            log::info!("Override of device {} {} at priority {} cleared", cleared.device_id, cleared.object_id, cleared.priority);
+            let _ = self.event_tx.send(Event::OverrideCleared(cleared));
+        }
+        current
+    }
+}
@@ -0,0 +1,608 @@
+use crate::common::types::{AuditEntry, AuditFilter, BacnetDevice, BacnetObjectInfo, HistoryAggregation, HistoryPolicy, HistorySample, HistoryValue, PointConfig, PublishFilter, QueuedMessage, TimedOverride, WritePolicy};
+use crate::core::persistence::schema::{ADDED_COLUMNS, SCHEMA};
+use anyhow::Result;
+use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous};
+use sqlx::Row;
+use std::str::FromStr;
+
+/// Handle to the gateway's SQLite database. Cheap to clone.
+#[derive(Clone)]
+pub struct Database {
+    pool: SqlitePool,
+}
+
+pub fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+impl Database {
+    /// Opens (creating if needed) the database at `path` and applies the schema.
+    pub async fn open(path: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path))?
+            .create_if_missing(true)
+            // The historian inserts continuously; WAL keeps readers off its back
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal);
+        let db = Self::connect(options, 4).await?;
+        log::info!("Opened database {}", path);
+        Ok(db)
+    }
+
+    /// A private in-memory database for tests. It has a single connection,
+    /// since every connection to `:memory:` opens a database of its own.
+    #[cfg(test)]
+    pub async fn open_in_memory() -> Result<Self> {
+        Self::connect(SqliteConnectOptions::from_str("sqlite::memory:")?, 1).await
+    }
+
+    async fn connect(options: SqliteConnectOptions, max_connections: u32) -> Result<Self> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        for statement in ADDED_COLUMNS {
+            if let Err(e) = sqlx::query(statement).execute(&pool).await {
+                if !e.to_string().contains("duplicate column") {
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(Self { pool })
+    }
+
+    pub async fn upsert_device(&self, device: &BacnetDevice) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO devices (instance, data, updated_at) VALUES (?, ?, ?)
+             ON CONFLICT(instance) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at",
+        )
+        .bind(device.instance as i64)
+        .bind(serde_json::to_string(device)?)
+        .bind(now_ms())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn load_devices(&self) -> Result<Vec<BacnetDevice>> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT data FROM devices ORDER BY instance")
+            .fetch_all(&self.pool)
+            .await?;
+        let mut devices = Vec::with_capacity(rows.len());
+        for (data,) in rows {
+            match serde_json::from_str(&data) {
+                Ok(device) => devices.push(device),
+                Err(e) => log::warn!("Skipping unreadable device record: {}", e),
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Deletes a device and its stored object list.
+    pub async fn delete_device(&self, instance: u32) -> Result<()> {
+        sqlx::query("DELETE FROM devices WHERE instance = ?").bind(instance as i64).execute(&self.pool).await?;
+        sqlx::query("DELETE FROM device_objects WHERE device_id = ?").bind(instance as i64).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Replaces the stored object list of a device.
+    pub async fn save_objects(&self, device_id: u32, objects: &[BacnetObjectInfo]) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO device_objects (device_id, data, updated_at) VALUES (?, ?, ?)
+             ON CONFLICT(device_id) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at",
+        )
+        .bind(device_id as i64)
+        .bind(serde_json::to_string(objects)?)
+        .bind(now_ms())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The stored object list of a device, `None` if it was never read.
+    pub async fn load_objects(&self, device_id: u32) -> Result<Option<Vec<BacnetObjectInfo>>> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT data FROM device_objects WHERE device_id = ?")
+            .bind(device_id as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some((data,)) => Ok(Some(serde_json::from_str(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Inserts a point, or updates the existing point for the same object.
+    /// Returns the point's ID.
+    pub async fn upsert_point(&self, point: &PointConfig) -> Result<i64> {
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO points (device_id, object_type, instance, name, poll_interval_secs, cov, enabled, write_policy, history_policy, publish_filter)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(device_id, object_type, instance) DO UPDATE SET
+                name = excluded.name, poll_interval_secs = excluded.poll_interval_secs,
+                cov = excluded.cov, enabled = excluded.enabled, write_policy = excluded.write_policy,
+                history_policy = excluded.history_policy, publish_filter = excluded.publish_filter
+             RETURNING id",
+        )
+        .bind(point.device_id as i64)
+        .bind(point.object_type as i64)
+        .bind(point.instance as i64)
+        .bind(&point.name)
+        .bind(point.poll_interval_secs as i64)
+        .bind(point.cov)
+        .bind(point.enabled)
+        .bind(serde_json::to_string(&point.write)?)
+        .bind(serde_json::to_string(&point.history)?)
+        .bind(serde_json::to_string(&point.publish)?)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    /// Returns true if a point was deleted. Its history goes with it.
+    pub async fn delete_point(&self, id: i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query("DELETE FROM points WHERE id = ?").bind(id).execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM history_raw WHERE point_id = ?").bind(id).execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM history_buckets WHERE point_id = ?").bind(id).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn load_points(&self) -> Result<Vec<PointConfig>> {
+        let rows = sqlx::query(
+            "SELECT id, device_id, object_type, instance, name, poll_interval_secs, cov, enabled, write_policy, history_policy, publish_filter FROM points ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(point_from_row).collect())
+    }
+
+    /// The point mapped to an object, if any.
+    pub async fn find_point(&self, device_id: u32, object_type: u16, instance: u32) -> Result<Option<PointConfig>> {
+        let row = sqlx::query(
+            "SELECT id, device_id, object_type, instance, name, poll_interval_secs, cov, enabled, write_policy, history_policy, publish_filter FROM points
+             WHERE device_id = ? AND object_type = ? AND instance = ?",
+        )
+        .bind(device_id as i64)
+        .bind(object_type as i64)
+        .bind(instance as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.as_ref().map(point_from_row))
+    }
+
+    /// Appends an entry to the audit log and returns its ID.
+    pub async fn record_audit(&self, entry: &AuditEntry) -> Result<i64> {
+        let result = sqlx::query(
+            "INSERT INTO audit_log (timestamp, action, source, client_id, device_id, object_id, property, target, value, priority, result, detail)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(entry.timestamp_ms)
+        .bind(enum_text(&entry.action)?)
+        .bind(enum_text(&entry.source)?)
+        .bind(&entry.client_id)
+        .bind(entry.device_id.map(|id| id as i64))
+        .bind(&entry.object_id)
+        .bind(entry.property.map(|p| p as i64))
+        .bind(&entry.target)
+        .bind(&entry.value)
+        .bind(entry.priority.map(|p| p as i64))
+        .bind(enum_text(&entry.result)?)
+        .bind(&entry.detail)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Audit entries matching `filter`, newest first, and how many match in
+    /// total. `limit` `None` returns all of them.
+    pub async fn query_audit(&self, filter: &AuditFilter, limit: Option<u32>, offset: u32) -> Result<(u64, Vec<AuditEntry>)> {
+        let mut count = sqlx::QueryBuilder::<Sqlite>::new("SELECT COUNT(*) FROM audit_log WHERE 1 = 1");
+        push_audit_filter(&mut count, filter)?;
+        let (total,): (i64,) = count.build_query_as().fetch_one(&self.pool).await?;
+
+        let mut query = sqlx::QueryBuilder::<Sqlite>::new(
+            "SELECT id, timestamp, action, source, client_id, device_id, object_id, property, target, value, priority, result, detail
+             FROM audit_log WHERE 1 = 1",
+        );
+        push_audit_filter(&mut query, filter)?;
+        query.push(" ORDER BY id DESC LIMIT ");
+        // SQLite treats a negative limit as none
+        query.push_bind(limit.map(i64::from).unwrap_or(-1));
+        query.push(" OFFSET ");
+        query.push_bind(offset as i64);
+        let rows = query.build().fetch_all(&self.pool).await?;
+        let entries = rows
+            .iter()
+            .map(|row| {
+                Ok(AuditEntry {
+                    id: row.get("id"),
+                    timestamp_ms: row.get("timestamp"),
+                    action: enum_from_text(row.get("action"))?,
+                    source: enum_from_text(row.get("source"))?,
+                    client_id: row.get("client_id"),
+                    device_id: row.get::<Option<i64>, _>("device_id").map(|id| id as u32),
+                    object_id: row.get("object_id"),
+                    property: row.get::<Option<i64>, _>("property").map(|p| p as u32),
+                    target: row.get("target"),
+                    value: row.get("value"),
+                    priority: row.get::<Option<i64>, _>("priority").map(|p| p as u8),
+                    result: enum_from_text(row.get("result"))?,
+                    detail: row.get("detail"),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok((total as u64, entries))
+    }
+
+    /// Appends raw history samples in one transaction. A sample with the
+    /// timestamp of a stored one replaces it.
+    pub async fn insert_history(&self, samples: &[HistorySample]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        // Four bound values per row, well below SQLite's variable limit
+        for chunk in samples.chunks(1000) {
+            let mut query = sqlx::QueryBuilder::<Sqlite>::new("INSERT OR REPLACE INTO history_raw (point_id, timestamp, value, quality) ");
+            let mut rows = Vec::with_capacity(chunk.len());
+            for sample in chunk {
+                rows.push((sample, enum_text(&sample.quality)?));
+            }
+            query.push_values(rows, |mut row, (sample, quality)| {
+                row.push_bind(sample.point_id).push_bind(sample.timestamp_ms).push_bind(sample.value).push_bind(quality);
+            });
+            query.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Moves a point's raw samples older than `before_ms` into buckets of
+    /// `bucket_ms` holding their count, min, max, avg and last value. Samples
+    /// of bad quality are dropped. Returns the number of samples moved.
+    pub async fn downsample_history(&self, point_id: i64, before_ms: i64, bucket_ms: i64) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO history_buckets (point_id, start, count, min, max, avg, last)
+             SELECT point_id, start, COUNT(*), MIN(value), MAX(value), AVG(value), MAX(last) FROM (
+                SELECT point_id, timestamp / ?1 * ?1 AS start, value,
+                    LAST_VALUE(value) OVER (PARTITION BY timestamp / ?1 ORDER BY timestamp
+                        ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING) AS last
+                FROM history_raw WHERE point_id = ?2 AND timestamp < ?3 AND quality != 'bad'
+             ) WHERE true GROUP BY start
+             ON CONFLICT(point_id, start) DO UPDATE SET
+                avg = (avg * count + excluded.avg * excluded.count) / (count + excluded.count),
+                count = count + excluded.count,
+                min = MIN(min, excluded.min),
+                max = MAX(max, excluded.max),
+                last = excluded.last",
+        )
+        .bind(bucket_ms)
+        .bind(point_id)
+        .bind(before_ms)
+        .execute(&mut *tx)
+        .await?;
+        let moved = sqlx::query("DELETE FROM history_raw WHERE point_id = ? AND timestamp < ?")
+            .bind(point_id)
+            .bind(before_ms)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(moved.rows_affected())
+    }
+
+    /// A point's raw samples between `from_ms` and `to_ms` inclusive, oldest
+    /// first. Downsampled history has no raw samples.
+    pub async fn query_history_raw(&self, point_id: i64, from_ms: i64, to_ms: i64) -> Result<Vec<HistoryValue>> {
+        let rows = sqlx::query("SELECT timestamp, value, quality FROM history_raw WHERE point_id = ? AND timestamp >= ? AND timestamp <= ? ORDER BY timestamp")
+            .bind(point_id)
+            .bind(from_ms)
+            .bind(to_ms)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(HistoryValue {
+                    timestamp_ms: row.get("timestamp"),
+                    value: row.get::<f64, _>("value") as f32,
+                    quality: Some(enum_from_text(row.get("quality"))?),
+                    count: None,
+                })
+            })
+            .collect()
+    }
+
+    /// A point's history between `from_ms` and `to_ms` inclusive, aggregated
+    /// per `interval_ms` (aligned to the epoch), oldest first. Raw samples
+    /// and downsampled buckets are combined; bad-quality samples are left out.
+    /// `aggregation` must not be `Raw`.
+    pub async fn query_history(&self, point_id: i64, from_ms: i64, to_ms: i64, interval_ms: i64, aggregation: HistoryAggregation) -> Result<Vec<HistoryValue>> {
+        let value = match aggregation {
+            HistoryAggregation::Avg => "avg",
+            HistoryAggregation::Min => "min",
+            HistoryAggregation::Max => "max",
+            HistoryAggregation::Last => "last",
+            HistoryAggregation::Raw => anyhow::bail!("Raw history is not aggregated"),
+        };
+        let rows = sqlx::query(
+            "SELECT start, SUM(count) AS count, MIN(min) AS min, MAX(max) AS max,
+                SUM(avg * count) / SUM(count) AS avg, MAX(last) AS last
+             FROM (
+                SELECT timestamp / ?1 * ?1 AS start, count, min, max, avg,
+                    LAST_VALUE(last) OVER (PARTITION BY timestamp / ?1 ORDER BY timestamp
+                        ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING) AS last
+                FROM (
+                    SELECT timestamp, 1 AS count, value AS min, value AS max, value AS avg, value AS last FROM history_raw
+                    WHERE point_id = ?2 AND timestamp >= ?3 AND timestamp <= ?4 AND quality != 'bad'
+                    UNION ALL
+                    SELECT start, count, min, max, avg, last FROM history_buckets
+                    WHERE point_id = ?2 AND start >= ?3 AND start <= ?4
+                )
+             ) GROUP BY start ORDER BY start",
+        )
+        .bind(interval_ms)
+        .bind(point_id)
+        .bind(from_ms)
+        .bind(to_ms)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| HistoryValue {
+                timestamp_ms: row.get("start"),
+                value: row.get::<f64, _>(value) as f32,
+                quality: None,
+                count: Some(row.get::<i64, _>("count") as u64),
+            })
+            .collect())
+    }
+
+    /// Deletes a point's history older than `before_ms`.
+    pub async fn expire_history(&self, point_id: i64, before_ms: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM history_raw WHERE point_id = ? AND timestamp < ?").bind(point_id).bind(before_ms).execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM history_buckets WHERE point_id = ? AND start < ?").bind(point_id).bind(before_ms).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Queues an MQTT message for when the broker is reachable again.
+    pub async fn push_outbox(&self, topic: &str, payload: &str, retain: bool, created_ms: i64) -> Result<()> {
+        sqlx::query("INSERT INTO mqtt_outbox (topic, payload, retain, created_at) VALUES (?, ?, ?, ?)")
+            .bind(topic)
+            .bind(payload)
+            .bind(retain)
+            .bind(created_ms)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// The oldest queued MQTT messages, in queue order.
+    pub async fn load_outbox(&self, limit: u32) -> Result<Vec<QueuedMessage>> {
+        let rows = sqlx::query("SELECT id, topic, payload, retain FROM mqtt_outbox ORDER BY id LIMIT ?")
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| QueuedMessage {
+                id: row.get("id"),
+                topic: row.get("topic"),
+                payload: row.get("payload"),
+                retain: row.get("retain"),
+            })
+            .collect())
+    }
+
+    /// Removes queued MQTT messages up to and including `id`.
+    pub async fn delete_outbox_through(&self, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM mqtt_outbox WHERE id <= ?").bind(id).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Drops queued MQTT messages created before `before_ms` and the oldest
+    /// beyond `max_messages`. Returns how many were dropped.
+    pub async fn trim_outbox(&self, max_messages: u64, before_ms: i64) -> Result<u64> {
+        let aged = sqlx::query("DELETE FROM mqtt_outbox WHERE created_at < ?").bind(before_ms).execute(&self.pool).await?;
+        let excess = sqlx::query("DELETE FROM mqtt_outbox WHERE id <= (SELECT MAX(id) FROM mqtt_outbox) - ?")
+            .bind(max_messages as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(aged.rows_affected() + excess.rows_affected())
+    }
+
+    /// Number of queued MQTT messages and creation time of the oldest.
+    pub async fn outbox_stats(&self) -> Result<(u64, Option<i64>)> {
+        let (count, oldest): (i64, Option<i64>) = sqlx::query_as("SELECT COUNT(*), MIN(created_at) FROM mqtt_outbox").fetch_one(&self.pool).await?;
+        Ok((count as u64, oldest))
+    }
+
+    /// Stores a timed override, replacing a pending one at the same priority
+    /// of the same object. Returns it with its ID.
+    pub async fn upsert_timed_override(&self, timed: &TimedOverride) -> Result<TimedOverride> {
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO timed_overrides (device_id, object_type, instance, priority, value, created_at, expires_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(device_id, object_type, instance, priority) DO UPDATE SET
+                value = excluded.value, created_at = excluded.created_at, expires_at = excluded.expires_at
+             RETURNING id",
+        )
+        .bind(timed.device_id as i64)
+        .bind(timed.object_type as i64)
+        .bind(timed.instance as i64)
+        .bind(timed.priority as i64)
+        .bind(&timed.value)
+        .bind(timed.created_ms)
+        .bind(timed.expires_ms)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(TimedOverride { id, ..timed.clone() })
+    }
+
+    /// Pending timed overrides, soonest to expire first.
+    pub async fn load_timed_overrides(&self) -> Result<Vec<TimedOverride>> {
+        let rows = sqlx::query(
+            "SELECT id, device_id, object_type, instance, priority, value, created_at, expires_at FROM timed_overrides ORDER BY expires_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| TimedOverride {
+                id: row.get("id"),
+                device_id: row.get::<i64, _>("device_id") as u32,
+                object_type: row.get::<i64, _>("object_type") as u16,
+                instance: row.get::<i64, _>("instance") as u32,
+                priority: row.get::<i64, _>("priority") as u8,
+                value: row.get("value"),
+                created_ms: row.get("created_at"),
+                expires_ms: row.get("expires_at"),
+            })
+            .collect())
+    }
+
+    pub async fn set_timed_override_expiry(&self, id: i64, expires_ms: i64) -> Result<()> {
+        sqlx::query("UPDATE timed_overrides SET expires_at = ? WHERE id = ?").bind(expires_ms).bind(id).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Returns true if a timed override was deleted.
+    pub async fn delete_timed_override(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM timed_overrides WHERE id = ?").bind(id).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Drops the pending relinquish at a priority, for writes that replace it.
+    /// Returns true if one was pending.
+    pub async fn delete_timed_override_at(&self, device_id: u32, object_type: u16, instance: u32, priority: u8) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM timed_overrides WHERE device_id = ? AND object_type = ? AND instance = ? AND priority = ?")
+            .bind(device_id as i64)
+            .bind(object_type as i64)
+            .bind(instance as i64)
+            .bind(priority as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+fn point_from_row(row: &sqlx::sqlite::SqliteRow) -> PointConfig {
+    let write_policy: String = row.get("write_policy");
+    let history_policy: String = row.get("history_policy");
+    let publish_filter: String = row.get("publish_filter");
+    PointConfig {
+        id: row.get("id"),
+        device_id: row.get::<i64, _>("device_id") as u32,
+        object_type: row.get::<i64, _>("object_type") as u16,
+        instance: row.get::<i64, _>("instance") as u32,
+        name: row.get("name"),
+        poll_interval_secs: row.get::<i64, _>("poll_interval_secs") as u32,
+        cov: row.get("cov"),
+        enabled: row.get("enabled"),
+        // An unreadable policy leaves the point read-only
+        write: serde_json::from_str::<WritePolicy>(&write_policy).unwrap_or_default(),
+        history: serde_json::from_str::<HistoryPolicy>(&history_policy).unwrap_or_default(),
+        publish: serde_json::from_str::<PublishFilter>(&publish_filter).unwrap_or_default(),
+    }
+}
+
+/// The serde name of a unit enum variant, as stored in text columns.
+fn enum_text<T: serde::Serialize>(value: &T) -> Result<String> {
+    match serde_json::to_value(value)? {
+        serde_json::Value::String(text) => Ok(text),
+        other => anyhow::bail!("Not a unit variant: {}", other),
+    }
+}
+
+fn enum_from_text<T: serde::de::DeserializeOwned>(text: String) -> Result<T> {
+    Ok(serde_json::from_value(serde_json::Value::String(text))?)
+}
+
+fn push_audit_filter(query: &mut sqlx::QueryBuilder<'_, Sqlite>, filter: &AuditFilter) -> Result<()> {
+    if let Some(from) = filter.from {
+        query.push(" AND timestamp >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        query.push(" AND timestamp <= ").push_bind(to);
+    }
+    if let Some(action) = &filter.action {
+        query.push(" AND action = ").push_bind(enum_text(action)?);
+    }
+    if let Some(source) = &filter.source {
+        query.push(" AND source = ").push_bind(enum_text(source)?);
+    }
+    if let Some(result) = &filter.result {
+        query.push(" AND result = ").push_bind(enum_text(result)?);
+    }
+    if let Some(device_id) = filter.device_id {
+        query.push(" AND device_id = ").push_bind(device_id as i64);
+    }
+    if let Some(client_id) = &filter.client_id {
+        query.push(" AND client_id = ").push_bind(client_id.clone());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::types::Quality;
+
+    const POINT: i64 = 1;
+    const MINUTE: i64 = 60_000;
+
+    fn samples(point_id: i64, values: &[(i64, f32)], quality: Quality) -> Vec<HistorySample> {
+        values.iter().map(|&(timestamp_ms, value)| HistorySample { point_id, timestamp_ms, value, quality }).collect()
+    }
+
+    /// Buckets of 10s at 0 (1, 5, 3, 4) and at 90s (8, 9), raw samples at
+    /// 30s, 59.999s and 60s, and samples that must not count: a bad one, one
+    /// past the range and one of another point.
+    async fn history() -> Database {
+        let db = Database::open_in_memory().await.unwrap();
+        db.insert_history(&samples(POINT, &[(0, 1.0), (1_000, 5.0), (2_000, 3.0), (3_000, 4.0), (90_000, 8.0), (95_000, 9.0)], Quality::Good))
+            .await
+            .unwrap();
+        assert_eq!(db.downsample_history(POINT, 100_000, 10_000).await.unwrap(), 6);
+        db.insert_history(&samples(POINT, &[(30_000, 10.0), (59_999, 2.0), (60_000, 7.0), (120_000, 50.0)], Quality::Good)).await.unwrap();
+        db.insert_history(&samples(POINT, &[(50_000, 100.0)], Quality::Bad)).await.unwrap();
+        db.insert_history(&samples(2, &[(30_000, 1000.0)], Quality::Good)).await.unwrap();
+        db
+    }
+
+    async fn query(db: &Database, aggregation: HistoryAggregation) -> Vec<(i64, f32, Option<u64>)> {
+        let values = db.query_history(POINT, 0, 2 * MINUTE - 1, MINUTE, aggregation).await.unwrap();
+        values.into_iter().map(|v| (v.timestamp_ms, v.value, v.count)).collect()
+    }
+
+    #[tokio::test]
+    async fn aggregates_raw_samples_and_buckets_per_interval() {
+        let db = history().await;
+        assert_eq!(query(&db, HistoryAggregation::Min).await, [(0, 1.0, Some(6)), (MINUTE, 7.0, Some(3))]);
+        assert_eq!(query(&db, HistoryAggregation::Max).await, [(0, 10.0, Some(6)), (MINUTE, 9.0, Some(3))]);
+        // The latest sample or bucket of each interval
+        assert_eq!(query(&db, HistoryAggregation::Last).await, [(0, 2.0, Some(6)), (MINUTE, 9.0, Some(3))]);
+
+        // Buckets weigh by their sample count: (13 + 10 + 2) / 6 and (7 + 17) / 3
+        let avg = query(&db, HistoryAggregation::Avg).await;
+        assert_eq!(avg.len(), 2);
+        assert!((avg[0].1 - 25.0 / 6.0).abs() < 1e-5, "{:?}", avg);
+        assert!((avg[1].1 - 8.0).abs() < 1e-5, "{:?}", avg);
+    }
+
+    #[tokio::test]
+    async fn keeps_raw_samples_with_their_quality() {
+        let db = history().await;
+        let raw = db.query_history_raw(POINT, 0, 2 * MINUTE - 1).await.unwrap();
+        let raw: Vec<_> = raw.iter().map(|v| (v.timestamp_ms, v.value, v.quality)).collect();
+        assert_eq!(
+            raw,
+            [(30_000, 10.0, Some(Quality::Good)), (50_000, 100.0, Some(Quality::Bad)), (59_999, 2.0, Some(Quality::Good)), (60_000, 7.0, Some(Quality::Good))]
+        );
+        assert!(db.query_history(POINT, 0, MINUTE, MINUTE, HistoryAggregation::Raw).await.is_err());
+    }
+}
@@ -1 +1,2 @@
-pub mod schema; pub mod db;
+pub mod db;
+pub mod schema;
@@ -0,0 +1,85 @@
+/// Schema statements, applied in order on every start. Each statement must be
+/// idempotent (`IF NOT EXISTS`).
+pub const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS devices (
+        instance INTEGER PRIMARY KEY,
+        data TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS points (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        device_id INTEGER NOT NULL,
+        object_type INTEGER NOT NULL,
+        instance INTEGER NOT NULL,
+        name TEXT NOT NULL DEFAULT '',
+        poll_interval_secs INTEGER NOT NULL,
+        cov INTEGER NOT NULL,
+        enabled INTEGER NOT NULL,
+        UNIQUE (device_id, object_type, instance)
+    )",
+    "CREATE TABLE IF NOT EXISTS device_objects (
+        device_id INTEGER PRIMARY KEY,
+        data TEXT NOT NULL,
+        updated_at INTEGER NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS timed_overrides (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        device_id INTEGER NOT NULL,
+        object_type INTEGER NOT NULL,
+        instance INTEGER NOT NULL,
+        priority INTEGER NOT NULL,
+        value TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL,
+        UNIQUE (device_id, object_type, instance, priority)
+    )",
+    "CREATE TABLE IF NOT EXISTS audit_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp INTEGER NOT NULL,
+        action TEXT NOT NULL,
+        device_id INTEGER,
+        object_id TEXT,
+        property INTEGER,
+        value TEXT,
+        priority INTEGER,
+        result TEXT NOT NULL,
+        detail TEXT
+    )",
+    "CREATE INDEX IF NOT EXISTS audit_log_timestamp ON audit_log (timestamp)",
+    "CREATE TABLE IF NOT EXISTS history_raw (
+        point_id INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        value REAL NOT NULL,
+        quality TEXT NOT NULL,
+        PRIMARY KEY (point_id, timestamp)
+    ) WITHOUT ROWID",
+    "CREATE TABLE IF NOT EXISTS history_buckets (
+        point_id INTEGER NOT NULL,
+        start INTEGER NOT NULL,
+        count INTEGER NOT NULL,
+        min REAL NOT NULL,
+        max REAL NOT NULL,
+        avg REAL NOT NULL,
+        last REAL NOT NULL,
+        PRIMARY KEY (point_id, start)
+    ) WITHOUT ROWID",
+    "CREATE TABLE IF NOT EXISTS mqtt_outbox (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        topic TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        retain INTEGER NOT NULL,
+        created_at INTEGER NOT NULL
+    )",
+];
+
+/// Columns added to existing tables, applied after `SCHEMA`. SQLite cannot
+/// add a column only if it is missing, so "duplicate column" errors are
+/// ignored.
+pub const ADDED_COLUMNS: &[&str] = &[
+    "ALTER TABLE points ADD COLUMN write_policy TEXT NOT NULL DEFAULT '{}'",
+    "ALTER TABLE audit_log ADD COLUMN source TEXT NOT NULL DEFAULT 'gateway'",
+    "ALTER TABLE audit_log ADD COLUMN client_id TEXT",
+    "ALTER TABLE audit_log ADD COLUMN target TEXT",
+    "ALTER TABLE points ADD COLUMN history_policy TEXT NOT NULL DEFAULT '{}'",
+    "ALTER TABLE points ADD COLUMN publish_filter TEXT NOT NULL DEFAULT '{}'",
+];
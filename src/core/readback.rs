@@ -0,0 +1,56 @@
+use crate::common::types::{ReadbackSource, WriteVerification};
+use crate::core::bacnet::codec;
+use crate::core::bacnet::npdu::BacnetAddress;
+use crate::core::bacnet::object_id::ObjectId;
+use crate::core::bacnet::services::BacnetServices;
+use anyhow::Result;
+use bacnet_rs::object::{PropertyIdentifier, PropertyValue};
+use std::time::Instant;
+
+/// Absolute difference accepted between written and read-back values
+pub const DEFAULT_TOLERANCE: f32 = 0.01;
+
+/// Reads a written value back and compares it with `expected`: Real values
+/// within `tolerance`, enumerated and unsigned values exactly and of the
+/// same datatype. A write at a priority of a commandable Present_Value is
+/// checked in its Priority_Array slot, since a higher priority may hold the
+/// present value; anything else is checked by reading the property itself.
+pub async fn verify_write(services: &BacnetServices, dest: &BacnetAddress, object_id: ObjectId, property: u32, priority: Option<u8>, expected: &PropertyValue, tolerance: f32) -> WriteVerification {
+    let slot = priority.filter(|_| property == PropertyIdentifier::PresentValue as u32 && object_id.is_commandable());
+    let (source, read_property, array_index) = match slot {
+        Some(priority) => (ReadbackSource::PriorityArray, PropertyIdentifier::PriorityArray as u32, Some(priority as u32)),
+        None => (ReadbackSource::Property, property, None),
+    };
+    let started = Instant::now();
+    let result = read_value(services, dest, object_id, read_property, array_index).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let (readback, error) = match result {
+        Ok(readback) => (readback, None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let passed = match (expected, &readback) {
+        (PropertyValue::Enumerated(expected), Some(codec::BacnetValue::Enumerated(v))) => v == expected,
+        (PropertyValue::UnsignedInteger(expected), Some(codec::BacnetValue::Unsigned(v))) => *v == *expected as u64,
+        (PropertyValue::Real(expected), Some(value)) => value.as_f32().is_some_and(|v| (v - expected).abs() <= tolerance),
+        _ => false,
+    };
+    WriteVerification {
+        source,
+        readback: readback.as_ref().and_then(|v| v.as_f32()),
+        tolerance,
+        passed,
+        latency_ms,
+        error,
+    }
+}
+
+/// The numeric value of a property; `None` for Null (a relinquished slot).
+async fn read_value(services: &BacnetServices, dest: &BacnetAddress, object_id: ObjectId, property: u32, array_index: Option<u32>) -> Result<Option<codec::BacnetValue>> {
+    let data = services.read_property(dest, object_id, property, array_index).await?;
+    let (value, _) = codec::decode_application_value(&data)?;
+    match value {
+        codec::BacnetValue::Null => Ok(None),
+        value if value.as_f32().is_some() => Ok(Some(value)),
+        _ => anyhow::bail!("Read back a non-numeric value"),
+    }
+}
@@ -0,0 +1,221 @@
+use crate::common::config::DiscoveryConfig;
+use crate::common::types::{object_id_string, BacnetDevice, Event};
+use crate::core::bacnet::client::BacnetClient;
+use crate::core::bacnet::codec::{self, BacnetValue};
+use crate::core::bacnet::npdu::{BacnetAddress, RoutingTable};
+use crate::core::bacnet::object_id::ObjectId;
+use crate::core::bacnet::services::BacnetServices;
+use crate::core::devices::DeviceMap;
+use crate::core::objects;
+use crate::core::persistence::db::Database;
+use crate::core::sweep::{sweep_range, Sweep};
+use bacnet_rs::object::PropertyIdentifier;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Periodically rediscovers the network and compares the result with the
+/// stored device map: devices that miss several rounds are removed
+/// (`DeviceRemoved`), and devices whose `Database_Revision` changed (or that
+/// do not have one) get their object list re-read (`ObjectListChanged`).
+/// New and moved devices are reported by the device manager as their I-Am arrives.
+/// A round that lost I-Am events is not counted against any device.
+pub struct Rediscovery {
+    pub client: Arc<Mutex<BacnetClient>>,
+    pub subnet_broadcast: Option<IpAddr>,
+    pub services: BacnetServices,
+    pub db: Database,
+    pub event_tx: broadcast::Sender<Event>,
+    pub routing_table: RoutingTable,
+    pub devices: DeviceMap,
+    pub config: DiscoveryConfig,
+}
+
+impl Rediscovery {
+    pub async fn run(self) {
+        if self.config.rediscovery_interval_secs == 0 {
+            log::info!("Background rediscovery disabled");
+            return;
+        }
+        let mut missed: HashMap<u32, u32> = HashMap::new();
+        loop {
+            tokio::time::sleep(Duration::from_secs(self.config.rediscovery_interval_secs)).await;
+            log::info!("Starting rediscovery round");
+            let Some(seen) = self.collect_i_ams().await else {
+                log::warn!("Rediscovery round inconclusive, not counting missed devices");
+                continue;
+            };
+
+            let known: Vec<BacnetDevice> = self.devices.lock().await.values().cloned().collect();
+            for device in known {
+                if seen.contains(&device.instance) {
+                    missed.remove(&device.instance);
+                    self.check_object_list(device).await;
+                    continue;
+                }
+                let count = missed.entry(device.instance).or_default();
+                *count += 1;
+                log::info!("Device {} missed {} rediscovery round(s)", device.instance, count);
+                if *count >= self.config.removal_after_missed.max(1) {
+                    missed.remove(&device.instance);
+                    self.remove_device(device.instance).await;
+                }
+            }
+        }
+    }
+
+    /// Sends Who-Is (or a ranged sweep) and returns the instances that
+    /// answered, or `None` when the round is inconclusive because I-Am
+    /// events were lost or the sweep range is invalid.
+    async fn collect_i_ams(&self) -> Option<HashSet<u32>> {
+        let mut event_rx = self.event_tx.subscribe();
+        let probe = async {
+            if self.config.rediscovery_sweep {
+                let (low, high) = match sweep_range(self.config.sweep_low, self.config.sweep_high) {
+                    Ok(range) => range,
+                    Err(e) => {
+                        log::error!("Rediscovery sweep not started: {}", e);
+                        return false;
+                    }
+                };
+                Sweep {
+                    client: Arc::clone(&self.client),
+                    subnet_broadcast: self.subnet_broadcast,
+                    event_tx: self.event_tx.clone(),
+                    low,
+                    high,
+                    chunk_size: self.config.chunk_size,
+                    chunk_delay: Duration::from_millis(self.config.chunk_delay_ms),
+                    cancel: Arc::new(AtomicBool::new(false)),
+                }
+                .run()
+                .await;
+            } else {
+                let sent = self.client.lock().unwrap().broadcast_who_is(None, None, self.subnet_broadcast);
+                if let Err(e) = sent {
+                    log::error!("Rediscovery Who-Is failed: {}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(self.config.rediscovery_wait_secs)).await;
+            }
+            true
+        };
+        tokio::pin!(probe);
+
+        // Receive while probing so a burst of I-Ams does not overrun the bus
+        let mut seen = HashSet::new();
+        let mut lagged = false;
+        let completed = loop {
+            tokio::select! {
+                completed = &mut probe => break completed,
+                event = event_rx.recv() => match event {
+                    Ok(Event::DeviceDiscovered(device)) => {
+                        seen.insert(device.instance);
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        log::warn!("Rediscovery missed {} events", missed);
+                        lagged = true;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+            }
+        };
+        loop {
+            match event_rx.try_recv() {
+                Ok(Event::DeviceDiscovered(device)) => {
+                    seen.insert(device.instance);
+                }
+                Ok(_) => {}
+                Err(broadcast::error::TryRecvError::Lagged(_)) => lagged = true,
+                Err(_) => break,
+            }
+        }
+        (completed && !lagged).then_some(seen)
+    }
+
+    async fn remove_device(&self, device_id: u32) {
+        log::warn!("Device {} stopped answering, removing it", device_id);
+        self.devices.lock().await.remove(&device_id);
+        if let Err(e) = self.db.delete_device(device_id).await {
+            log::error!("Failed to delete device {}: {}", device_id, e);
+        }
+        let _ = self.event_tx.send(Event::DeviceRemoved { device_id });
+    }
+
+    /// Re-reads the object list when `Database_Revision` changed (or cannot be
+    /// read) and compares it with the stored one.
+    async fn check_object_list(&self, device: BacnetDevice) {
+        let Some(dest) = BacnetAddress::resolve(&device.address, device.network, device.mac.as_deref(), &self.routing_table) else { return };
+        let stored = match self.db.load_objects(device.instance).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                log::error!("Failed to load objects of device {}: {}", device.instance, e);
+                return;
+            }
+        };
+        let revision = self.read_database_revision(&dest, device.instance).await;
+        if stored.is_some() && revision.is_some() && revision == device.database_revision {
+            return;
+        }
+
+        let current = match objects::read_object_list(&self.services, &dest, device.instance).await {
+            Ok(list) => list.iter().map(|id| id.to_string()).collect::<BTreeSet<_>>(),
+            Err(e) => {
+                log::warn!("Rediscovery could not read the object list of device {}: {}", device.instance, e);
+                return;
+            }
+        };
+        let previous = stored.as_ref().map(|objects| {
+            objects.iter().map(|o| object_id_string(o.object_type, o.instance)).collect::<BTreeSet<_>>()
+        });
+        let changed = previous.as_ref() != Some(&current);
+        if let Some(previous) = previous.filter(|_| changed) {
+            let added: Vec<String> = current.difference(&previous).cloned().collect();
+            let removed: Vec<String> = previous.difference(&current).cloned().collect();
+            log::info!("Object list of device {} changed: +{:?} -{:?}", device.instance, added, removed);
+            let _ = self.event_tx.send(Event::ObjectListChanged { device_id: device.instance, added, removed });
+        }
+        if changed {
+            let caps = device.capabilities();
+            match objects::discover_objects(&self.services, &dest, device.instance, &caps).await {
+                Ok(objects) => {
+                    let _ = self.event_tx.send(Event::DeviceObjectsDiscovered { device_id: device.instance, objects });
+                }
+                Err(e) => {
+                    log::warn!("Rediscovery could not read the objects of device {}: {}", device.instance, e);
+                    return;
+                }
+            }
+        }
+
+        if revision.is_some() && revision != device.database_revision {
+            let updated = {
+                let mut devices = self.devices.lock().await;
+                let Some(entry) = devices.get_mut(&device.instance) else { return };
+                entry.database_revision = revision;
+                entry.clone()
+            };
+            if let Err(e) = self.db.upsert_device(&updated).await {
+                log::error!("Failed to persist device {}: {}", updated.instance, e);
+            }
+            let _ = self.event_tx.send(Event::DeviceUpdated(updated));
+        }
+    }
+
+    async fn read_database_revision(&self, dest: &BacnetAddress, device_id: u32) -> Option<u32> {
+        let object_id = ObjectId::device(device_id);
+        match self.services.read_property(dest, object_id, PropertyIdentifier::DatabaseRevision as u32, None).await {
+            Ok(data) => match codec::decode_application_value(&data) {
+                Ok((BacnetValue::Unsigned(revision), _)) => Some(revision as u32),
+                _ => None,
+            },
+            Err(e) => {
+                log::debug!("Device {} Database_Revision unreadable: {}", device_id, e);
+                None
+            }
+        }
+    }
+}
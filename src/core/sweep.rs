@@ -0,0 +1,112 @@
+use crate::common::types::{DiscoveryProgress, Event, MAX_DEVICE_INSTANCE};
+use crate::core::bacnet::client::BacnetClient;
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+/// Minimum time to wait for late I-Am replies after the last chunk.
+const FINAL_WAIT: Duration = Duration::from_secs(2);
+
+/// Checks a sweep range, capping `high` at the largest device instance.
+pub fn sweep_range(low: u32, high: u32) -> Result<(u32, u32)> {
+    let high = high.min(MAX_DEVICE_INSTANCE);
+    if low > high {
+        bail!("invalid instance range {}..={}", low, high);
+    }
+    Ok((low, high))
+}
+
+/// Discovers devices by sending ranged Who-Is requests over `low..=high` in
+/// chunks of `chunk_size` instances, pausing between chunks so that large
+/// sites answer in small bursts instead of a single I-Am storm. Broadcasts
+/// `DiscoveryProgress` after each chunk. Unset parameters of a
+/// `SweepDiscovery` take the `discovery` config defaults, and
+/// `StopDiscovery` sets `cancel`.
+pub struct Sweep {
+    pub client: Arc<Mutex<BacnetClient>>,
+    pub subnet_broadcast: Option<IpAddr>,
+    pub event_tx: broadcast::Sender<Event>,
+    pub low: u32,
+    pub high: u32,
+    pub chunk_size: u32,
+    pub chunk_delay: Duration,
+    /// Set to stop the sweep before the next chunk
+    pub cancel: Arc<AtomicBool>,
+}
+
+impl Sweep {
+    pub async fn run(self) {
+        let mut event_rx = self.event_tx.subscribe();
+        let chunk_size = self.chunk_size.max(1);
+        let mut progress = DiscoveryProgress {
+            low: self.low,
+            high: self.high,
+            swept_to: self.low,
+            chunks_sent: 0,
+            chunks_total: (self.high - self.low) / chunk_size + 1,
+            devices_found: 0,
+            finished: false,
+            cancelled: false,
+        };
+        log::info!("Sweeping device instances {}..={} in {} chunks", self.low, self.high, progress.chunks_total);
+        let mut found = HashSet::new();
+        let mut chunk_low = self.low;
+
+        loop {
+            if self.cancel.load(Ordering::SeqCst) {
+                progress.finished = true;
+                progress.cancelled = true;
+                log::info!("Sweep stopped at instance {}", progress.swept_to);
+                let _ = self.event_tx.send(Event::DiscoveryProgress(progress));
+                return;
+            }
+
+            let chunk_high = chunk_low.saturating_add(chunk_size - 1).min(self.high);
+            let sent = self.client.lock().unwrap().broadcast_who_is(Some(chunk_low), Some(chunk_high), self.subnet_broadcast);
+            if let Err(e) = sent {
+                log::error!("Who-Is for {}..={} failed: {}", chunk_low, chunk_high, e);
+            }
+            progress.chunks_sent += 1;
+            progress.swept_to = chunk_high;
+            let last = chunk_high >= self.high;
+
+            let delay = if last { self.chunk_delay.max(FINAL_WAIT) } else { self.chunk_delay };
+            self.collect(&mut event_rx, &mut found, Instant::now() + delay).await;
+            progress.devices_found = found.len();
+            progress.finished = last;
+            let _ = self.event_tx.send(Event::DiscoveryProgress(progress.clone()));
+
+            if last {
+                log::info!("Sweep of {}..={} finished, {} devices found", self.low, self.high, found.len());
+                return;
+            }
+            chunk_low = chunk_high + 1;
+        }
+    }
+
+    /// Collects the devices in range that announce themselves until `until`,
+    /// receiving while waiting so a burst of I-Ams does not overrun the
+    /// event bus.
+    async fn collect(&self, event_rx: &mut broadcast::Receiver<Event>, found: &mut HashSet<u32>, until: Instant) {
+        let deadline = tokio::time::sleep_until(until);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                _ = &mut deadline => return,
+                event = event_rx.recv() => match event {
+                    Ok(Event::DeviceDiscovered(device)) if (self.low..=self.high).contains(&device.instance) => {
+                        found.insert(device.instance);
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(missed)) => log::warn!("Sweep missed {} events", missed),
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::common::types::{object_id_string, AuditAction, AuditEntry, AuditResult, Event, Origin, TimedOverride, TimedOverrideEnd};
+use crate::core::audit;
+use crate::core::bacnet::npdu::{BacnetAddress, RoutingTable};
+use crate::core::bacnet::object_id::ObjectId;
+use crate::core::bacnet::services::BacnetServices;
+use crate::core::devices::DeviceMap;
+use crate::core::liveness::Liveness;
+use crate::core::overrides::GatewayCommands;
+use crate::core::persistence::db::{now_ms, Database};
+use anyhow::Result;
+use bacnet_rs::object::{PropertyIdentifier, PropertyValue};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+const TICK: Duration = Duration::from_secs(1);
+/// Delay before a failed relinquish is retried
+const RETRY_DELAY_MS: i64 = 30_000;
+
+/// Relinquishes timed overrides when they expire. Pending relinquishes are
+/// kept in the database, so overrides that expired while the gateway was
+/// down are relinquished once it is back. A failed relinquish is retried
+/// after 30s, and offline devices are relinquished once they are back.
+/// Only commandable Present_Values written at an explicit priority can be
+/// timed; a new timed write at the same priority replaces the pending
+/// override, and an untimed write there drops it. Cheap to clone.
+#[derive(Clone)]
+pub struct TimedOverrides {
+    pub services: BacnetServices,
+    pub db: Database,
+    pub event_tx: broadcast::Sender<Event>,
+    pub routing_table: RoutingTable,
+    pub devices: DeviceMap,
+    pub liveness: Liveness,
+    pub commands: GatewayCommands,
+}
+
+impl TimedOverrides {
+    pub async fn run(self) {
+        match self.db.load_timed_overrides().await {
+            Ok(pending) => {
+                // Still commanded by the gateway, so not reported as overrides
+                for timed in &pending {
+                    self.commands.record(timed.device_id, object_id(timed), timed.priority, false);
+                }
+                if !pending.is_empty() {
+                    log::info!("{} timed overrides pending", pending.len());
+                }
+            }
+            Err(e) => log::error!("Failed to load timed overrides: {}", e),
+        }
+        let mut tick = tokio::time::interval(TICK);
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tick.tick().await;
+            let pending = match self.db.load_timed_overrides().await {
+                Ok(pending) => pending,
+                Err(e) => {
+                    log::error!("Failed to load timed overrides: {}", e);
+                    continue;
+                }
+            };
+            let now = now_ms();
+            for timed in pending.into_iter().take_while(|t| t.expires_ms <= now) {
+                // Offline devices are relinquished once they are back
+                if !self.liveness.is_online(timed.device_id) {
+                    continue;
+                }
+                if let Err(e) = self.end(timed.clone(), &Origin::default(), false).await {
+                    log::warn!("Relinquishing timed override {} failed, retrying: {}", timed.id, e);
+                    if let Err(e) = self.db.set_timed_override_expiry(timed.id, now + RETRY_DELAY_MS).await {
+                        log::error!("Failed to reschedule timed override {}: {}", timed.id, e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Relinquishes a timed override now.
+    pub async fn cancel(&self, id: i64, origin: &Origin) -> Result<()> {
+        let pending = self.db.load_timed_overrides().await?;
+        let timed = pending.into_iter().find(|t| t.id == id).ok_or_else(|| anyhow::anyhow!("Unknown timed override {}", id))?;
+        self.end(timed, origin, true).await
+    }
+
+    /// Writes Null at the override's priority and forgets it. Emits
+    /// `TimedOverrideEnded` for successful relinquishes and failed
+    /// cancellations.
+    async fn end(&self, timed: TimedOverride, origin: &Origin, cancelled: bool) -> Result<()> {
+        let result = self.relinquish(&timed).await;
+        let entry = audit::entry(AuditAction::Relinquish, origin, if result.is_ok() { AuditResult::Ok } else { AuditResult::Failed });
+        audit::record(&self.db, AuditEntry {
+            device_id: Some(timed.device_id),
+            object_id: Some(object_id_string(timed.object_type, timed.instance)),
+            property: Some(PropertyIdentifier::PresentValue as u32),
+            target: Some(format!("timed override {}", timed.id)),
+            priority: Some(timed.priority),
+            detail: result.as_ref().err().map(|e| e.to_string()),
+            ..entry
+        })
+        .await;
+        if result.is_ok() {
+            log::info!("Relinquished priority {} of device {} {}:{}", timed.priority, timed.device_id, timed.object_type, timed.instance);
+            self.commands.record(timed.device_id, object_id(&timed), timed.priority, true);
+            self.db.delete_timed_override(timed.id).await?;
+        }
+        if result.is_ok() || cancelled {
+            let error = result.as_ref().err().map(|e| e.to_string());
+            let _ = self.event_tx.send(Event::TimedOverrideEnded(TimedOverrideEnd { timed_override: timed, cancelled, error }));
+        }
+        result
+    }
+
+    async fn relinquish(&self, timed: &TimedOverride) -> Result<()> {
+        let dest = {
+            let devices = self.devices.lock().await;
+            let device = devices.get(&timed.device_id).ok_or_else(|| anyhow::anyhow!("Unknown device {}", timed.device_id))?;
+            BacnetAddress::resolve(&device.address, device.network, device.mac.as_deref(), &self.routing_table)
+                .ok_or_else(|| anyhow::anyhow!("Cannot route to {}", device.address))?
+        };
+        let result = self
+            .services
+            .write_property(&dest, object_id(timed), PropertyIdentifier::PresentValue as u32, PropertyValue::Null, Some(timed.priority))
+            .await;
+        self.liveness.record_result(timed.device_id, &result);
+        result
+    }
+}
+
+fn object_id(timed: &TimedOverride) -> ObjectId {
+    ObjectId::new(timed.object_type, timed.instance)
+}
@@ -0,0 +1,170 @@
+use crate::common::types::{BacnetDevice, PointConfig};
+use bacnet_rs::object::PropertyIdentifier;
+
+/// Checks a write against the gateway's permissions: the read-only switch,
+/// the target's address and the write policy of its point. Only the
+/// Present_Value of mapped points with writes enabled can be written.
+/// Returns the reason when the write is refused. `value` is `None` when it
+/// does not parse; the caller reports that separately. Writes without a
+/// priority are checked as priority 16. Relinquishes of timed overrides
+/// are not checked.
+pub fn authorize(read_only: bool, device: Option<&BacnetDevice>, target: (&str, Option<u16>, Option<&str>), point: Option<&PointConfig>, property: u32, priority: u8, value: Option<f32>) -> Result<(), String> {
+    if read_only {
+        return Err("Gateway is in read-only mode".to_string());
+    }
+    let Some(device) = device else {
+        return Err("Unknown device".to_string());
+    };
+    if (device.address.as_str(), device.network, device.mac.as_deref()) != target {
+        return Err(format!("{} is not the address of device {}", target.0, device.instance));
+    }
+    if property != PropertyIdentifier::PresentValue as u32 {
+        return Err(format!("Property {} is not writable; only Present_Value of mapped points is", property));
+    }
+    let Some(point) = point else {
+        return Err("Object is not a mapped point".to_string());
+    };
+    let policy = &point.write;
+    if !policy.enabled {
+        return Err(format!("Writes to point {} are not enabled", point.id));
+    }
+    if !(policy.priority_min..=policy.priority_max).contains(&priority) {
+        return Err(format!("Priority {} is outside the permitted range {}-{}", priority, policy.priority_min, policy.priority_max));
+    }
+    let Some(value) = value else { return Ok(()) };
+    if let Some(min) = policy.min.filter(|min| value < *min) {
+        return Err(format!("Value {} is below the minimum {}", value, min));
+    }
+    if let Some(max) = policy.max.filter(|max| value > *max) {
+        return Err(format!("Value {} is above the maximum {}", value, max));
+    }
+    if let Some(states) = &policy.allowed_states {
+        if value.fract() != 0.0 || value < 0.0 || !states.contains(&(value as u32)) {
+            return Err(format!("Value {} is not one of the permitted states {:?}", value, states));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::types::{HistoryPolicy, PublishFilter, Segmentation, WritePolicy};
+
+    const PRESENT_VALUE: u32 = PropertyIdentifier::PresentValue as u32;
+    const TARGET: (&str, Option<u16>, Option<&str>) = ("10.0.0.5:47808", None, None);
+
+    fn device() -> BacnetDevice {
+        BacnetDevice {
+            instance: 1234,
+            address: TARGET.0.to_string(),
+            network: None,
+            mac: None,
+            name: "AHU-1".to_string(),
+            max_apdu_length_accepted: 1476,
+            segmentation_supported: Segmentation::None,
+            vendor_id: 15,
+            vendor_name: None,
+            model_name: None,
+            firmware_revision: None,
+            application_software_version: None,
+            location: None,
+            description: None,
+            protocol_services_supported: None,
+            database_revision: None,
+        }
+    }
+
+    fn point(write: WritePolicy) -> PointConfig {
+        PointConfig {
+            id: 1,
+            device_id: 1234,
+            object_type: 2,
+            instance: 1,
+            name: "Setpoint".to_string(),
+            poll_interval_secs: 10,
+            cov: false,
+            enabled: true,
+            write,
+            history: HistoryPolicy::default(),
+            publish: PublishFilter::default(),
+        }
+    }
+
+    fn enabled() -> WritePolicy {
+        WritePolicy { enabled: true, ..WritePolicy::default() }
+    }
+
+    fn check(point: &PointConfig, priority: u8, value: f32) -> Result<(), String> {
+        authorize(false, Some(&device()), TARGET, Some(point), PRESENT_VALUE, priority, Some(value))
+    }
+
+    #[test]
+    fn permits_writes_within_the_policy() {
+        let point = point(WritePolicy { min: Some(15.0), max: Some(28.0), priority_min: 8, priority_max: 16, ..enabled() });
+        assert_eq!(check(&point, 8, 21.5), Ok(()));
+        assert_eq!(check(&point, 16, 15.0), Ok(()));
+        assert_eq!(check(&point, 16, 28.0), Ok(()));
+        // Unparsable values are reported by the caller
+        assert_eq!(authorize(false, Some(&device()), TARGET, Some(&point), PRESENT_VALUE, 8, None), Ok(()));
+    }
+
+    #[test]
+    fn refuses_everything_while_read_only() {
+        let point = point(enabled());
+        assert!(authorize(true, Some(&device()), TARGET, Some(&point), PRESENT_VALUE, 8, Some(1.0)).unwrap_err().contains("read-only"));
+    }
+
+    #[test]
+    fn refuses_unknown_devices_and_other_addresses() {
+        let point = point(enabled());
+        assert_eq!(authorize(false, None, TARGET, Some(&point), PRESENT_VALUE, 8, Some(1.0)), Err("Unknown device".to_string()));
+        for target in [("10.0.0.6:47808", None, None), (TARGET.0, Some(5), Some("0a"))] {
+            let refused = authorize(false, Some(&device()), target, Some(&point), PRESENT_VALUE, 8, Some(1.0)).unwrap_err();
+            assert!(refused.contains("is not the address of device 1234"), "{}", refused);
+        }
+    }
+
+    #[test]
+    fn refuses_other_properties_and_unmapped_objects() {
+        let point = point(enabled());
+        let refused = authorize(false, Some(&device()), TARGET, Some(&point), PropertyIdentifier::ObjectName as u32, 8, Some(1.0));
+        assert!(refused.unwrap_err().contains("not writable"));
+        let refused = authorize(false, Some(&device()), TARGET, None, PRESENT_VALUE, 8, Some(1.0));
+        assert_eq!(refused, Err("Object is not a mapped point".to_string()));
+    }
+
+    #[test]
+    fn refuses_points_without_writes_enabled() {
+        assert!(check(&point(WritePolicy::default()), 8, 1.0).unwrap_err().contains("not enabled"));
+    }
+
+    #[test]
+    fn refuses_priorities_outside_the_range() {
+        let point = point(WritePolicy { priority_min: 8, priority_max: 12, ..enabled() });
+        assert!(check(&point, 7, 1.0).unwrap_err().contains("outside the permitted range 8-12"));
+        assert!(check(&point, 13, 1.0).is_err());
+        assert!(check(&point, 12, 1.0).is_ok());
+    }
+
+    #[test]
+    fn refuses_values_outside_the_limits() {
+        let point = point(WritePolicy { min: Some(-10.0), max: Some(28.0), ..enabled() });
+        assert!(check(&point, 8, -10.5).unwrap_err().contains("below the minimum"));
+        assert!(check(&point, 8, 28.1).unwrap_err().contains("above the maximum"));
+        assert!(check(&point, 8, -10.0).is_ok());
+    }
+
+    #[test]
+    fn refuses_states_not_listed() {
+        let point = point(WritePolicy { allowed_states: Some(vec![0, 1, 3]), ..enabled() });
+        assert!(check(&point, 8, 0.0).is_ok());
+        assert!(check(&point, 8, 3.0).is_ok());
+        assert!(check(&point, 8, 2.0).unwrap_err().contains("not one of the permitted states"));
+        // Neither truncated nor wrapped into a listed state
+        assert!(check(&point, 8, 1.5).is_err());
+        assert!(check(&point, 8, 0.5).is_err());
+        assert!(check(&point, 8, -1.0).is_err());
+        assert!(check(&point, 8, -0.5).is_err());
+    }
+}
@@ -3,7 +3,12 @@ mod ui;
 mod common;
 
 use crate::core::Core;
-use crate::common::types::{Command, Event};
+use crate::common::config::{GatewayConfig, DEFAULT_CONFIG_PATH};
+use crate::common::types::{AuditSource, Command, Event, ObjectSearch, Origin};
+use crate::core::bacnet::who_has::aggregate_i_haves;
+use bacnet_rs::object::PropertyIdentifier;
+use crate::core::mqtt::client::MqttBridge;
+use crate::core::persistence::db::Database;
 use crate::core::network::interface;
 use tokio::sync::{mpsc, broadcast};
 use std::env;
@@ -12,36 +17,96 @@ fn main() {
     env_logger::init();
 
     let args: Vec<String> = env::args().collect();
+    let config_path = flag(&args, "--config").map(String::as_str).unwrap_or(DEFAULT_CONFIG_PATH);
+    let config = match GatewayConfig::load(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
 
     // Check for CLI mode
     if args.len() > 1 {
+        let route = parse_route(&args);
         match args[1].as_str() {
             "list" => {
                 println!("Available Network Interfaces:");
                 if let Ok(ifaces) = interface::list_interfaces() {
                     for iface in ifaces {
-                        println!(" - {}", iface.name);
+                        let mut flags = vec![if iface.is_up { "UP" } else { "DOWN" }];
+                        if iface.is_loopback {
+                            flags.push("LOOPBACK");
+                        }
+                        println!(" - {} [{}] mac={}", iface.name, flags.join(","), iface.mac_address.as_deref().unwrap_or("n/a"));
+                        for addr in iface.addresses {
+                            match addr.broadcast {
+                                Some(bcast) => println!("     {} netmask {} broadcast {}", addr.ip, addr.netmask, bcast),
+                                None => println!("     {} netmask {}", addr.ip, addr.netmask),
+                            }
+                        }
                     }
                 }
                 return;
             }
             "discover" => {
-                if let Some(iface_name) = args.get(2) {
-                    println!("Running manual discovery on {}...", iface_name);
-                    run_core_oneshot(Command::BindAndDiscover(iface_name.clone()));
-                    return;
-                } else {
-                    println!("Usage: cargo run -- discover <interface_name>");
-                    return;
+                let args = positional(&args);
+                match (args.get(2), parse_range(args.get(3..).unwrap_or(&[]))) {
+                    (Some(iface_name), Ok(None)) => {
+                        println!("Running manual discovery on {}...", iface_name);
+                        run_core_oneshot(&config, vec![Command::BindAndDiscover(iface_name.clone())], ONESHOT_WAIT);
+                    }
+                    (Some(iface_name), Ok(Some((low, high)))) => {
+                        println!("Discovering instances {}..={} on {}...", low, high, iface_name);
+                        run_core_oneshot(&config, vec![
+                            Command::BindInterface(iface_name.clone()),
+                            Command::DiscoverRange { low, high },
+                        ], ONESHOT_WAIT);
+                    }
+                    _ => println!("Usage: cargo run -- discover <interface_name> [low high]"),
+                }
+                return;
+            }
+            "sweep" => {
+                let args = positional(&args);
+                match (args.get(2), parse_range(args.get(3..).unwrap_or(&[]))) {
+                    (Some(iface_name), Ok(range)) => {
+                        let discovery = &config.discovery;
+                        let (low, high) = range.unwrap_or((discovery.sweep_low, discovery.sweep_high));
+                        let chunks = (high.saturating_sub(low) / discovery.chunk_size.max(1) + 1) as u64;
+                        let wait = std::time::Duration::from_millis(chunks * discovery.chunk_delay_ms) + ONESHOT_WAIT;
+                        println!("Sweeping instances {}..={} on {} in {} chunks...", low, high, iface_name, chunks);
+                        run_core_oneshot(&config, vec![
+                            Command::BindInterface(iface_name.clone()),
+                            Command::SweepDiscovery { low: Some(low), high: Some(high), chunk_size: None, chunk_delay_ms: None },
+                        ], wait);
+                    }
+                    _ => println!("Usage: cargo run -- sweep <interface_name> [low high]"),
+                }
+                return;
+            }
+            "who-has" => {
+                let args = positional(&args);
+                match (args.get(2), args.get(3).filter(|o| !o.is_empty()), parse_range(args.get(4..).unwrap_or(&[]))) {
+                    (Some(iface), Some(object), Ok(range)) => {
+                        let search = ObjectSearch::parse(object);
+                        println!("Searching for {:?} via {}...", search, iface);
+                        run_core_oneshot(&config, vec![
+                            Command::BindInterface(iface.clone()),
+                            Command::WhoHas { search, low: range.map(|r| r.0), high: range.map(|r| r.1) },
+                        ], ONESHOT_WAIT);
+                    }
+                    _ => println!("Usage: cargo run -- who-has <interface_name> <object_name|type:instance> [low high]"),
                 }
+                return;
             }
             "ping" => {
                 if let (Some(iface), Some(target)) = (args.get(2), args.get(3)) {
                     println!("Pinging {} via {}...", target, iface);
-                    run_core_oneshot(Command::Ping { 
+                    run_core_oneshot(&config, vec![Command::Ping { 
                         interface: iface.clone(), 
                         target: target.clone() 
-                    });
+                    }], ONESHOT_WAIT);
                     return;
                 } else {
                     println!("Usage: cargo run -- ping <interface_name> <target_ip>");
@@ -52,17 +117,72 @@ fn main() {
                 if let (Some(iface), Some(device_id), Some(address)) = (args.get(2), args.get(3), args.get(4)) {
                     let id = device_id.parse().unwrap();
                     println!("Discovering objects on {} ({}) via {}...", id, address, iface);
-                    run_core_oneshot(Command::DiscoverObjects { 
+                    run_core_oneshot(&config, vec![Command::DiscoverObjects { 
                         interface: iface.clone(),
                         device_id: id,
-                        address: address.clone()
-                    });
+                        address: address.clone(),
+                        network: args.get(5).and_then(|n| n.parse().ok()),
+                        mac: args.get(6).cloned(),
+                    }], ONESHOT_WAIT);
                     return;
                 } else {
-                    println!("Usage: cargo run -- discover-objects <interface_name> <device_id> <device_address>");
+                    println!("Usage: cargo run -- discover-objects <interface_name> <device_id> <device_address> [network] [mac_hex]");
                     return;
                 }
             }
+            "read-property" => {
+                let args = positional(&args);
+                let object = args.get(5).and_then(|o| o.split_once(':')).and_then(|(t, i)| Some((t.parse().ok()?, i.parse().ok()?)));
+                match (args.get(2), args.get(3).and_then(|d| d.parse().ok()), args.get(4), object, args.get(6).and_then(|p| p.parse().ok()), route) {
+                    (Some(iface), Some(device_id), Some(address), Some((object_type, instance)), Some(property), Ok((network, mac))) => {
+                        println!("Reading {}:{} property {} of device {} via {}...", object_type, instance, property, device_id, iface);
+                        run_core_oneshot(&config, vec![
+                            Command::BindInterface(iface.clone()),
+                            Command::ReadProperty {
+                                device_id,
+                                address: address.clone(),
+                                network,
+                                mac,
+                                object_type,
+                                instance,
+                                property,
+                                array_index: args.get(7).and_then(|i| i.parse().ok()),
+                            },
+                        ], ONESHOT_WAIT);
+                    }
+                    _ => println!("Usage: cargo run -- read-property <interface_name> <device_id> <device_address> <type:instance> <property> [array_index] [--network <n> --mac <hex>]"),
+                }
+                return;
+            }
+            "write" => {
+                let args = positional(&args);
+                let object = args.get(5).and_then(|o| o.split_once(':')).and_then(|(t, i)| Some((t.parse().ok()?, i.parse().ok()?)));
+                match (args.get(2), args.get(3).and_then(|d| d.parse().ok()), args.get(4), object, args.get(6), route) {
+                    (Some(iface), Some(device_id), Some(address), Some((object_type, instance)), Some(value), Ok((network, mac))) => {
+                        println!("Writing {} to {}:{} of device {} via {}...", value, object_type, instance, device_id, iface);
+                        run_core_oneshot(&config, vec![
+                            Command::BindInterface(iface.clone()),
+                            Command::WriteProperty {
+                                device_id,
+                                address: address.clone(),
+                                network,
+                                mac,
+                                object_type,
+                                instance,
+                                property: PropertyIdentifier::PresentValue as u32,
+                                value: value.clone(),
+                                priority: args.get(7).and_then(|p| p.parse().ok()),
+                                duration_secs: None,
+                                verify: false,
+                                tolerance: None,
+                                origin: Origin::new(AuditSource::Cli, env::var("USER").ok()),
+                            },
+                        ], ONESHOT_WAIT);
+                    }
+                    _ => println!("Usage: cargo run -- write <interface_name> <device_id> <device_address> <type:instance> <value> [priority] [--network <n> --mac <hex>]"),
+                }
+                return;
+            }
             _ => {} // Fall through to standard app launch
         }
     }
@@ -71,11 +191,18 @@ fn main() {
     let with_simulator = args.iter().any(|arg| arg == "--with-simulator");
     
     let (cmd_tx, cmd_rx) = mpsc::channel::<Command>(100);
-    let (event_tx, mut _event_rx) = broadcast::channel(100);
+    let (event_tx, mut _event_rx) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
 
     let core_event_tx = event_tx.clone();
     let rt = tokio::runtime::Runtime::new().unwrap();
     rt.block_on(async {
+        let db = match Database::open(&config.database_path).await {
+            Ok(db) => db,
+            Err(e) => {
+                log::error!("Failed to open database {}: {}", config.database_path, e);
+                return;
+            }
+        };
         let cmd_tx_clone = cmd_tx.clone();
         let event_tx_clone = event_tx.clone();
         
@@ -100,12 +227,19 @@ fn main() {
             tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         }
 
-        tokio::spawn(async move {
-            ui::launch(cmd_tx_clone, event_tx_clone).await;
-        });
+        if config.mqtt.enabled {
+            log::info!("Publishing to MQTT broker {}:{}", config.mqtt.host, config.mqtt.port);
+            tokio::spawn(MqttBridge::new(&config.mqtt).run(event_tx.clone(), cmd_tx.clone(), db.clone()));
+        }
 
-        let mut core = Core::new(cmd_rx, core_event_tx);
+        let mut core = Core::new(cmd_rx, core_event_tx, db.clone(), config.clone());
         let shutdown_trigger = core.shutdown.clone();
+
+        let ui_db = db.clone();
+        let read_only = core.read_only.clone();
+        tokio::spawn(async move {
+            ui::launch(cmd_tx_clone, event_tx_clone, ui_db, read_only).await;
+        });
         
         // Spawn core in background
         tokio::spawn(async move {
@@ -124,15 +258,57 @@ fn main() {
     });
 }
 
-fn run_core_oneshot(cmd: Command) {
+/// Event bus capacity. Acquisition publishes a `PointUpdate` per point per
+/// poll, so subscribers need room for a few full polling rounds.
+const EVENT_CHANNEL_CAPACITY: usize = 4096;
+
+/// How long CLI commands wait for replies.
+const ONESHOT_WAIT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Value following `--name`, if given.
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1))
+}
+
+/// Command line arguments up to the first `--` option.
+fn positional(args: &[String]) -> &[String] {
+    let end = args.iter().position(|arg| arg.starts_with("--")).unwrap_or(args.len());
+    &args[..end]
+}
+
+/// Parses optional `low high` instance limits. Both or neither must be given.
+fn parse_range(args: &[String]) -> anyhow::Result<Option<(u32, u32)>> {
+    match args {
+        [] => Ok(None),
+        [low, high] => Ok(Some((low.parse()?, high.parse()?))),
+        _ => anyhow::bail!("Expected both a low and a high limit"),
+    }
+}
+
+/// Parses `--network <n> --mac <hex>` for devices behind a router.
+fn parse_route(args: &[String]) -> anyhow::Result<(Option<u16>, Option<String>)> {
+    let network = flag(args, "--network").map(|n| n.parse()).transpose()?;
+    Ok((network, flag(args, "--mac").cloned()))
+}
+
+fn run_core_oneshot(config: &GatewayConfig, cmds: Vec<Command>, wait: std::time::Duration) {
     let (cmd_tx, cmd_rx) = mpsc::channel::<Command>(100);
     let (event_tx, mut event_rx) = broadcast::channel(100);
 
     let rt = tokio::runtime::Runtime::new().unwrap();
     rt.block_on(async {
-        let mut core = Core::new(cmd_rx, event_tx);
+        let db = match Database::open(&config.database_path).await {
+            Ok(db) => db,
+            Err(e) => {
+                println!("Failed to open database {}: {}", config.database_path, e);
+                return;
+            }
+        };
+        let mut core = Core::new(cmd_rx, event_tx, db, config.clone());
         
-        let _ = cmd_tx.send(cmd).await;
+        for cmd in cmds {
+            let _ = cmd_tx.send(cmd).await;
+        }
 
         let core_shutdown_handle = core.shutdown.clone();
         tokio::spawn(async move {
@@ -141,8 +317,9 @@ fn run_core_oneshot(cmd: Command) {
             }
         });
 
-        println!("Waiting for devices (5s)...");
-        let timeout = tokio::time::sleep(std::time::Duration::from_secs(5));
+        let mut i_haves = Vec::new();
+        println!("Waiting for devices ({}s)...", wait.as_secs());
+        let timeout = tokio::time::sleep(wait);
         tokio::pin!(timeout);
 
         loop {
@@ -150,14 +327,73 @@ fn run_core_oneshot(cmd: Command) {
                 Ok(event) = event_rx.recv() => {
                     match event {
                         Event::DeviceDiscovered(dev) => {
-                            println!("FOUND DEVICE: ID={} Address={}", dev.instance, dev.address);
+                            match dev.network {
+                                Some(network) => println!("FOUND DEVICE: ID={} Address={} Network={} MAC={}", dev.instance, dev.address, network, dev.mac.as_deref().unwrap_or_default()),
+                                None => println!("FOUND DEVICE: ID={} Address={}", dev.instance, dev.address),
+                            }
+                            println!("    Vendor={} ({}) MaxAPDU={} Segmentation={:?}",
+                                dev.vendor_name.as_deref().unwrap_or("Unknown"), dev.vendor_id,
+                                dev.max_apdu_length_accepted, dev.segmentation_supported);
+                        }
+                        Event::DeviceUpdated(dev) => {
+                            println!("DEVICE INFO: ID={} Name={:?} Model={} Firmware={} Software={} Location={} Description={}",
+                                dev.instance, dev.name,
+                                dev.model_name.as_deref().unwrap_or("-"),
+                                dev.firmware_revision.as_deref().unwrap_or("-"),
+                                dev.application_software_version.as_deref().unwrap_or("-"),
+                                dev.location.as_deref().unwrap_or("-"),
+                                dev.description.as_deref().unwrap_or("-"));
+                            if let Some(services) = dev.protocol_services_supported {
+                                println!("    Services: {}", services.join(", "));
+                            }
                         }
                         Event::DeviceObjectsDiscovered { device_id, objects } => {
                             println!("OBJECTS DISCOVERED for Device {}:", device_id);
                             for obj in objects {
-                                println!(" - [{:?}] {} (Instance {})", obj.object_type, obj.name, obj.instance);
+                                println!(" - [{}] {} (Instance {})", obj.type_name, obj.name, obj.instance);
+                                if let Some(description) = &obj.description {
+                                    println!("     Description: {}", description);
+                                }
+                                if let Some(units) = &obj.units {
+                                    println!("     Units: {}", units);
+                                }
+                                if let (Some(active), Some(inactive)) = (&obj.active_text, &obj.inactive_text) {
+                                    println!("     States: {} / {}", inactive, active);
+                                }
+                                if let Some(states) = &obj.state_text {
+                                    println!("     States: {}", states.join(", "));
+                                }
+                            }
+                        }
+                        Event::DiscoveryProgress(progress) => {
+                            println!("SWEEP: {}/{} chunks, swept to {}, {} devices found",
+                                progress.chunks_sent, progress.chunks_total, progress.swept_to, progress.devices_found);
+                            if progress.finished {
+                                core_shutdown_handle.store(true, std::sync::atomic::Ordering::SeqCst);
+                                break;
                             }
                         }
+                        Event::PropertyRead(reading) => {
+                            match (&reading.value, &reading.error) {
+                                (Some(value), None) => println!("VALUE: {}", serde_json::to_string_pretty(value).unwrap_or_default()),
+                                (_, error) => println!("READ FAILED: {}", error.as_deref().unwrap_or("unknown error")),
+                            }
+                            if let Some(raw) = &reading.raw {
+                                println!("    Raw: {}", raw);
+                            }
+                            core_shutdown_handle.store(true, std::sync::atomic::Ordering::SeqCst);
+                            break;
+                        }
+                        Event::WriteCompleted(outcome) => {
+                            match &outcome.error {
+                                None => println!("WRITTEN: {} to {}", outcome.value, outcome.object_id),
+                                Some(error) if outcome.rejected => println!("WRITE REJECTED: {}", error),
+                                Some(error) => println!("WRITE FAILED: {}", error),
+                            }
+                            core_shutdown_handle.store(true, std::sync::atomic::Ordering::SeqCst);
+                            break;
+                        }
+                        Event::IHave(i_have) => i_haves.push(i_have),
                         Event::StatusMessage(msg) => println!("Status: {}", msg),
                         _ => {}
                     }
@@ -169,6 +405,12 @@ fn run_core_oneshot(cmd: Command) {
                 }
             }
         }
+        for result in aggregate_i_haves(&i_haves) {
+            println!("I-HAVE: Device={} Address={}", result.device_id, result.address);
+            for object in result.objects {
+                println!(" - {}:{} {}", object.object_type, object.instance, object.name);
+            }
+        }
         // Give it a moment to clean up
         tokio::time::sleep(std::time::Duration::from_millis(200)).await;
     });
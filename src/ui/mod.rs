@@ -1,39 +1,84 @@
-use crate::common::types::{Command, Event, BacnetDevice, BacnetObjectInfo};
+use crate::common::types::{AuditAction, AuditEntry, AuditFilter, AuditResult, AuditSource, Command, Event, HistoryAggregation, HistoryValue, MqttQueueStatus, Origin, BacnetDevice, BacnetObjectInfo, BacnetRouter, object_id_string, ConflictKind, DeviceConflict, DeviceStatus, NetworkSegment, NetworkTopology, ObjectSearch, PointConfig, PriorityOverride};
 use crate::core::network::interface;
+use crate::core::audit;
+use crate::core::bacnet::who_has::aggregate_i_haves;
+use crate::core::persistence::db::{now_ms, Database};
 use tokio::sync::{mpsc, broadcast, Mutex as TokioMutex};
-use axum::routing::{get, post};
+use axum::extract::ConnectInfo;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::routing::{delete, get, post};
 use axum::{Json, Router, extract::State, response::IntoResponse, response::Sse, response::sse::{Event as SseEvent, KeepAlive}};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use futures::stream::Stream;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 
+/// Longer than a confirmed request with all its retries.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(12);
+
 struct AppState {
     cmd_tx: mpsc::Sender<Command>,
     event_tx: broadcast::Sender<Event>,
+    db: Database,
+    /// Shared with the core, which rejects writes while it is set
+    read_only: Arc<AtomicBool>,
     discovered_devices: TokioMutex<HashMap<u32, BacnetDevice>>,
     device_objects: TokioMutex<HashMap<u32, Vec<BacnetObjectInfo>>>,
+    routers: TokioMutex<HashMap<String, BacnetRouter>>,
+    device_status: TokioMutex<HashMap<u32, DeviceStatus>>,
+    conflicts: TokioMutex<HashMap<(ConflictKind, String), DeviceConflict>>,
+    /// Active overrides by device, object and priority
+    overrides: TokioMutex<HashMap<(u32, String, u8), PriorityOverride>>,
+    /// Last report of the MQTT bridge; `None` while MQTT is disabled
+    mqtt_queue: TokioMutex<Option<MqttQueueStatus>>,
 }
 
-pub async fn launch(cmd_tx: mpsc::Sender<Command>, event_tx: broadcast::Sender<Event>) {
+pub async fn launch(cmd_tx: mpsc::Sender<Command>, event_tx: broadcast::Sender<Event>, db: Database, read_only: Arc<AtomicBool>) {
+    let persisted_devices = match db.load_devices().await {
+        Ok(devices) => devices.into_iter().map(|d| (d.instance, d)).collect(),
+        Err(e) => {
+            log::error!("Failed to load persisted devices: {}", e);
+            HashMap::new()
+        }
+    };
     let state = Arc::new(AppState { 
         cmd_tx, 
         event_tx: event_tx.clone(),
-        discovered_devices: TokioMutex::new(HashMap::new()),
+        db,
+        read_only,
+        discovered_devices: TokioMutex::new(persisted_devices),
         device_objects: TokioMutex::new(HashMap::new()),
+        routers: TokioMutex::new(HashMap::new()),
+        device_status: TokioMutex::new(HashMap::new()),
+        conflicts: TokioMutex::new(HashMap::new()),
+        overrides: TokioMutex::new(HashMap::new()),
+        mqtt_queue: TokioMutex::new(None),
     });
 
     // Spawn a task to update discovered devices from events
     let mut event_rx = event_tx.subscribe();
     let state_clone = Arc::clone(&state);
     tokio::spawn(async move {
-        while let Ok(event) = event_rx.recv().await {
+        // Devices that announced themselves since startup (persisted ones may be stale)
+        let mut seen = HashSet::new();
+        loop {
+            let event = match event_rx.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    log::warn!("Web UI state missed {} events", missed);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
             match event {
                 Event::DeviceDiscovered(dev) => {
                     let mut devices = state_clone.discovered_devices.lock().await;
-                    let is_new = !devices.contains_key(&dev.instance);
-                    devices.insert(dev.instance, dev.clone());
+                    let is_new = seen.insert(dev.instance);
+                    // Address changes arrive as DeviceUpdated once the core has ruled out a
+                    // duplicate instance, so a raw I-Am never overwrites a known device
+                    devices.entry(dev.instance).or_insert_with(|| dev.clone());
                     
                     if is_new {
                         // Check if we already have objects (to avoid re-scanning)
@@ -44,6 +89,8 @@ pub async fn launch(cmd_tx: mpsc::Sender<Command>, event_tx: broadcast::Sender<E
                                 interface: "".to_string(),
                                 device_id: dev.instance,
                                 address: dev.address.clone(),
+                                network: dev.network,
+                                mac: dev.mac.clone(),
                             }).await;
                         }
                     }
@@ -52,6 +99,39 @@ pub async fn launch(cmd_tx: mpsc::Sender<Command>, event_tx: broadcast::Sender<E
                     let mut all_objects = state_clone.device_objects.lock().await;
                     all_objects.insert(device_id, objects);
                 }
+                Event::DeviceUpdated(dev) => {
+                    let mut devices = state_clone.discovered_devices.lock().await;
+                    devices.insert(dev.instance, dev);
+                }
+                Event::DeviceRemoved { device_id } => {
+                    state_clone.discovered_devices.lock().await.remove(&device_id);
+                    state_clone.device_objects.lock().await.remove(&device_id);
+                    state_clone.device_status.lock().await.remove(&device_id);
+                    state_clone.overrides.lock().await.retain(|(id, _, _), _| *id != device_id);
+                    seen.remove(&device_id);
+                }
+                Event::DeviceStatusChanged(status) => {
+                    state_clone.device_status.lock().await.insert(status.device_id, status);
+                }
+                Event::DeviceConflict(conflict) => {
+                    let mut conflicts = state_clone.conflicts.lock().await;
+                    conflicts.insert((conflict.kind, conflict.key.clone()), conflict);
+                }
+                Event::OverrideDetected(found) => {
+                    let mut overrides = state_clone.overrides.lock().await;
+                    overrides.insert((found.device_id, found.object_id.clone(), found.priority), found);
+                }
+                Event::OverrideCleared(cleared) => {
+                    let mut overrides = state_clone.overrides.lock().await;
+                    overrides.remove(&(cleared.device_id, cleared.object_id, cleared.priority));
+                }
+                Event::RouterDiscovered(router) => {
+                    let mut routers = state_clone.routers.lock().await;
+                    routers.insert(router.address.clone(), router);
+                }
+                Event::MqttQueue(status) => {
+                    *state_clone.mqtt_queue.lock().await = Some(status);
+                }
                 _ => {}
             }
         }
@@ -62,10 +142,32 @@ pub async fn launch(cmd_tx: mpsc::Sender<Command>, event_tx: broadcast::Sender<E
         .route("/api/interfaces", get(list_interfaces))
         .route("/api/bind", post(bind_interface))
         .route("/api/discover", post(start_discovery))
+        .route("/api/discover/range", post(discover_range))
+        .route("/api/discover/sweep", post(start_sweep))
+        .route("/api/discover/stop", post(stop_discovery))
         .route("/api/ping", post(ping_handler))
+        .route("/api/who-has", post(who_has_handler))
         .route("/api/write", post(write_handler))
+        .route("/api/read-only", get(get_read_only).put(set_read_only))
         .route("/api/devices", get(get_devices))
         .route("/api/devices/:id/objects", get(get_device_objects))
+        .route("/api/devices/:id/objects/:object_type/:instance/properties/:property", get(read_property_handler))
+        .route("/api/devices/:id/objects/:object_type/:instance/priority-array", get(priority_array_handler))
+        .route("/api/devices/status", get(get_device_status))
+        .route("/api/devices/conflicts", get(get_device_conflicts))
+        .route("/api/devices/:id/capabilities", get(get_device_capabilities))
+        .route("/api/points", get(get_points).post(save_point))
+        .route("/api/points/:id", delete(delete_point))
+        .route("/api/points/:id/history", get(get_history))
+        .route("/api/points/:id/history/export", get(export_history))
+        .route("/api/overrides", get(get_overrides))
+        .route("/api/timed-overrides", get(get_timed_overrides))
+        .route("/api/timed-overrides/:id", delete(cancel_timed_override))
+        .route("/api/mqtt/queue", get(get_mqtt_queue))
+        .route("/api/network", get(get_network))
+        .route("/api/network/discover", post(discover_network))
+        .route("/api/audit", get(get_audit))
+        .route("/api/audit/export", get(export_audit))
         .route("/api/events", get(events_handler))
         .with_state(state);
 
@@ -79,7 +181,7 @@ pub async fn launch(cmd_tx: mpsc::Sender<Command>, event_tx: broadcast::Sender<E
             return;
         }
     };
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
 
 async fn index_handler() -> impl IntoResponse {
@@ -88,10 +190,7 @@ async fn index_handler() -> impl IntoResponse {
 
 async fn list_interfaces() -> impl IntoResponse {
     match interface::list_interfaces() {
-        Ok(ifaces) => {
-            let names: Vec<String> = ifaces.into_iter().map(|i| i.name).collect();
-            Json(names)
-        }
+        Ok(ifaces) => Json(ifaces),
         Err(_) => Json(vec![]),
     }
 }
@@ -116,6 +215,52 @@ async fn start_discovery(
     Json("Discovery started")
 }
 
+#[derive(serde::Deserialize)]
+struct RangeRequest {
+    low: u32,
+    high: u32,
+}
+
+async fn discover_range(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RangeRequest>,
+) -> impl IntoResponse {
+    let _ = state.cmd_tx.send(Command::DiscoverRange { low: payload.low, high: payload.high }).await;
+    Json("Ranged discovery started")
+}
+
+#[derive(serde::Deserialize)]
+struct SweepRequest {
+    #[serde(default)]
+    low: Option<u32>,
+    #[serde(default)]
+    high: Option<u32>,
+    #[serde(default)]
+    chunk_size: Option<u32>,
+    #[serde(default)]
+    chunk_delay_ms: Option<u64>,
+}
+
+async fn start_sweep(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SweepRequest>,
+) -> impl IntoResponse {
+    let _ = state.cmd_tx.send(Command::SweepDiscovery {
+        low: payload.low,
+        high: payload.high,
+        chunk_size: payload.chunk_size,
+        chunk_delay_ms: payload.chunk_delay_ms,
+    }).await;
+    Json("Sweep started")
+}
+
+async fn stop_discovery(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let _ = state.cmd_tx.send(Command::StopDiscovery).await;
+    Json("Sweep stop requested")
+}
+
 #[derive(serde::Deserialize)]
 struct PingRequest {
     target_ip: String,
@@ -132,29 +277,256 @@ async fn ping_handler(
     Json("Ping sent")
 }
 
+#[derive(serde::Deserialize)]
+struct WhoHasRequest {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    object_type: Option<u16>,
+    #[serde(default)]
+    instance: Option<u32>,
+    #[serde(default)]
+    low: Option<u32>,
+    #[serde(default)]
+    high: Option<u32>,
+    /// How long to collect I-Have replies
+    #[serde(default = "default_who_has_wait")]
+    wait_ms: u64,
+}
+
+fn default_who_has_wait() -> u64 {
+    3000
+}
+
+/// Sends Who-Has and returns the I-Have replies collected within `wait_ms`, grouped by device.
+async fn who_has_handler(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<WhoHasRequest>,
+) -> impl IntoResponse {
+    let search = match (payload.name, payload.object_type, payload.instance) {
+        (Some(name), _, _) => ObjectSearch::Name(name),
+        (None, Some(object_type), Some(instance)) => ObjectSearch::Id { object_type, instance },
+        _ => return (StatusCode::BAD_REQUEST, "Give a name or an object_type and instance").into_response(),
+    };
+    let in_range = |device_id: u32| match (payload.low, payload.high) {
+        (Some(low), Some(high)) => (low..=high).contains(&device_id),
+        _ => true,
+    };
+
+    let mut event_rx = state.event_tx.subscribe();
+    let _ = state.cmd_tx.send(Command::WhoHas { search: search.clone(), low: payload.low, high: payload.high }).await;
+    let mut i_haves = Vec::new();
+    let deadline = tokio::time::sleep(std::time::Duration::from_millis(payload.wait_ms.min(30_000)));
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            event = event_rx.recv() => match event {
+                Ok(Event::IHave(i_have)) if search.matches(&i_have) && in_range(i_have.device_id) => i_haves.push(i_have),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut deadline => break,
+        }
+    }
+    Json(aggregate_i_haves(&i_haves)).into_response()
+}
+
 #[derive(serde::Deserialize)]
 struct WriteRequest {
     device_id: u32,
     address: String,
+    #[serde(default)]
+    network: Option<u16>,
+    #[serde(default)]
+    mac: Option<String>,
     object_type: u16,
     instance: u32,
     property: u32,
     value: String,
+    #[serde(default)]
+    priority: Option<u8>,
+    #[serde(default)]
+    duration_secs: Option<u64>,
+    #[serde(default)]
+    verify: bool,
+    #[serde(default)]
+    tolerance: Option<f32>,
+}
+
+/// Origin of a REST request: the `X-Client-Id` header, or the peer address
+/// when it is missing.
+fn rest_origin(headers: &HeaderMap, peer: SocketAddr) -> Origin {
+    let client_id = headers
+        .get("x-client-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| peer.ip().to_string());
+    Origin::new(AuditSource::Rest, Some(client_id))
+}
+
+/// Records a configuration change made over REST.
+async fn audit_config(state: &AppState, origin: &Origin, target: String, value: Option<String>, result: Result<(), String>) {
+    let (result, detail) = match result {
+        Ok(()) => (AuditResult::Ok, None),
+        Err(e) => (AuditResult::Failed, Some(e)),
+    };
+    audit::record(&state.db, AuditEntry { target: Some(target), value, detail, ..audit::entry(AuditAction::Config, origin, result) }).await;
 }
 
 async fn write_handler(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<WriteRequest>,
 ) -> impl IntoResponse {
+    let object_id = object_id_string(payload.object_type, payload.instance);
+    let (device_id, property) = (payload.device_id, payload.property);
+    let mut event_rx = state.event_tx.subscribe();
     let _ = state.cmd_tx.send(Command::WriteProperty {
         device_id: payload.device_id,
         address: payload.address,
+        network: payload.network,
+        mac: payload.mac,
         object_type: payload.object_type,
         instance: payload.instance,
         property: payload.property,
         value: payload.value,
+        priority: payload.priority,
+        duration_secs: payload.duration_secs,
+        verify: payload.verify,
+        tolerance: payload.tolerance,
+        origin: rest_origin(&headers, peer),
     }).await;
-    Json("Write requested")
+    // A verified write is followed by a readback request
+    let deadline = tokio::time::sleep(if payload.verify { REQUEST_TIMEOUT * 2 } else { REQUEST_TIMEOUT });
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            event = event_rx.recv() => match event {
+                Ok(Event::WriteCompleted(outcome)) if outcome.device_id == device_id && outcome.object_id == object_id && outcome.property == property => {
+                    let status = match (&outcome.error, outcome.rejected) {
+                        (None, _) => StatusCode::OK,
+                        (Some(_), true) => StatusCode::FORBIDDEN,
+                        (Some(_), false) => StatusCode::BAD_GATEWAY,
+                    };
+                    return (status, Json(outcome)).into_response();
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut deadline => break,
+        }
+    }
+    (StatusCode::GATEWAY_TIMEOUT, "Write outcome not received").into_response()
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ReadOnlySetting {
+    read_only: bool,
+}
+
+async fn get_read_only(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    Json(ReadOnlySetting { read_only: state.read_only.load(Ordering::SeqCst) })
+}
+
+/// Switches the gateway-wide read-only mode until the next restart; the
+/// `writes.read_only` config setting applies at startup.
+async fn set_read_only(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(setting): Json<ReadOnlySetting>,
+) -> impl IntoResponse {
+    state.read_only.store(setting.read_only, Ordering::SeqCst);
+    log::warn!("Read-only mode {}", if setting.read_only { "enabled" } else { "disabled" });
+    audit_config(&state, &rest_origin(&headers, peer), "read_only".to_string(), Some(setting.read_only.to_string()), Ok(())).await;
+    Json(setting)
+}
+
+#[derive(serde::Deserialize)]
+struct ReadQuery {
+    #[serde(default)]
+    array_index: Option<u32>,
+}
+
+/// Reads any property of an object on a known device and returns its
+/// structured value.
+async fn read_property_handler(
+    axum::extract::Path((device_id, object_type, instance, property)): axum::extract::Path<(u32, u16, u32, u32)>,
+    axum::extract::Query(query): axum::extract::Query<ReadQuery>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let Some(device) = state.discovered_devices.lock().await.get(&device_id).cloned() else {
+        return (StatusCode::NOT_FOUND, "Unknown device").into_response();
+    };
+    let object_id = object_id_string(object_type, instance);
+    let array_index = query.array_index;
+    let mut event_rx = state.event_tx.subscribe();
+    let _ = state.cmd_tx.send(Command::ReadProperty {
+        device_id,
+        address: device.address,
+        network: device.network,
+        mac: device.mac,
+        object_type,
+        instance,
+        property,
+        array_index,
+    }).await;
+    let deadline = tokio::time::sleep(REQUEST_TIMEOUT);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            event = event_rx.recv() => match event {
+                Ok(Event::PropertyRead(reading)) if reading.device_id == device_id && reading.object_id == object_id && reading.property == property && reading.array_index == array_index => {
+                    let status = if reading.error.is_none() { StatusCode::OK } else { StatusCode::BAD_GATEWAY };
+                    return (status, Json(reading)).into_response();
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut deadline => break,
+        }
+    }
+    (StatusCode::GATEWAY_TIMEOUT, "Read result not received").into_response()
+}
+
+/// Reads the Priority_Array of a commandable object on a known device,
+/// marking the slots the gateway wrote.
+async fn priority_array_handler(
+    axum::extract::Path((device_id, object_type, instance)): axum::extract::Path<(u32, u16, u32)>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let Some(device) = state.discovered_devices.lock().await.get(&device_id).cloned() else {
+        return (StatusCode::NOT_FOUND, "Unknown device").into_response();
+    };
+    let object_id = object_id_string(object_type, instance);
+    let mut event_rx = state.event_tx.subscribe();
+    let _ = state.cmd_tx.send(Command::ReadPriorityArray {
+        device_id,
+        address: device.address,
+        network: device.network,
+        mac: device.mac,
+        object_type,
+        instance,
+    }).await;
+    let deadline = tokio::time::sleep(REQUEST_TIMEOUT);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            event = event_rx.recv() => match event {
+                Ok(Event::PriorityArrayRead(reading)) if reading.device_id == device_id && reading.object_id == object_id => {
+                    let status = if reading.error.is_none() { StatusCode::OK } else { StatusCode::BAD_GATEWAY };
+                    return (status, Json(reading)).into_response();
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut deadline => break,
+        }
+    }
+    (StatusCode::GATEWAY_TIMEOUT, "Priority array not received").into_response()
 }
 
 async fn get_devices(
@@ -179,6 +551,8 @@ async fn get_device_objects(
                     interface: "".to_string(), // Core will use current bound interface if empty
                     device_id: id,
                     address: dev.address.clone(),
+                    network: dev.network,
+                    mac: dev.mac.clone(),
                 }).await;
             }
             Json(vec![])
@@ -186,6 +560,346 @@ async fn get_device_objects(
     }
 }
 
+async fn get_device_capabilities(
+    axum::extract::Path(id): axum::extract::Path<u32>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let devices = state.discovered_devices.lock().await;
+    match devices.get(&id) {
+        Some(dev) => Json(dev.capabilities()).into_response(),
+        None => (StatusCode::NOT_FOUND, "Unknown device").into_response(),
+    }
+}
+
+async fn get_device_status(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let status = state.device_status.lock().await;
+    let mut status: Vec<DeviceStatus> = status.values().cloned().collect();
+    status.sort_by_key(|s| s.device_id);
+    Json(status)
+}
+
+async fn get_device_conflicts(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let conflicts = state.conflicts.lock().await;
+    let mut conflicts: Vec<DeviceConflict> = conflicts.values().cloned().collect();
+    conflicts.sort_by_key(|c| c.first_seen_ms);
+    Json(conflicts)
+}
+
+/// Broker connection and depth of the MQTT store-and-forward queue.
+async fn get_mqtt_queue(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    match state.mqtt_queue.lock().await.clone() {
+        Some(status) => Json(status).into_response(),
+        None => (StatusCode::NOT_FOUND, "MQTT is not enabled").into_response(),
+    }
+}
+
+async fn get_overrides(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let overrides = state.overrides.lock().await;
+    let mut overrides: Vec<PriorityOverride> = overrides.values().cloned().collect();
+    overrides.sort_by_key(|o| o.since_ms);
+    Json(overrides)
+}
+
+async fn get_timed_overrides(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    match state.db.load_timed_overrides().await {
+        Ok(pending) => Json(pending).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Relinquishes a timed override now and returns how it ended.
+async fn cancel_timed_override(
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    match state.db.load_timed_overrides().await {
+        Ok(pending) if pending.iter().any(|t| t.id == id) => {}
+        Ok(_) => return (StatusCode::NOT_FOUND, "Unknown timed override").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+    let mut event_rx = state.event_tx.subscribe();
+    let _ = state.cmd_tx.send(Command::CancelTimedOverride { id, origin: rest_origin(&headers, peer) }).await;
+    let deadline = tokio::time::sleep(REQUEST_TIMEOUT);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            event = event_rx.recv() => match event {
+                Ok(Event::TimedOverrideEnded(end)) if end.timed_override.id == id => {
+                    let status = if end.error.is_none() { StatusCode::OK } else { StatusCode::BAD_GATEWAY };
+                    return (status, Json(end)).into_response();
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut deadline => break,
+        }
+    }
+    (StatusCode::GATEWAY_TIMEOUT, "Relinquish outcome not received").into_response()
+}
+
+async fn get_points(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    match state.db.load_points().await {
+        Ok(points) => Json(points).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Adds a point to the point map, or updates the point for the same object.
+async fn save_point(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(mut point): Json<PointConfig>,
+) -> impl IntoResponse {
+    let origin = rest_origin(&headers, peer);
+    let value = serde_json::to_string(&point).ok();
+    match state.db.upsert_point(&point).await {
+        Ok(id) => {
+            point.id = id;
+            audit_config(&state, &origin, format!("point {}", id), value, Ok(())).await;
+            let _ = state.cmd_tx.send(Command::ReloadPoints).await;
+            Json(point).into_response()
+        }
+        Err(e) => {
+            audit_config(&state, &origin, format!("point {}", point.name), value, Err(e.to_string())).await;
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+async fn delete_point(
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let result = state.db.delete_point(id).await;
+    if !matches!(result, Ok(false)) {
+        let audited = result.as_ref().map(|_| ()).map_err(|e| e.to_string());
+        audit_config(&state, &rest_origin(&headers, peer), format!("point {}", id), None, audited).await;
+    }
+    match result {
+        Ok(true) => {
+            let _ = state.cmd_tx.send(Command::ReloadPoints).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Unknown point").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct HistoryQuery {
+    /// Time range in Unix milliseconds, inclusive; the last 24 hours by default
+    #[serde(default)]
+    from: Option<i64>,
+    #[serde(default)]
+    to: Option<i64>,
+    /// Aggregation interval in seconds
+    #[serde(default)]
+    interval: Option<u32>,
+    /// `raw` without an interval and `avg` with one by default
+    #[serde(default)]
+    agg: Option<HistoryAggregation>,
+}
+
+/// A point's history series, or the error response.
+async fn query_history(state: &AppState, id: i64, query: &HistoryQuery) -> Result<Vec<HistoryValue>, axum::response::Response> {
+    let internal_error = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    if !state.db.load_points().await.map_err(internal_error)?.iter().any(|p| p.id == id) {
+        return Err((StatusCode::NOT_FOUND, "Unknown point").into_response());
+    }
+    let to = query.to.unwrap_or_else(now_ms);
+    let from = query.from.unwrap_or(to - 24 * 3_600_000);
+    if from > to {
+        return Err((StatusCode::BAD_REQUEST, "from is after to").into_response());
+    }
+    let agg = query.agg.unwrap_or(if query.interval.is_some() { HistoryAggregation::Avg } else { HistoryAggregation::Raw });
+    let result = match (agg, query.interval) {
+        (HistoryAggregation::Raw, None) => state.db.query_history_raw(id, from, to).await,
+        (HistoryAggregation::Raw, Some(_)) => return Err((StatusCode::BAD_REQUEST, "Raw history has no interval").into_response()),
+        (_, None | Some(0)) => return Err((StatusCode::BAD_REQUEST, "Aggregates need an interval of at least 1 second").into_response()),
+        (agg, Some(interval)) => state.db.query_history(id, from, to, interval as i64 * 1000, agg).await,
+    };
+    result.map_err(internal_error)
+}
+
+/// A point's history, raw or aggregated per interval, oldest first.
+async fn get_history(
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    axum::extract::Query(query): axum::extract::Query<HistoryQuery>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    match query_history(&state, id, &query).await {
+        Ok(values) => Json(values).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// A point's history as CSV.
+async fn export_history(
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    axum::extract::Query(query): axum::extract::Query<HistoryQuery>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let values = match query_history(&state, id, &query).await {
+        Ok(values) => values,
+        Err(response) => return response,
+    };
+    let mut csv = String::from("timestamp_ms,value,quality,count\n");
+    for v in values {
+        let fields = [
+            v.timestamp_ms.to_string(),
+            v.value.to_string(),
+            v.quality.as_ref().map(enum_name).unwrap_or_default(),
+            v.count.map(|c| c.to_string()).unwrap_or_default(),
+        ];
+        csv.push_str(&csv_row(&fields));
+    }
+    let disposition = format!("attachment; filename=\"point-{}-history.csv\"", id);
+    ([(header::CONTENT_TYPE, "text/csv".to_string()), (header::CONTENT_DISPOSITION, disposition)], csv).into_response()
+}
+
+#[derive(serde::Deserialize)]
+struct Page {
+    #[serde(default = "default_page_limit")]
+    limit: u32,
+    #[serde(default)]
+    offset: u32,
+}
+
+fn default_page_limit() -> u32 {
+    100
+}
+
+#[derive(serde::Serialize)]
+struct AuditPage {
+    total: u64,
+    entries: Vec<AuditEntry>,
+}
+
+/// Audit log entries matching the filter, newest first.
+async fn get_audit(
+    axum::extract::Query(filter): axum::extract::Query<AuditFilter>,
+    axum::extract::Query(page): axum::extract::Query<Page>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    match state.db.query_audit(&filter, Some(page.limit), page.offset).await {
+        Ok((total, entries)) => Json(AuditPage { total, entries }).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// All audit log entries matching the filter as CSV, newest first.
+async fn export_audit(
+    axum::extract::Query(filter): axum::extract::Query<AuditFilter>,
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let entries = match state.db.query_audit(&filter, None, 0).await {
+        Ok((_, entries)) => entries,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let mut csv = String::from("id,timestamp_ms,action,source,client_id,device_id,object_id,property,target,value,priority,result,detail\n");
+    for e in entries {
+        let fields = [
+            e.id.to_string(),
+            e.timestamp_ms.to_string(),
+            enum_name(&e.action),
+            enum_name(&e.source),
+            e.client_id.unwrap_or_default(),
+            e.device_id.map(|v| v.to_string()).unwrap_or_default(),
+            e.object_id.unwrap_or_default(),
+            e.property.map(|v| v.to_string()).unwrap_or_default(),
+            e.target.unwrap_or_default(),
+            e.value.unwrap_or_default(),
+            e.priority.map(|v| v.to_string()).unwrap_or_default(),
+            enum_name(&e.result),
+            e.detail.unwrap_or_default(),
+        ];
+        csv.push_str(&csv_row(&fields));
+    }
+    let headers = [(header::CONTENT_TYPE, "text/csv"), (header::CONTENT_DISPOSITION, "attachment; filename=\"audit.csv\"")];
+    (headers, csv).into_response()
+}
+
+/// Serialized name of a unit enum variant.
+fn enum_name<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_value(value).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+/// A CSV line, quoting fields that contain separators, quotes or newlines.
+fn csv_row(fields: &[String]) -> String {
+    let quoted: Vec<String> = fields
+        .iter()
+        .map(|f| if f.contains([',', '"', '\n', '\r']) { format!("\"{}\"", f.replace('"', "\"\"")) } else { f.clone() })
+        .collect();
+    quoted.join(",") + "\n"
+}
+
+async fn get_network(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let routers = state.routers.lock().await;
+    let devices = state.discovered_devices.lock().await;
+    Json(build_topology(&routers, &devices))
+}
+
+async fn discover_network(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let _ = state.cmd_tx.send(Command::DiscoverRouters).await;
+    Json("Router discovery started")
+}
+
+/// Groups routers and devices by network number. The local BACnet/IP network
+/// (devices without SNET) is listed first.
+fn build_topology(routers: &HashMap<String, BacnetRouter>, devices: &HashMap<u32, BacnetDevice>) -> NetworkTopology {
+    let mut segments: BTreeMap<Option<u16>, NetworkSegment> = BTreeMap::new();
+    segments.insert(None, NetworkSegment { network: None, routers: vec![], devices: vec![] });
+
+    let mut router_list: Vec<BacnetRouter> = routers.values().cloned().collect();
+    router_list.sort_by(|a, b| a.address.cmp(&b.address));
+    for router in &router_list {
+        for network in &router.networks {
+            segments
+                .entry(Some(*network))
+                .or_insert_with(|| NetworkSegment { network: Some(*network), routers: vec![], devices: vec![] })
+                .routers
+                .push(router.address.clone());
+        }
+    }
+
+    let mut device_ids: Vec<&u32> = devices.keys().collect();
+    device_ids.sort();
+    for id in device_ids {
+        let network = devices[id].network;
+        segments
+            .entry(network)
+            .or_insert_with(|| NetworkSegment { network, routers: vec![], devices: vec![] })
+            .devices
+            .push(*id);
+    }
+
+    NetworkTopology {
+        routers: router_list,
+        networks: segments.into_values().collect(),
+    }
+}
+
 async fn events_handler(
     State(state): State<Arc<AppState>>,
 ) -> Sse<impl Stream<Item = Result<SseEvent, std::convert::Infallible>>> {
@@ -1,19 +1,21 @@
 use bacnet_rs::{
     app::Apdu,
-    datalink::{bip::BacnetIpDataLink, DataLink},
+    datalink::{bip::BacnetIpDataLink, DataLink, DataLinkAddress},
     object::{
-        analog::AnalogInput,
+        analog::{AnalogInput, AnalogValue},
         binary::BinaryInput,
         database::ObjectDatabase,
         Device, ObjectIdentifier, ObjectType, PropertyIdentifier, PropertyValue,
     },
-    service::{IAmRequest, UnconfirmedServiceChoice, WhoIsRequest, ReadPropertyRequest},
+    network::{NetworkAddress, Npdu},
+    service::{IAmRequest, UnconfirmedServiceChoice, WhoIsRequest},
     encoding::{
         self,
         encode_context_object_id, encode_context_enumerated,
     },
 };
 use rumqttc::{AsyncClient, MqttOptions, QoS, Event as MqttEvent, Packet};
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex, atomic::{AtomicU32, Ordering}};
 use tokio::time::{Duration, sleep};
@@ -22,8 +24,60 @@ use std::env;
 struct ResponderState {
     device_instance: u32,
     db: ObjectDatabase,
+    /// Properties the bacnet-rs objects do not model (description, units, ...)
+    extra: HashMap<(ObjectIdentifier, u32), PropertyValue>,
+    cov_subscriptions: Vec<CovSubscription>,
+    /// Objects of types bacnet-rs cannot represent: type, instance and name
+    opaque: Vec<(u16, u32, String)>,
+    /// Encoded values of constructed properties `PropertyValue` cannot hold
+    constructed: HashMap<(RawObjectId, u32), Vec<u8>>,
+    /// Priority_Array of commandable objects, kept here so writes and
+    /// relinquishes at any priority take effect
+    commands: HashMap<ObjectIdentifier, [Option<PropertyValue>; 16]>,
 }
 
+/// Object identifier as type number and instance, for types outside `ObjectType`.
+type RawObjectId = (u16, u32);
+
+/// An active SubscribeCOV (unconfirmed notifications only).
+struct CovSubscription {
+    subscriber: DataLinkAddress,
+    reply_to: Option<NetworkAddress>,
+    process_id: u32,
+    object_id: ObjectIdentifier,
+    expires: std::time::Instant,
+    last_value: Option<f32>,
+    last_status_flags: Vec<bool>,
+}
+
+const DESCRIPTION: u32 = 28;
+const INACTIVE_TEXT: u32 = 46;
+const PROTOCOL_SERVICES_SUPPORTED: u32 = 97;
+const RELIABILITY: u32 = 103;
+const RELINQUISH_DEFAULT: u32 = 104;
+const STATUS_FLAGS: u32 = 111;
+const UNITS: u32 = 117;
+const WEEKLY_SCHEDULE: u32 = 123;
+const DEGREES_CELSIUS: u32 = 62;
+const NO_FAULT_DETECTED: u32 = 0;
+const NO_SENSOR: u32 = 1;
+/// The fan status reports a sensor fault for this many of every `FAULT_CYCLE` simulation ticks
+const FAULT_TICKS: u32 = 5;
+const FAULT_CYCLE: u32 = 15;
+/// Object types bacnet-rs does not define: a newer standard one and a proprietary one
+const NETWORK_PORT: u16 = 56;
+const PROPRIETARY_DIAGNOSTICS: u16 = 130;
+const SCHEDULE: u16 = 17;
+/// Error class/code and reject reasons sent back to clients
+const ERROR_CLASS_PROPERTY: u32 = 2;
+const ERROR_CODE_UNKNOWN_PROPERTY: u32 = 32;
+const ERROR_CODE_WRITE_ACCESS_DENIED: u32 = 40;
+const REJECT_INVALID_PARAMETER_DATA_TYPE: u8 = 3;
+const REJECT_UNRECOGNIZED_SERVICE: u8 = 9;
+/// Unconfirmed service choices
+const I_HAVE: u8 = 1;
+const WHO_HAS: u8 = 7;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     if env::var("RUST_LOG").is_err() {
@@ -41,7 +95,11 @@ async fn main() -> anyhow::Result<()> {
         device_id, mqtt_host, iface_name, gateway_ip);
 
     // Initialize Database with Device object
-    let device = Device::new(device_id, format!("Ghost Device {}", device_id));
+    let mut device = Device::new(device_id, format!("Ghost Device {}", device_id));
+    device.vendor_name = "RustyGate".to_string();
+    device.model_name = "Ghost Device".to_string();
+    device.firmware_revision = env!("CARGO_PKG_VERSION").to_string();
+    device.application_software_version = format!("bacnet-responder {}", env!("CARGO_PKG_VERSION"));
     let db = ObjectDatabase::new(device);
 
     // Add some objects
@@ -57,12 +115,43 @@ async fn main() -> anyhow::Result<()> {
     av1.set_present_value(21.0);
     db.add_object(Box::new(av1)).unwrap();
 
+    // Commandable, for writes
+    let mut av_setpoint = AnalogValue::new(1, "Zone Setpoint".to_string());
+    av_setpoint.present_value = 21.0;
+    db.add_object(Box::new(av_setpoint)).unwrap();
+
     let bi1 = BinaryInput::new(1, "Fan Status".to_string());
     db.add_object(Box::new(bi1)).unwrap();
 
+    let mut extra = HashMap::new();
+    let mut services = vec![false; 40];
+    for bit in [5, 12, 14, 15, 26, 28, 33, 34] { // SubscribeCOV, ReadProperty, RPM, WriteProperty, I-Am, UnconfirmedCOVNotification, Who-Has, Who-Is
+        services[bit] = true;
+    }
+    extra.insert((ObjectIdentifier::new(ObjectType::Device, device_id), PROTOCOL_SERVICES_SUPPORTED), PropertyValue::BitString(services));
+    for (instance, description) in [(1, "Zone 1 space temperature"), (2, "Outside air temperature"), (10, "Zone 1 setpoint")] {
+        let id = ObjectIdentifier::new(ObjectType::AnalogInput, instance);
+        extra.insert((id, DESCRIPTION), PropertyValue::CharacterString(description.to_string()));
+        extra.insert((id, UNITS), PropertyValue::Enumerated(DEGREES_CELSIUS));
+    }
+    extra.insert((ObjectIdentifier::new(ObjectType::AnalogValue, 1), RELINQUISH_DEFAULT), PropertyValue::Real(21.0));
+    let fan = ObjectIdentifier::new(ObjectType::BinaryInput, 1);
+    extra.insert((fan, DESCRIPTION), PropertyValue::CharacterString("Supply fan run status".to_string()));
+    extra.insert((fan, PropertyIdentifier::ActiveText as u32), PropertyValue::CharacterString("Running".to_string()));
+    extra.insert((fan, INACTIVE_TEXT), PropertyValue::CharacterString("Stopped".to_string()));
+
     let state = Arc::new(Mutex::new(ResponderState {
         device_instance: device_id,
         db,
+        extra,
+        cov_subscriptions: Vec::new(),
+        opaque: vec![
+            (NETWORK_PORT, 1, "BACnet/IP Port".to_string()),
+            (PROPRIETARY_DIAGNOSTICS, 1, "Vendor Diagnostics".to_string()),
+            (SCHEDULE, 1, "Occupancy Schedule".to_string()),
+        ],
+        constructed: HashMap::from([(((SCHEDULE, 1), WEEKLY_SCHEDULE), encode_weekly_schedule()?)]),
+        commands: HashMap::new(),
     }));
 
     let error_count = Arc::new(AtomicU32::new(0));
@@ -72,19 +161,22 @@ async fn main() -> anyhow::Result<()> {
     tokio::spawn(async move {
         let mut temp = 22.5;
         let mut out_temp = 10.0;
+        let mut tick = 0u32;
         loop {
             sleep(Duration::from_secs(2)).await;
+            tick = tick.wrapping_add(1);
             temp += 0.1;
             if temp > 25.0 { temp = 20.0; }
             out_temp += 0.05;
             if out_temp > 15.0 { out_temp = 5.0; }
             
-            let s = state_clone.lock().unwrap();
-            let ai1_id = ObjectIdentifier::new(ObjectType::AnalogInput, 1);
-            let _ = s.db.set_property(ai1_id, PropertyIdentifier::PresentValue, PropertyValue::Real(temp));
-            
-            let ai2_id = ObjectIdentifier::new(ObjectType::AnalogInput, 2);
-            let _ = s.db.set_property(ai2_id, PropertyIdentifier::PresentValue, PropertyValue::Real(out_temp));
+            let mut s = state_clone.lock().unwrap();
+            set_analog_input(&s.db, 1, "Room Temperature", temp);
+            set_analog_input(&s.db, 2, "Outdoor Temperature", out_temp);
+            let fault = tick % FAULT_CYCLE < FAULT_TICKS;
+            let fan = ObjectIdentifier::new(ObjectType::BinaryInput, 1);
+            s.extra.insert((fan, STATUS_FLAGS), PropertyValue::BitString(vec![false, fault, false, false]));
+            s.extra.insert((fan, RELIABILITY), PropertyValue::Enumerated(if fault { NO_SENSOR } else { NO_FAULT_DETECTED }));
         }
     });
 
@@ -137,7 +229,19 @@ async fn main() -> anyhow::Result<()> {
             Ok((data, src_addr)) => {
                 log::info!("RESPONDER RECEIVED: {} bytes from {:?}", data.len(), src_addr);
                 error_count.store(0, Ordering::Relaxed);
-                match Apdu::decode(&data) {
+                // Strip the NPDU; replies to routed requesters go back via SNET/SADR
+                let (npdu, npdu_len) = match Npdu::decode(&data) {
+                    Ok(decoded) => decoded,
+                    Err(e) => {
+                        log::error!("Failed to decode NPDU: {}", e);
+                        continue;
+                    }
+                };
+                if npdu.is_network_message() {
+                    continue;
+                }
+                let reply_to = npdu.source.clone();
+                match Apdu::decode(&data[npdu_len..]) {
                     Ok(apdu) => {
                         match apdu {
                             Apdu::UnconfirmedRequest { service_choice, service_data } => {
@@ -165,7 +269,7 @@ async fn main() -> anyhow::Result<()> {
                                                 service_data,
                                             };
                                             
-                                            let encoded = apdu.encode();
+                                            let encoded = npdu_wrap(&apdu.encode(), None);
                                             
                                             // Send broadcast
                                             if let Err(e) = datalink.send_frame(&encoded, &bacnet_rs::datalink::DataLinkAddress::Broadcast) {
@@ -178,29 +282,40 @@ async fn main() -> anyhow::Result<()> {
                                             let _ = datalink.send_frame(&encoded, &src_addr);
                                         }
                                     }
+                                } else if service_choice == WHO_HAS {
+                                    let s = state.lock().unwrap();
+                                    match find_who_has_object(&s, &service_data) {
+                                        Ok(Some((obj_id, name))) => {
+                                            log::info!("Received Who-Has from {:?}, have {:?} '{}'", src_addr, obj_id, name);
+                                            let apdu = Apdu::UnconfirmedRequest {
+                                                service_choice: I_HAVE,
+                                                service_data: encode_i_have(s.device_instance, obj_id, &name)?,
+                                            };
+                                            let encoded = npdu_wrap(&apdu.encode(), None);
+                                            if let Err(e) = datalink.send_frame(&encoded, &bacnet_rs::datalink::DataLinkAddress::Broadcast) {
+                                                log::error!("Failed to send I-Have broadcast: {}", e);
+                                            }
+                                            let _ = datalink.send_frame(&encoded, &src_addr);
+                                        }
+                                        Ok(None) => {}
+                                        Err(e) => log::error!("Failed to decode Who-Has: {}", e),
+                                    }
                                 }
                             }
                             Apdu::ConfirmedRequest { invoke_id, service_choice, service_data, .. } => {
                                 if service_choice == 12 { // ReadProperty
                                     let s = state.lock().unwrap();
                                     match decode_read_property_request(&service_data) {
-                                        Ok(req) => {
-                                            log::info!("Received ReadProperty: {:?} for property {}", req.object_identifier, req.property_identifier);
-                                            
-                                            let result = if req.property_identifier == PropertyIdentifier::ObjectList as u32 {
-                                                let list = s.db.get_all_objects();
-                                                log::info!("Responding with ObjectList ({} objects)", list.len());
-                                                let val = PropertyValue::Array(list.into_iter().map(PropertyValue::ObjectIdentifier).collect());
-                                                Some(val)
-                                            } else {
-                                                s.db.get_property(req.object_identifier, unsafe { std::mem::transmute(req.property_identifier) }).ok()
-                                            };
+                                        Ok((obj_id, prop_id, array_index)) => {
+                                            log::info!("Received ReadProperty: {:?} for property {} index {:?}", obj_id, prop_id, array_index);
 
-                                            if let Some(val) = result {
-                                                let mut response_data = Vec::new();
-                                                if let Err(e) = encode_read_property_response(&mut response_data, req.object_identifier, req.property_identifier, val) {
-                                                    log::error!("Failed to encode ReadProperty response: {}", e);
-                                                } else {
+                                            let mut value = Vec::new();
+                                            let found = match array_index {
+                                                Some(index) => encode_array_element(&s, obj_id, prop_id, index, &mut value),
+                                                None => encode_value(&s, obj_id, prop_id, &mut value),
+                                            };
+                                            match found {
+                                                Ok(true) => {
                                                     let ack = Apdu::ComplexAck {
                                                         segmented: false,
                                                         more_follows: false,
@@ -208,56 +323,117 @@ async fn main() -> anyhow::Result<()> {
                                                         sequence_number: None,
                                                         proposed_window_size: None,
                                                         service_choice,
-                                                        service_data: response_data,
+                                                        service_data: encode_read_property_response(obj_id, prop_id, array_index, &value)?,
                                                     };
-                                                    if let Err(e) = datalink.send_frame(&ack.encode(), &src_addr) {
+                                                    if let Err(e) = datalink.send_frame(&npdu_wrap(&ack.encode(), reply_to.as_ref()), &src_addr) {
                                                         log::error!("Failed to send ComplexAck: {}", e);
                                                     } else {
                                                         log::info!("Sent ComplexAck to {:?}", src_addr);
                                                     }
                                                 }
-                                            } else {
-                                                log::warn!("Property {} not found on object {:?}", req.property_identifier, req.object_identifier);
-                                                let err = Apdu::Error {
-                                                    invoke_id,
-                                                    service_choice,
-                                                    error_class: 1, // Object
-                                                    error_code: 31, // Unknown property
-                                                };
-                                                let _ = datalink.send_frame(&err.encode(), &src_addr);
+                                                Ok(false) => {
+                                                    log::warn!("Property {} not found on object {:?}", prop_id, obj_id);
+                                                    let err = encode_error(invoke_id, service_choice, ERROR_CLASS_PROPERTY, ERROR_CODE_UNKNOWN_PROPERTY)?;
+                                                    let _ = datalink.send_frame(&npdu_wrap(&err, reply_to.as_ref()), &src_addr);
+                                                }
+                                                Err(e) => log::error!("Failed to encode ReadProperty response: {}", e),
                                             }
                                         }
                                         Err(e) => {
                                             log::error!("Failed to decode ReadProperty request: {}", e);
                                         }
                                     }
+                                } else if service_choice == 5 { // SubscribeCOV
+                                    let mut s = state.lock().unwrap();
+                                    match decode_subscribe_cov_request(&service_data) {
+                                        Ok((process_id, object_id, lifetime)) => {
+                                            log::info!("Received SubscribeCOV for {:?} (process {}, lifetime {:?})", object_id, process_id, lifetime);
+                                            s.cov_subscriptions.retain(|sub| !(sub.subscriber == src_addr && sub.process_id == process_id && sub.object_id == object_id));
+                                            if let Some(lifetime) = lifetime {
+                                                s.cov_subscriptions.push(CovSubscription {
+                                                    subscriber: src_addr.clone(),
+                                                    reply_to: reply_to.clone(),
+                                                    process_id,
+                                                    object_id,
+                                                    expires: std::time::Instant::now() + Duration::from_secs(lifetime as u64),
+                                                    last_value: None,
+                                                    last_status_flags: Vec::new(),
+                                                });
+                                            }
+                                            let ack = Apdu::SimpleAck { invoke_id, service_choice };
+                                            let _ = datalink.send_frame(&npdu_wrap(&ack.encode(), reply_to.as_ref()), &src_addr);
+                                        }
+                                        Err(e) => {
+                                            log::error!("Failed to decode SubscribeCOV request: {}", e);
+                                        }
+                                    }
+                                } else if service_choice == 14 { // ReadPropertyMultiple
+                                    let s = state.lock().unwrap();
+                                    match decode_read_property_multiple_request(&service_data) {
+                                        Ok(specs) => {
+                                            log::info!("Received ReadPropertyMultiple for {} objects", specs.len());
+                                            match encode_read_property_multiple_response(&s, &specs) {
+                                                Ok(response_data) => {
+                                                    let ack = Apdu::ComplexAck {
+                                                        segmented: false,
+                                                        more_follows: false,
+                                                        invoke_id,
+                                                        sequence_number: None,
+                                                        proposed_window_size: None,
+                                                        service_choice,
+                                                        service_data: response_data,
+                                                    };
+                                                    let _ = datalink.send_frame(&npdu_wrap(&ack.encode(), reply_to.as_ref()), &src_addr);
+                                                }
+                                                Err(e) => log::error!("Failed to encode ReadPropertyMultiple response: {}", e),
+                                            }
+                                        }
+                                        Err(e) => {
+                                            log::error!("Failed to decode ReadPropertyMultiple request: {}", e);
+                                        }
+                                    }
                                 } else if service_choice == 15 { // WriteProperty
                                     let mut s = state.lock().unwrap();
-                                    match decode_write_property_request(&service_data) {
-                                        Ok((obj_id, prop_id, val)) => {
-                                            log::info!("Received WriteProperty: {:?} property {} = {:?}", obj_id, prop_id, val);
-                                            if let Err(e) = s.db.set_property(obj_id, unsafe { std::mem::transmute(prop_id) }, val) {
+                                    match decode_write(&s, &service_data) {
+                                        Ok(WriteValue::Constructed(key, value)) => {
+                                            log::info!("Received WriteProperty: {:?} property {} ({} bytes)", key.0, key.1, value.len());
+                                            s.constructed.insert(key, value);
+                                            let ack = Apdu::SimpleAck {
+                                                invoke_id,
+                                                service_choice,
+                                            };
+                                            let _ = datalink.send_frame(&npdu_wrap(&ack.encode(), reply_to.as_ref()), &src_addr);
+                                        }
+                                        Ok(WriteValue::Primitive(obj_id, prop_id, val, priority)) => {
+                                            log::info!("Received WriteProperty: {:?} property {} = {:?} at priority {:?}", obj_id, prop_id, val, priority);
+                                            let result = match property_from_u32(prop_id) {
+                                                Some(PropertyIdentifier::PresentValue) if is_commandable(&s, obj_id) => command(&mut s, obj_id, val, priority.unwrap_or(16)),
+                                                Some(prop) => s.db.set_property(obj_id, prop, val).map_err(|e| e.to_string()),
+                                                None => Err(format!("Unsupported property {}", prop_id)),
+                                            };
+                                            if let Err(e) = result {
                                                 log::error!("Failed to set property: {}", e);
-                                                let err = Apdu::Error {
-                                                    invoke_id,
-                                                    service_choice,
-                                                    error_class: 1, // Object
-                                                    error_code: 31, // Unknown property or failed write
-                                                };
-                                                let _ = datalink.send_frame(&err.encode(), &src_addr);
+                                                let err = encode_error(invoke_id, service_choice, ERROR_CLASS_PROPERTY, ERROR_CODE_WRITE_ACCESS_DENIED)?;
+                                                let _ = datalink.send_frame(&npdu_wrap(&err, reply_to.as_ref()), &src_addr);
                                             } else {
                                                 log::info!("Property set successfully. Sending SimpleAck");
                                                 let ack = Apdu::SimpleAck {
                                                     invoke_id,
                                                     service_choice,
                                                 };
-                                                let _ = datalink.send_frame(&ack.encode(), &src_addr);
+                                                let _ = datalink.send_frame(&npdu_wrap(&ack.encode(), reply_to.as_ref()), &src_addr);
                                             }
                                         }
                                         Err(e) => {
                                             log::error!("Failed to decode WriteProperty request: {}", e);
+                                            let reject = Apdu::Reject { invoke_id, reject_reason: REJECT_INVALID_PARAMETER_DATA_TYPE };
+                                            let _ = datalink.send_frame(&npdu_wrap(&reject.encode(), reply_to.as_ref()), &src_addr);
                                         }
                                     }
+                                } else {
+                                    log::warn!("Rejecting unsupported confirmed service {}", service_choice);
+                                    let reject = Apdu::Reject { invoke_id, reject_reason: REJECT_UNRECOGNIZED_SERVICE };
+                                    let _ = datalink.send_frame(&npdu_wrap(&reject.encode(), reply_to.as_ref()), &src_addr);
                                 }
                             }
                             _ => {}
@@ -279,28 +455,402 @@ async fn main() -> anyhow::Result<()> {
                 sleep(Duration::from_millis(100)).await;
             }
         }
+        send_cov_notifications(&mut state.lock().unwrap(), &mut datalink);
         tokio::task::yield_now().await;
     }
 }
 
-fn decode_read_property_request(data: &[u8]) -> anyhow::Result<ReadPropertyRequest> {
+/// Sends an UnconfirmedCOVNotification to every subscriber whose object's
+/// present value changed since the last notification (the first one is sent
+/// right after subscribing). Expired subscriptions are dropped.
+fn send_cov_notifications(s: &mut ResponderState, datalink: &mut BacnetIpDataLink) {
+    let now = std::time::Instant::now();
+    s.cov_subscriptions.retain(|sub| sub.expires > now);
+    let device_id = ObjectIdentifier::new(ObjectType::Device, s.device_instance);
+    let mut notifications = Vec::new();
+    let mut subscriptions = std::mem::take(&mut s.cov_subscriptions);
+    for sub in subscriptions.iter_mut() {
+        let value = match s.db.get_property(sub.object_id, PropertyIdentifier::PresentValue) {
+            Ok(PropertyValue::Real(v)) => v,
+            Ok(PropertyValue::Enumerated(v)) => v as f32,
+            _ => continue,
+        };
+        let status_flags = match read_value(s, sub.object_id, STATUS_FLAGS) {
+            Some(PropertyValue::BitString(bits)) => bits,
+            _ => vec![false; 4],
+        };
+        if sub.last_value == Some(value) && sub.last_status_flags == status_flags {
+            continue;
+        }
+        sub.last_value = Some(value);
+        sub.last_status_flags = status_flags.clone();
+        let time_remaining = sub.expires.saturating_duration_since(now).as_secs() as u32;
+        match encode_cov_notification(sub.process_id, device_id, sub.object_id, time_remaining, value, &status_flags) {
+            Ok(data) => notifications.push((data, sub.subscriber.clone(), sub.reply_to.clone())),
+            Err(e) => log::error!("Failed to encode COV notification: {}", e),
+        }
+    }
+    s.cov_subscriptions = subscriptions;
+    for (data, subscriber, reply_to) in notifications {
+        let apdu = Apdu::UnconfirmedRequest {
+            service_choice: 2, // UnconfirmedCOVNotification
+            service_data: data,
+        };
+        if let Err(e) = datalink.send_frame(&npdu_wrap(&apdu.encode(), reply_to.as_ref()), &subscriber) {
+            log::error!("Failed to send COV notification: {}", e);
+        }
+    }
+}
+
+fn decode_subscribe_cov_request(data: &[u8]) -> anyhow::Result<(u32, ObjectIdentifier, Option<u32>)> {
     let mut pos = 0;
-    let ((obj_type, instance), c1) = encoding::decode_context_object_id(&data[pos..], 0)
+    let (process_id, c) = encoding::decode_context_unsigned(data, 0)
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    pos += c;
+    let ((obj_type, instance), c) = encoding::decode_context_object_id(&data[pos..], 1)
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    pos += c;
+    let obj_type = ObjectType::try_from(obj_type).map_err(|_| anyhow::anyhow!("Unknown object type {}", obj_type))?;
+    // Without issueConfirmedNotifications/lifetime the request is a cancellation
+    let lifetime = if pos < data.len() {
+        let (_, c) = encoding::decode_context_unsigned(&data[pos..], 2)
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        pos += c;
+        let (lifetime, _) = encoding::decode_context_unsigned(&data[pos..], 3)
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        Some(lifetime)
+    } else {
+        None
+    };
+    Ok((process_id, ObjectIdentifier::new(obj_type, instance), lifetime))
+}
+
+fn encode_cov_notification(process_id: u32, device_id: ObjectIdentifier, object_id: ObjectIdentifier, time_remaining: u32, value: f32, status_flags: &[bool]) -> anyhow::Result<Vec<u8>> {
+    let err = |e: encoding::EncodingError| anyhow::anyhow!(e.to_string());
+    let mut buf = Vec::new();
+    buf.extend(encoding::encode_context_unsigned(process_id, 0).map_err(err)?);
+    buf.extend(encode_context_object_id(device_id.object_type as u16, device_id.instance, 1).map_err(err)?);
+    buf.extend(encode_context_object_id(object_id.object_type as u16, object_id.instance, 2).map_err(err)?);
+    buf.extend(encoding::encode_context_unsigned(time_remaining, 3).map_err(err)?);
+    buf.push(0x4E); // Opening Tag 4: list of values
+    buf.extend(encode_context_enumerated(PropertyIdentifier::PresentValue as u32, 0).map_err(err)?);
+    buf.push(0x2E); // Opening Tag 2
+    encoding::encode_real(&mut buf, value).map_err(err)?;
+    buf.push(0x2F); // Closing Tag 2
+    buf.extend(encode_context_enumerated(STATUS_FLAGS, 0).map_err(err)?);
+    buf.push(0x2E);
+    encoding::advanced::bitstring::encode_bit_string(&mut buf, status_flags).map_err(err)?;
+    buf.push(0x2F);
+    buf.push(0x4F); // Closing Tag 4
+    Ok(buf)
+}
+
+/// Decodes a Who-Has request and looks the object up by identifier or name.
+/// Returns `None` when this device is outside the limits or lacks the object.
+fn find_who_has_object(s: &ResponderState, data: &[u8]) -> anyhow::Result<Option<(RawObjectId, String)>> {
+    let err = |e: encoding::EncodingError| anyhow::anyhow!(e.to_string());
+    let mut pos = 0;
+    if let Ok((low, c1)) = encoding::decode_context_unsigned(data, 0) {
+        let (high, c2) = encoding::decode_context_unsigned(&data[c1..], 1).map_err(err)?;
+        if s.device_instance < low || s.device_instance > high {
+            return Ok(None);
+        }
+        pos = c1 + c2;
+    }
+    let (tag, len, header) = encoding::decode_context_tag(&data[pos..]).map_err(err)?;
+    let name_of = |id: RawObjectId| {
+        let mut value = Vec::new();
+        match encode_value(s, id, PropertyIdentifier::ObjectName as u32, &mut value) {
+            Ok(true) => match encoding::decode_character_string(&value) {
+                Ok((name, _)) => name,
+                Err(_) => String::new(),
+            },
+            _ => String::new(),
+        }
+    };
+    let all_objects = || s.db.get_all_objects().into_iter().map(|id| (id.object_type as u16, id.instance)).chain(s.opaque.iter().map(|(t, i, _)| (*t, *i)));
+    match tag {
+        2 => {
+            let (wanted, _) = encoding::decode_context_object_id(&data[pos..], 2).map_err(err)?;
+            Ok(all_objects().find(|id| *id == wanted).map(|id| (id, name_of(id))))
+        }
+        3 => {
+            let bytes = data.get(pos + header..pos + header + len).ok_or_else(|| anyhow::anyhow!("Truncated object name"))?;
+            // First byte is the character set
+            let name = String::from_utf8_lossy(bytes.get(1..).unwrap_or_default()).into_owned();
+            Ok(all_objects().find(|id| name_of(*id) == name).map(|id| (id, name)))
+        }
+        _ => anyhow::bail!("Unexpected Who-Has tag {}", tag),
+    }
+}
+
+/// Encodes an Error PDU. `Apdu::Error::encode` writes class and code as raw
+/// bytes; the standard encodes them as application-tagged enumerations.
+fn encode_error(invoke_id: u8, service_choice: u8, error_class: u32, error_code: u32) -> anyhow::Result<Vec<u8>> {
+    let err = |e: encoding::EncodingError| anyhow::anyhow!(e.to_string());
+    let mut buf = vec![0x50, invoke_id, service_choice];
+    encoding::encode_enumerated(&mut buf, error_class).map_err(err)?;
+    encoding::encode_enumerated(&mut buf, error_code).map_err(err)?;
+    Ok(buf)
+}
+
+fn encode_i_have(device_instance: u32, obj_id: RawObjectId, name: &str) -> anyhow::Result<Vec<u8>> {
+    let err = |e: encoding::EncodingError| anyhow::anyhow!(e.to_string());
+    let mut buf = Vec::new();
+    encoding::encode_object_identifier(&mut buf, ObjectType::Device as u16, device_instance).map_err(err)?;
+    encoding::encode_object_identifier(&mut buf, obj_id.0, obj_id.1).map_err(err)?;
+    encoding::encode_character_string(&mut buf, name).map_err(err)?;
+    Ok(buf)
+}
+
+/// Maps a wire property identifier onto the (partial) `PropertyIdentifier`
+/// enum. Identifiers the enum does not define are unsupported here.
+fn property_from_u32(id: u32) -> Option<PropertyIdentifier> {
+    use PropertyIdentifier::*;
+    let prop = match id {
+        12 => ApplicationSoftwareVersion,
+        44 => FirmwareRevision,
+        62 => MaxApduLengthAccepted,
+        70 => ModelName,
+        75 => ObjectIdentifier,
+        76 => ObjectList,
+        77 => ObjectName,
+        79 => ObjectType,
+        81 => OutOfService,
+        85 => PresentValue,
+        87 => PriorityArray,
+        98 => ProtocolVersion,
+        107 => SegmentationSupported,
+        112 => SystemStatus,
+        120 => VendorIdentifier,
+        121 => VendorName,
+        139 => ProtocolRevision,
+        155 => DatabaseRevision,
+        _ => return None,
+    };
+    Some(prop)
+}
+
+/// Analog Input present values are read-only through `set_property`, so the
+/// simulation swaps in a fresh object carrying the new value.
+fn set_analog_input(db: &ObjectDatabase, instance: u32, name: &str, value: f32) {
+    let _ = db.remove_object(ObjectIdentifier::new(ObjectType::AnalogInput, instance));
+    let mut ai = AnalogInput::new(instance, name.to_string());
+    ai.set_present_value(value);
+    if let Err(e) = db.add_object(Box::new(ai)) {
+        log::error!("Failed to update AI {}: {}", instance, e);
+    }
+}
+
+/// Looks up a property in the object database or the extra property table.
+/// Status_Flags and Reliability default to a fault-free object.
+fn read_value(s: &ResponderState, obj_id: ObjectIdentifier, prop_id: u32) -> Option<PropertyValue> {
+    if let Some(val) = s.extra.get(&(obj_id, prop_id)) {
+        return Some(val.clone());
+    }
+    if prop_id == PropertyIdentifier::PriorityArray as u32 && is_commandable(s, obj_id) {
+        let slots = s.commands.get(&obj_id).cloned().unwrap_or_default();
+        return Some(PropertyValue::Array(slots.into_iter().map(|v| v.unwrap_or(PropertyValue::Null)).collect()));
+    }
+    if prop_id == STATUS_FLAGS || prop_id == RELIABILITY {
+        s.db.get_property(obj_id, PropertyIdentifier::ObjectName).ok()?;
+        let out_of_service = matches!(s.db.get_property(obj_id, PropertyIdentifier::OutOfService), Ok(PropertyValue::Boolean(true)));
+        return Some(match prop_id {
+            STATUS_FLAGS => PropertyValue::BitString(vec![false, false, false, out_of_service]),
+            _ => PropertyValue::Enumerated(NO_FAULT_DETECTED),
+        });
+    }
+    property_from_u32(prop_id).and_then(|prop| s.db.get_property(obj_id, prop).ok())
+}
+
+fn known_id((obj_type, instance): RawObjectId) -> Option<ObjectIdentifier> {
+    ObjectType::try_from(obj_type).ok().map(|t| ObjectIdentifier::new(t, instance))
+}
+
+/// Appends the application-tagged value of a property to `buf`. Returns false
+/// if the object or property does not exist. Object_List and the opaque
+/// objects are encoded here since `PropertyValue` cannot hold their types.
+fn encode_value(s: &ResponderState, obj_id: RawObjectId, prop_id: u32, buf: &mut Vec<u8>) -> anyhow::Result<bool> {
+    let err = |e: encoding::EncodingError| anyhow::anyhow!(e.to_string());
+    if let Some(value) = s.constructed.get(&(obj_id, prop_id)) {
+        buf.extend_from_slice(value);
+        return Ok(true);
+    }
+    if obj_id == (ObjectType::Device as u16, s.device_instance) && prop_id == PropertyIdentifier::ObjectList as u32 {
+        let list = s.db.get_all_objects();
+        log::info!("Responding with ObjectList ({} objects)", list.len() + s.opaque.len());
+        for id in list {
+            encoding::encode_object_identifier(buf, id.object_type as u16, id.instance).map_err(err)?;
+        }
+        for (obj_type, instance, _) in &s.opaque {
+            encoding::encode_object_identifier(buf, *obj_type, *instance).map_err(err)?;
+        }
+        return Ok(true);
+    }
+    if let Some((_, _, name)) = s.opaque.iter().find(|(t, i, _)| (*t, *i) == obj_id) {
+        match property_from_u32(prop_id) {
+            Some(PropertyIdentifier::ObjectName) => encoding::encode_character_string(buf, name).map_err(err)?,
+            Some(PropertyIdentifier::ObjectIdentifier) => encoding::encode_object_identifier(buf, obj_id.0, obj_id.1).map_err(err)?,
+            Some(PropertyIdentifier::ObjectType) => encoding::encode_enumerated(buf, obj_id.0 as u32).map_err(err)?,
+            _ => return Ok(false),
+        }
+        return Ok(true);
+    }
+    match known_id(obj_id).and_then(|id| read_value(s, id, prop_id)) {
+        Some(val) => {
+            encode_property_value(buf, val)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+fn npdu_wrap(apdu: &[u8], dest: Option<&NetworkAddress>) -> Vec<u8> {
+    let mut npdu = Npdu::new();
+    if let Some(dest) = dest {
+        npdu.control.destination_present = true;
+        npdu.destination = Some(dest.clone());
+        npdu.hop_count = Some(255);
+    }
+    let mut frame = npdu.encode();
+    frame.extend_from_slice(apdu);
+    frame
+}
+
+fn decode_read_property_request(data: &[u8]) -> anyhow::Result<(RawObjectId, u32, Option<u32>)> {
+    let mut pos = 0;
+    let (obj_id, c1) = encoding::decode_context_object_id(&data[pos..], 0)
         .map_err(|e| anyhow::anyhow!(e.to_string()))?;
     pos += c1;
-    let (prop_id, _c2) = encoding::decode_context_enumerated(&data[pos..], 1)
+    let (prop_id, c2) = encoding::decode_context_enumerated(&data[pos..], 1)
         .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-    
-    Ok(ReadPropertyRequest::new(
-        ObjectIdentifier::new(ObjectType::try_from(obj_type).unwrap(), instance),
-        prop_id,
-    ))
+    pos += c2;
+    let array_index = match data.get(pos) {
+        Some(b) if b & 0xF8 == 0x28 => Some(
+            encoding::decode_context_unsigned(&data[pos..], 2)
+                .map_err(|e| anyhow::anyhow!(e.to_string()))?
+                .0,
+        ),
+        _ => None,
+    };
+    Ok((obj_id, prop_id, array_index))
 }
 
-fn decode_write_property_request(data: &[u8]) -> anyhow::Result<(ObjectIdentifier, u32, PropertyValue)> {
+/// Appends one element of an array property (its length for index 0).
+/// Returns false if the property is not an array or the index is out of range.
+fn encode_array_element(s: &ResponderState, obj_id: RawObjectId, prop_id: u32, index: u32, buf: &mut Vec<u8>) -> anyhow::Result<bool> {
+    let Some(PropertyValue::Array(elements)) = known_id(obj_id).and_then(|id| read_value(s, id, prop_id)) else {
+        return Ok(false);
+    };
+    match index {
+        0 => encoding::encode_unsigned(buf, elements.len() as u32).map_err(|e| anyhow::anyhow!(e.to_string()))?,
+        n => match elements.into_iter().nth(n as usize - 1) {
+            Some(element) => encode_property_value(buf, element)?,
+            None => return Ok(false),
+        },
+    }
+    Ok(true)
+}
+
+fn decode_read_property_multiple_request(data: &[u8]) -> anyhow::Result<Vec<(RawObjectId, Vec<u32>)>> {
+    let mut specs = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let (obj_id, c) = encoding::decode_context_object_id(&data[pos..], 0)
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        pos += c;
+        if data.get(pos) != Some(&0x1E) {
+            anyhow::bail!("Expected opening tag 1 at pos {}", pos);
+        }
+        pos += 1;
+        let mut props = Vec::new();
+        while data.get(pos) != Some(&0x1F) {
+            let (prop_id, c) = encoding::decode_context_enumerated(&data[pos..], 0)
+                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+            props.push(prop_id);
+            pos += c;
+        }
+        pos += 1;
+        specs.push((obj_id, props));
+    }
+    Ok(specs)
+}
+
+fn encode_read_property_multiple_response(s: &ResponderState, specs: &[(RawObjectId, Vec<u32>)]) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    for (obj_id, props) in specs {
+        buf.extend(encode_context_object_id(obj_id.0, obj_id.1, 0).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+        buf.push(0x1E); // Opening Tag 1
+        for prop_id in props {
+            buf.extend(encode_context_enumerated(*prop_id, 2).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+            let mut value = Vec::new();
+            match encode_value(s, *obj_id, *prop_id, &mut value)? {
+                true => {
+                    buf.push(0x4E); // Opening Tag 4
+                    buf.extend(value);
+                    buf.push(0x4F); // Closing Tag 4
+                }
+                false => {
+                    buf.push(0x5E); // Opening Tag 5
+                    encoding::encode_enumerated(&mut buf, 2).map_err(|e| anyhow::anyhow!(e.to_string()))?; // Property
+                    encoding::encode_enumerated(&mut buf, 32).map_err(|e| anyhow::anyhow!(e.to_string()))?; // Unknown property
+                    buf.push(0x5F); // Closing Tag 5
+                }
+            }
+        }
+        buf.push(0x1F); // Closing Tag 1
+    }
+    Ok(buf)
+}
+
+/// A decoded WriteProperty value: stored as encoded for constructed
+/// properties, otherwise set on the object.
+enum WriteValue {
+    Constructed((RawObjectId, u32), Vec<u8>),
+    Primitive(ObjectIdentifier, u32, PropertyValue, Option<u8>),
+}
+
+fn is_commandable(s: &ResponderState, obj_id: ObjectIdentifier) -> bool {
+    s.db.get_property(obj_id, PropertyIdentifier::PriorityArray).is_ok()
+}
+
+/// Writes (or with Null relinquishes) a Present_Value at `priority`; the
+/// highest priority value, else Relinquish_Default, becomes the present value.
+fn command(s: &mut ResponderState, obj_id: ObjectIdentifier, value: PropertyValue, priority: u8) -> Result<(), String> {
+    if !(1..=16).contains(&priority) {
+        return Err(format!("Invalid priority {}", priority));
+    }
+    let slots = s.commands.entry(obj_id).or_default();
+    slots[priority as usize - 1] = match value {
+        PropertyValue::Null => None,
+        value => Some(value),
+    };
+    let effective = slots.iter().flatten().next().cloned().or_else(|| s.extra.get(&(obj_id, RELINQUISH_DEFAULT)).cloned());
+    match effective {
+        Some(value) => s.db.set_property(obj_id, PropertyIdentifier::PresentValue, value).map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
+
+fn decode_write(s: &ResponderState, data: &[u8]) -> anyhow::Result<WriteValue> {
+    let (obj_id, prop_id, value, priority) = decode_write_property_request(data)?;
+    if s.constructed.contains_key(&(obj_id, prop_id)) {
+        return Ok(WriteValue::Constructed((obj_id, prop_id), value.to_vec()));
+    }
+    let (val, consumed) = decode_property_value(value)?;
+    if consumed != value.len() {
+        anyhow::bail!("Unexpected data after property value");
+    }
+    let id = known_id(obj_id).ok_or_else(|| anyhow::anyhow!("Unsupported object type {}", obj_id.0))?;
+    Ok(WriteValue::Primitive(id, prop_id, val, priority))
+}
+
+/// Splits a WriteProperty request into object, property, the encoded value
+/// and the priority.
+fn decode_write_property_request(data: &[u8]) -> anyhow::Result<(RawObjectId, u32, &[u8], Option<u8>)> {
     let mut pos = 0;
     // 1. Object ID (Context 0)
-    let ((obj_type, instance), c1) = encoding::decode_context_object_id(&data[pos..], 0)
+    let (obj_id, c1) = encoding::decode_context_object_id(&data[pos..], 0)
         .map_err(|e| anyhow::anyhow!("Failed to decode object id: {}", e))?;
     pos += c1;
     
@@ -322,19 +872,14 @@ fn decode_write_property_request(data: &[u8]) -> anyhow::Result<(ObjectIdentifie
     }
     pos += 1;
 
-    let (val, consumed) = decode_property_value(&data[pos..])?;
-    pos += consumed;
-
-    // Closing Tag 3
-    if pos >= data.len() || data[pos] != 0x3F {
-        anyhow::bail!("Expected closing tag for property value (0x3F) at pos {}, got {:?}", pos, data.get(pos));
-    }
-    
-    Ok((
-        ObjectIdentifier::new(ObjectType::try_from(obj_type).unwrap(), instance),
-        prop_id,
-        val
-    ))
+    // Closing Tag 3 ends the request unless a priority (Context 4) follows
+    let (end, priority) = match data {
+        [.., 0x3F, 0x49, priority] => (data.len() - 3, Some(*priority)),
+        [.., 0x3F] => (data.len() - 1, None),
+        _ => anyhow::bail!("Expected closing tag for property value (0x3F)"),
+    };
+    let value = data.get(pos..end).ok_or_else(|| anyhow::anyhow!("Truncated property value"))?;
+    Ok((obj_id, prop_id, value, priority))
 }
 
 fn decode_property_value(data: &[u8]) -> anyhow::Result<(PropertyValue, usize)> {
@@ -347,6 +892,7 @@ fn decode_property_value(data: &[u8]) -> anyhow::Result<(PropertyValue, usize)>
     if (tag & 0x08) == 0 { // Application Tag
         let app_tag = tag >> 4;
         match app_tag {
+            0 => Ok((PropertyValue::Null, 1)),
             1 => { // Boolean
                 Ok((PropertyValue::Boolean(tag == 0x11), 1))
             }
@@ -368,7 +914,8 @@ fn decode_property_value(data: &[u8]) -> anyhow::Result<(PropertyValue, usize)>
             12 => { // Object Identifier
                 let ((t, i), c) = encoding::decode_object_identifier(data)
                     .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-                Ok((PropertyValue::ObjectIdentifier(ObjectIdentifier::new(ObjectType::try_from(t).unwrap(), i)), c))
+                let id = known_id((t, i)).ok_or_else(|| anyhow::anyhow!("Unsupported object type {}", t))?;
+                Ok((PropertyValue::ObjectIdentifier(id), c))
             }
             _ => anyhow::bail!("Unsupported application tag: {}", app_tag),
         }
@@ -377,26 +924,50 @@ fn decode_property_value(data: &[u8]) -> anyhow::Result<(PropertyValue, usize)>
     }
 }
 
-fn encode_read_property_response(buf: &mut Vec<u8>, obj_id: ObjectIdentifier, prop_id: u32, val: PropertyValue) -> anyhow::Result<()> {
+fn encode_read_property_response(obj_id: RawObjectId, prop_id: u32, array_index: Option<u32>, value: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
     // 1. Object ID (Context 0)
-    buf.extend(encode_context_object_id(obj_id.object_type as u16, obj_id.instance, 0).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+    buf.extend(encode_context_object_id(obj_id.0, obj_id.1, 0).map_err(|e| anyhow::anyhow!(e.to_string()))?);
     // 2. Property ID (Context 1)
     buf.extend(encode_context_enumerated(prop_id, 1).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+    // Array Index (Context 2)
+    if let Some(index) = array_index {
+        buf.extend(encoding::encode_context_unsigned(index, 2).map_err(|e| anyhow::anyhow!(e.to_string()))?);
+    }
     // 3. Value (Context 3)
     buf.push(0x3E); // Opening Tag 3
-    encode_property_value(buf, val)?;
+    buf.extend_from_slice(value);
     buf.push(0x3F); // Closing Tag 3
-    Ok(())
+    Ok(buf)
+}
+
+/// Weekdays occupied 07:00-18:00, weekends unoccupied.
+fn encode_weekly_schedule() -> anyhow::Result<Vec<u8>> {
+    let err = |e: encoding::EncodingError| anyhow::anyhow!(e.to_string());
+    let mut buf = Vec::new();
+    for day in 0..7 {
+        buf.push(0x0E); // Opening Tag 0 (day-schedule)
+        if day < 5 {
+            for (hour, occupied) in [(7, 1), (18, 0)] {
+                encoding::encode_time(&mut buf, hour, 0, 0, 0).map_err(err)?;
+                encoding::encode_enumerated(&mut buf, occupied).map_err(err)?;
+            }
+        }
+        buf.push(0x0F); // Closing Tag 0
+    }
+    Ok(buf)
 }
 
 fn encode_property_value(buf: &mut Vec<u8>, val: PropertyValue) -> anyhow::Result<()> {
     match val {
+        PropertyValue::Null => buf.push(0x00),
         PropertyValue::Real(f) => encoding::encode_real(buf, f).map_err(|e| anyhow::anyhow!(e.to_string()))?,
         PropertyValue::Boolean(b) => encoding::encode_boolean(buf, b).map_err(|e| anyhow::anyhow!(e.to_string()))?,
         PropertyValue::UnsignedInteger(u) => encoding::encode_unsigned(buf, u).map_err(|e| anyhow::anyhow!(e.to_string()))?,
         PropertyValue::CharacterString(s) => encoding::encode_character_string(buf, &s).map_err(|e| anyhow::anyhow!(e.to_string()))?,
         PropertyValue::ObjectIdentifier(id) => encoding::encode_object_identifier(buf, id.object_type as u16, id.instance).map_err(|e| anyhow::anyhow!(e.to_string()))?,
         PropertyValue::Enumerated(e) => encoding::encode_enumerated(buf, e).map_err(|e| anyhow::anyhow!(e.to_string()))?,
+        PropertyValue::BitString(bits) => encoding::advanced::bitstring::encode_bit_string(buf, &bits).map_err(|e| anyhow::anyhow!(e.to_string()))?,
         PropertyValue::Array(arr) => {
             for v in arr {
                 encode_property_value(buf, v)?;